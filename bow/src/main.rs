@@ -1,49 +1,99 @@
 use std::collections::{HashMap, HashSet};
 
-fn build_vocab(docs: &[&str]) -> HashMap<String, usize> {
-    // Initialize an empty set for unique words
-    let mut unique_words = HashSet::new();
+/// A document-frequency threshold: an absolute number of documents or a
+/// fraction of the corpus.
+#[derive(Debug, Clone, Copy)]
+enum DocFreq {
+    Count(usize),
+    Fraction(f32),
+}
+
+impl DocFreq {
+    /// The threshold as a number of documents, for a corpus of `n_docs` documents.
+    fn as_count(self, n_docs: usize) -> f32 {
+        match self {
+            DocFreq::Count(count) => count as f32,
+            DocFreq::Fraction(fraction) => fraction * n_docs as f32,
+        }
+    }
+}
 
-    // Iterate through each document and its words
+/// Build the vocabulary, keeping only words whose document frequency lies in
+/// `[min_df, max_df]`. `DocFreq::Count(1)` and `DocFreq::Fraction(1.0)` keep
+/// every word. Returns the word-to-index map and the document frequency of
+/// each kept word.
+fn build_vocab(
+    docs: &[&str],
+    min_df: DocFreq,
+    max_df: DocFreq,
+) -> (HashMap<String, usize>, HashMap<String, usize>) {
+    // Count, for each word, the number of documents it appears in
+    let mut doc_freq: HashMap<String, usize> = HashMap::new();
     for doc in docs {
+        let mut doc_words = HashSet::new();
         for word in doc.to_lowercase().split_whitespace() {
             // Clean words by converting to lowercase and removing punctuation
             let clean_word = word.trim_matches(|c: char| ".,!?".contains(c));
             if !clean_word.is_empty() {
-                unique_words.insert(clean_word.to_string());
+                doc_words.insert(clean_word.to_string());
             }
         }
+        for word in doc_words {
+            *doc_freq.entry(word).or_insert(0) += 1;
+        }
     }
 
-    // Convert set to sorted vector and create word-to-index mapping
-    let mut words: Vec<String> = unique_words.into_iter().collect();
+    // Drop words that are too rare or too common
+    let (min_count, max_count) = (min_df.as_count(docs.len()), max_df.as_count(docs.len()));
+    doc_freq.retain(|_, &mut df| df as f32 >= min_count && df as f32 <= max_count);
+
+    // Convert to sorted vector and create word-to-index mapping
+    let mut words: Vec<String> = doc_freq.keys().cloned().collect();
     words.sort();
 
-    // Return a dictionary mapping words to indices
-    words
+    // Return a dictionary mapping words to indices, and the document frequencies
+    let vocab = words
         .into_iter()
         .enumerate()
         .map(|(idx, word)| (word, idx))
-        .collect()
+        .collect();
+    (vocab, doc_freq)
 }
 
-fn bow_vectorize(text: &str, vocab: &HashMap<String, usize>) -> Vec<usize> {
+/// Count the vocabulary words of the text.
+///
+/// Also returns the fraction of the text's words found in the vocabulary
+/// (0.0 for a text without words). At 0.0 the vector is all zeros, so it
+/// matches nothing and any ranking built on it is arbitrary.
+fn bow_vectorize(text: &str, vocab: &HashMap<String, usize>) -> (Vec<usize>, f32) {
     // Create a zero vector with length equal to vocabulary size
     let mut vector = vec![0; vocab.len()];
+    let mut words = 0;
+    let mut known = 0;
 
     // Process each word in the text
     for word in text.to_lowercase().split_whitespace() {
         // Clean the word by removing punctuation
         let clean_word = word.trim_matches(|c: char| ".,!?".contains(c));
+        if clean_word.is_empty() {
+            continue;
+        }
+        words += 1;
 
         // If word exists in vocabulary, increment its count in the vector
         if let Some(&index) = vocab.get(clean_word) {
             vector[index] += 1;
+            known += 1;
         }
     }
 
-    // Return the BOW vector
-    vector
+    // Return the BOW vector and the vocabulary coverage
+    let coverage = if words == 0 {
+        0.0
+    } else {
+        known as f32 / words as f32
+    };
+    (vector, coverage)
 }
 
 fn main() {
@@ -52,19 +102,32 @@ fn main() {
         "Data is crucial for retrieval processes, and without data, retrieval systems cannot function effectively.",
     ];
 
-    // Build vocabulary from example texts
-    let vocab = build_vocab(&example_texts);
+    // Build vocabulary from example texts, keeping every word
+    let (vocab, doc_freq) = build_vocab(&example_texts, DocFreq::Count(1), DocFreq::Fraction(1.0));
 
-    // Print the vocabulary to see word-to-index mapping
+    // Print the vocabulary to see word-to-index mapping and document frequency
     println!("Vocabulary:");
     for (word, idx) in &vocab {
-        println!("{}: {}", word, idx);
+        println!("{}: {} (df {})", word, idx, doc_freq[word]);
     }
     println!();
 
     // Convert each text to its BOW vector and print
     for text in &example_texts {
-        let vector = bow_vectorize(text, &vocab);
-        println!("Text: {}\nBOW Vector: {:?}\n", text, vector);
+        let (vector, coverage) = bow_vectorize(text, &vocab);
+        println!(
+            "Text: {}\nBOW Vector: {:?}\nCoverage: {:.0}%\n",
+            text,
+            vector,
+            coverage * 100.0
+        );
+    }
+
+    // A text sharing no word with the vocabulary gets an all-zero vector
+    let unrelated = "Bananas are rich in potassium.";
+    let (vector, coverage) = bow_vectorize(unrelated, &vocab);
+    println!("Text: {}\nBOW Vector: {:?}", unrelated, vector);
+    if coverage == 0.0 {
+        println!("Warning: none of its words are in the vocabulary, so BOW cannot compare it");
     }
 }
@@ -13,10 +13,16 @@ pub struct Chunk {
     pub keywords: HashSet<String>,
 }
 
+/// One entry of the JSON corpus, as deserialized from the file.
 #[derive(Debug, Deserialize)]
-struct Document {
-    content: String,
-    category: Option<String>,
+pub struct Document {
+    /// Explicit document id; without one, loaders number it after the largest
+    /// explicit id (see `assign_doc_ids`).
+    pub id: Option<usize>,
+    pub content: String,
+    pub category: Option<String>,
+    /// Publication date, expected as `YYYY-MM-DD`.
+    pub date: Option<String>,
 }
 
 /// Splits the given text into chunks of size 'chunk_size' words and tags matching keywords.
@@ -32,11 +38,10 @@ pub fn chunk_text(
     let keyword_set: HashSet<String> = keywords.iter().map(|&k| k.to_lowercase()).collect();
     let mut chunks = Vec::new();
 
-    // Iterate over the text in steps of `chunk_size`
-    for i in (0..words.len()).step_by(chunk_size) {
-        let end = (i + chunk_size).min(words.len());
+    // Iterate over the text in groups of `chunk_size` words (at least one)
+    for group in words.chunks(chunk_size.max(1)) {
         // Join chunk into a string
-        let chunk_text = words[i..end].join(" ");
+        let chunk_text = group.join(" ");
 
         // Scan for matching keywords
         let mut matched_keywords = HashSet::new();
@@ -53,19 +58,38 @@ pub fn chunk_text(
     chunks
 }
 
+/// The id of each document, in file order: its explicit `id`, or for a
+/// document without one the next number after the largest explicit id, so a
+/// fallback id never collides with an explicit one.
+fn assign_doc_ids(documents: &[Document]) -> Vec<usize> {
+    let mut next_id = documents
+        .iter()
+        .filter_map(|doc| doc.id)
+        .max()
+        .map_or(0, |max| max + 1);
+    documents
+        .iter()
+        .map(|doc| {
+            doc.id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        })
+        .collect()
+}
+
 /// Loads a dataset from JSON file_path, then splits each document into smaller chunks.
 pub fn load_and_chunk_dataset(
     file_path: &str,
     chunk_size: usize,
     keywords: &[&str],
 ) -> Result<Vec<Chunk>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let documents: Vec<Document> = serde_json::from_reader(reader)?;
+    let documents = read_documents(file_path)?;
 
     let mut all_chunks = Vec::new();
 
-    for (doc_id, doc) in documents.iter().enumerate() {
+    let doc_ids = assign_doc_ids(&documents);
+    for (doc, doc_id) in documents.iter().zip(doc_ids) {
         let doc_text = &doc.content;
         let doc_category = doc
             .category
@@ -89,3 +113,58 @@ pub fn load_and_chunk_dataset(
 
     Ok(all_chunks)
 }
+
+/// Deserializes every document in the JSON file.
+pub fn read_documents(file_path: &str) -> Result<Vec<Document>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Loads the raw content of every document in the JSON file, without chunking.
+pub fn load_documents(file_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let documents = read_documents(file_path)?;
+
+    Ok(documents.into_iter().map(|doc| doc.content).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        // Any string, including whitespace runs and multibyte text, chunks
+        // without panicking into the input's words, in order
+        #[test]
+        fn chunk_text_only_returns_input_words(
+            text in any::<String>(),
+            chunk_size in 0usize..8,
+        ) {
+            let chunks = chunk_text(&text, chunk_size, &["alpha"]);
+
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let chunked: Vec<&str> = chunks
+                .iter()
+                .flat_map(|(chunk, _)| chunk.split_whitespace())
+                .collect();
+            prop_assert_eq!(chunked, words);
+            for (chunk, _) in &chunks {
+                prop_assert!(chunk.split_whitespace().count() <= chunk_size.max(1));
+            }
+        }
+    }
+
+    #[test]
+    fn fallback_ids_follow_the_largest_explicit_id() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": 5, "content": "a"}, {"content": "b"}, {"id": 2, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&documents), [5, 6, 2, 7]);
+
+        let documents: Vec<Document> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&documents), [0, 1]);
+    }
+}
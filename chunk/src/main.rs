@@ -1,12 +1,29 @@
 mod data;
+mod report;
 
-use data::load_and_chunk_dataset;
+use data::{load_and_chunk_dataset, load_documents};
+use report::{chunk_size_report, corpus_stats};
 use std::env;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let current_dir = env::current_dir()?;
     let dataset_path = current_dir.join("data").join("corpus.json");
 
+    // Sanity-check the corpus before chunking it
+    let stats = corpus_stats(dataset_path.to_str().unwrap())?;
+    println!("documents      {:>8}", stats.num_documents);
+    println!("total words    {:>8}", stats.total_words);
+    println!("mean words     {:>8.1}", stats.mean_words);
+    match stats.date_range {
+        Some((first, last)) => println!("date range     {} to {}", first, last),
+        None => println!("date range     (no dates)"),
+    }
+    println!("\ncategory        documents");
+    for (category, count) in &stats.category_counts {
+        println!("{:<14}  {:>9}", category, count);
+    }
+    println!();
+
     // TODO: Define keywords to track
     let keywords: &[&str] = &["testing", "chunking"];
     // TODO: Call load_and_chunk_dataset with the keywords
@@ -19,5 +36,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
+    // Compare candidate chunk sizes on this corpus
+    let documents = load_documents(dataset_path.to_str().unwrap())?;
+    println!("\nchunk_size  chunks  mean_words  median_words  split_sentences");
+    for stats in chunk_size_report(&documents, &[10, 20, 30, 50, 100]) {
+        println!(
+            "{:>10}  {:>6}  {:>10.1}  {:>12.1}  {:>14.1}%",
+            stats.chunk_size,
+            stats.num_chunks,
+            stats.mean_words,
+            stats.median_words,
+            stats.split_sentence_fraction * 100.0
+        );
+    }
+
     Ok(())
 }
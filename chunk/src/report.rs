@@ -0,0 +1,146 @@
+use crate::data::read_documents;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Chunking statistics for one candidate chunk size.
+#[derive(Debug, Clone)]
+pub struct ChunkStats {
+    pub chunk_size: usize,
+    pub num_chunks: usize,
+    pub mean_words: f32,
+    pub median_words: f32,
+    /// Fraction of sentences whose words end up in more than one chunk.
+    pub split_sentence_fraction: f32,
+}
+
+/// Word ranges `[start, end)` of the sentences in `words`.
+///
+/// A sentence ends at a word ending in '.', '!' or '?'; trailing words without
+/// a terminator form the last sentence.
+fn sentence_spans(words: &[&str]) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (i, word) in words.iter().enumerate() {
+        if word.ends_with(['.', '!', '?']) {
+            spans.push((start, i + 1));
+            start = i + 1;
+        }
+    }
+    if start < words.len() {
+        spans.push((start, words.len()));
+    }
+    spans
+}
+
+/// Report how each of `sizes` would chunk `documents`, to help pick a chunk size.
+///
+/// Chunks are fixed windows of `chunk_size` words, as produced by `chunk_text`.
+/// Works on the raw texts only, so no embedder or vector store is needed.
+/// Sizes of zero are skipped.
+pub fn chunk_size_report(documents: &[String], sizes: &[usize]) -> Vec<ChunkStats> {
+    let doc_words: Vec<Vec<&str>> = documents
+        .iter()
+        .map(|doc| doc.split_whitespace().collect())
+        .collect();
+
+    sizes
+        .iter()
+        .filter(|&&size| size > 0)
+        .map(|&chunk_size| {
+            let mut lengths = Vec::new();
+            let mut sentences = 0;
+            let mut split_sentences = 0;
+
+            for words in &doc_words {
+                for start in (0..words.len()).step_by(chunk_size) {
+                    lengths.push((words.len() - start).min(chunk_size));
+                }
+                for (start, end) in sentence_spans(words) {
+                    sentences += 1;
+                    if start / chunk_size != (end - 1) / chunk_size {
+                        split_sentences += 1;
+                    }
+                }
+            }
+
+            lengths.sort_unstable();
+            let mean_words = if lengths.is_empty() {
+                0.0
+            } else {
+                lengths.iter().sum::<usize>() as f32 / lengths.len() as f32
+            };
+            let median_words = match lengths.len() {
+                0 => 0.0,
+                n if n % 2 == 1 => lengths[n / 2] as f32,
+                n => (lengths[n / 2 - 1] + lengths[n / 2]) as f32 / 2.0,
+            };
+            let split_sentence_fraction = if sentences == 0 {
+                0.0
+            } else {
+                split_sentences as f32 / sentences as f32
+            };
+
+            ChunkStats {
+                chunk_size,
+                num_chunks: lengths.len(),
+                mean_words,
+                median_words,
+                split_sentence_fraction,
+            }
+        })
+        .collect()
+}
+
+/// Summary statistics of a JSON corpus.
+#[derive(Debug, Clone)]
+pub struct CorpusStats {
+    pub num_documents: usize,
+    pub total_words: usize,
+    pub mean_words: f32,
+    /// Documents per category; documents without one count as "general".
+    pub category_counts: BTreeMap<String, usize>,
+    /// Earliest and latest valid `YYYY-MM-DD` date, if any document has one.
+    pub date_range: Option<(NaiveDate, NaiveDate)>,
+}
+
+/// Load the corpus at `path` and summarize it, to sanity-check it before
+/// chunking or embedding. Dates that do not parse are ignored.
+pub fn corpus_stats(path: &str) -> Result<CorpusStats, Box<dyn Error>> {
+    let documents = read_documents(path)?;
+
+    let mut total_words = 0;
+    let mut category_counts = BTreeMap::new();
+    let mut date_range: Option<(NaiveDate, NaiveDate)> = None;
+    for doc in &documents {
+        total_words += doc.content.split_whitespace().count();
+
+        let category = doc.category.as_deref().unwrap_or("general");
+        *category_counts.entry(category.to_string()).or_insert(0) += 1;
+
+        let date = doc
+            .date
+            .as_deref()
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+        if let Some(date) = date {
+            date_range = Some(match date_range {
+                Some((first, last)) => (first.min(date), last.max(date)),
+                None => (date, date),
+            });
+        }
+    }
+
+    let mean_words = if documents.is_empty() {
+        0.0
+    } else {
+        total_words as f32 / documents.len() as f32
+    };
+
+    Ok(CorpusStats {
+        num_documents: documents.len(),
+        total_words,
+        mean_words,
+        category_counts,
+        date_range,
+    })
+}
@@ -2,8 +2,193 @@ mod embeddings;
 
 use embeddings::SentenceEmbedder;
 use ndarray::Array1;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 
+/// N-gram lengths used when no other range is given: unigrams and bigrams.
+const DEFAULT_NGRAM_RANGE: (usize, usize) = (1, 2);
+
+/// Lowercase the text, strip surrounding punctuation and return its n-grams of
+/// every length from `ngram_range.0` to `ngram_range.1` words, shortest first.
+fn tokenize_terms(text: &str, ngram_range: (usize, usize)) -> Vec<String> {
+    let words: Vec<String> = text
+        .to_lowercase()
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| ".,!?;:()\"'".contains(c))
+                .to_string()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let (min_n, max_n) = ngram_range;
+    (min_n.max(1)..=max_n)
+        .flat_map(|n| words.windows(n).map(|gram| gram.join(" ")))
+        .collect()
+}
+
+/// Term → index into BOW vectors.
+type Vocab = HashMap<String, usize>;
+
+/// Term → number of documents containing it.
+type DocFreqs = HashMap<String, usize>;
+
+/// A document-frequency threshold: an absolute number of documents or a
+/// fraction of the corpus.
+#[derive(Debug, Clone, Copy)]
+enum DocFreq {
+    Count(usize),
+    Fraction(f32),
+}
+
+impl DocFreq {
+    /// The threshold as a number of documents, for a corpus of `n_docs` documents.
+    fn as_count(self, n_docs: usize) -> f32 {
+        match self {
+            DocFreq::Count(count) => count as f32,
+            DocFreq::Fraction(fraction) => fraction * n_docs as f32,
+        }
+    }
+}
+
+/// Build the vocabulary of the n-grams in `docs` whose length is in
+/// `ngram_range` and whose document frequency lies in `[min_df, max_df]`.
+///
+/// `DocFreq::Count(1)` and `DocFreq::Fraction(1.0)` keep every term. Returns
+/// the term-to-index map and the document frequency of each kept term, for
+/// reuse in TF-IDF weighting. Fails if the n-gram range is empty
+/// (`min > max`) or starts at zero.
+fn build_vocab(
+    docs: &[&str],
+    ngram_range: (usize, usize),
+    min_df: DocFreq,
+    max_df: DocFreq,
+) -> Result<(Vocab, DocFreqs), Box<dyn Error>> {
+    let (min_n, max_n) = ngram_range;
+    if min_n == 0 || min_n > max_n {
+        return Err(
+            format!("invalid n-gram range ({min_n}, {max_n}): need 1 <= min <= max").into(),
+        );
+    }
+
+    // Count, for each n-gram, the number of documents it appears in
+    let mut doc_freq: DocFreqs = HashMap::new();
+    for doc in docs {
+        let doc_terms: HashSet<String> = tokenize_terms(doc, ngram_range).into_iter().collect();
+        for term in doc_terms {
+            *doc_freq.entry(term).or_insert(0) += 1;
+        }
+    }
+
+    // Drop terms that are too rare (noise) or too common (no signal)
+    let (min_count, max_count) = (min_df.as_count(docs.len()), max_df.as_count(docs.len()));
+    doc_freq.retain(|_, &mut df| df as f32 >= min_count && df as f32 <= max_count);
+
+    // Sort so that term indices are deterministic
+    let mut terms: Vec<String> = doc_freq.keys().cloned().collect();
+    terms.sort();
+
+    let vocab = terms
+        .into_iter()
+        .enumerate()
+        .map(|(idx, term)| (term, idx))
+        .collect();
+    Ok((vocab, doc_freq))
+}
+
+/// Count every known n-gram of the text; `ngram_range` must match the one the vocab was built with.
+///
+/// Also returns the fraction of the text's n-grams found in the vocabulary
+/// (0.0 for a text without any).
+fn bow_vectorize(
+    text: &str,
+    vocab: &HashMap<String, usize>,
+    ngram_range: (usize, usize),
+) -> (Vec<usize>, f32) {
+    let mut vector = vec![0; vocab.len()];
+    let terms = tokenize_terms(text, ngram_range);
+    let mut known = 0;
+    for term in &terms {
+        if let Some(&index) = vocab.get(term) {
+            vector[index] += 1;
+            known += 1;
+        }
+    }
+    let coverage = if terms.is_empty() {
+        0.0
+    } else {
+        known as f32 / terms.len() as f32
+    };
+    (vector, coverage)
+}
+
+/// BOW search: `(doc index, score)` sorted by score, and the query's
+/// vocabulary coverage (see `bow_vectorize`).
+///
+/// At a coverage of 0.0 every score is zero and the order is just document
+/// order, so the result says nothing about relevance; fall back to dense
+/// retrieval then.
+fn bow_search(
+    query: &str,
+    docs: &[&str],
+    vocab: &HashMap<String, usize>,
+    ngram_range: (usize, usize),
+) -> (Vec<(usize, usize)>, f32) {
+    let (hits, coverage) = bow_search_explain(query, docs, vocab, ngram_range);
+    let hits = hits
+        .into_iter()
+        .map(|(idx, score, _)| (idx, score))
+        .collect();
+    (hits, coverage)
+}
+
+/// A BOW hit: document index, score and the `(term, contribution)` pairs behind the score.
+type ExplainedHit = (usize, usize, Vec<(String, usize)>);
+
+/// BOW search that also reports which terms produced each score.
+///
+/// Returns `(doc index, score, matched terms)` sorted by score (highest first,
+/// ties in document order). Each matched term carries its contribution to the
+/// dot product, `query count * document count`, largest first; the
+/// contributions sum to the score. The query's vocabulary coverage is
+/// returned alongside, as in `bow_search`.
+fn bow_search_explain(
+    query: &str,
+    docs: &[&str],
+    vocab: &HashMap<String, usize>,
+    ngram_range: (usize, usize),
+) -> (Vec<ExplainedHit>, f32) {
+    // Map vector positions back to their terms
+    let mut terms_by_index = vec![""; vocab.len()];
+    for (term, &idx) in vocab {
+        terms_by_index[idx] = term.as_str();
+    }
+
+    let (query_vec, coverage) = bow_vectorize(query, vocab, ngram_range);
+
+    let mut results: Vec<ExplainedHit> = docs
+        .iter()
+        .enumerate()
+        .map(|(doc_idx, doc)| {
+            let (doc_vec, _) = bow_vectorize(doc, vocab, ngram_range);
+            let mut matched: Vec<(String, usize)> = query_vec
+                .iter()
+                .zip(&doc_vec)
+                .enumerate()
+                .filter(|(_, (q, d))| **q > 0 && **d > 0)
+                .map(|(term_idx, (q, d))| (terms_by_index[term_idx].to_string(), q * d))
+                .collect();
+            matched.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+            let score = matched.iter().map(|(_, contribution)| contribution).sum();
+            (doc_idx, score, matched)
+        })
+        .collect();
+
+    results.sort_by_key(|hit| std::cmp::Reverse(hit.1));
+    (results, coverage)
+}
+
 fn cosine_similarity(vec_a: &Array1<f32>, vec_b: &Array1<f32>) -> f32 {
     // Calculate dot product
     let dot_product = vec_a.dot(vec_b);
@@ -66,6 +251,61 @@ async fn main() -> Result<(), Box<dyn Error>> {
         "How does a system combine external data with language generation to improve responses?";
     println!("Query: {query}");
 
+    // BOW_NGRAMS=1,3 adds trigrams; the default is unigrams + bigrams
+    let ngram_range = match std::env::var("BOW_NGRAMS") {
+        Ok(value) => match value.split_once(',') {
+            Some((min, max)) => (min.trim().parse()?, max.trim().parse()?),
+            None => return Err(format!("BOW_NGRAMS must be 'min,max', got '{value}'").into()),
+        },
+        Err(_) => DEFAULT_NGRAM_RANGE,
+    };
+
+    // Keep every term; e.g. DocFreq::Count(2) and DocFreq::Fraction(0.5) would
+    // drop one-off terms and terms found in more than half the documents
+    let (vocab, doc_freq) = build_vocab(
+        &knowledge_base,
+        ngram_range,
+        DocFreq::Count(1),
+        DocFreq::Fraction(1.0),
+    )?;
+    let shared_terms = doc_freq.values().filter(|&&df| df > 1).count();
+    println!(
+        "Vocabulary: {} terms ({} appear in more than one document)",
+        vocab.len(),
+        shared_terms
+    );
+    let (bow_results, coverage) = bow_search(query, &knowledge_base, &vocab, ngram_range);
+    println!(
+        "\nBOW Search Results (query coverage {:.0}%):",
+        coverage * 100.0
+    );
+    if coverage == 0.0 {
+        println!(
+            "  Warning: no query term is in the vocabulary, so every BOW score is 0 and \
+             the order below is meaningless; rely on the embedding results instead."
+        );
+    }
+    for (idx, score) in bow_results {
+        println!(
+            "  Doc {idx} | Score: {score} | Text: {}",
+            knowledge_base[idx]
+        );
+    }
+
+    // Explain mode: which terms made up each BOW score
+    println!("\nBOW Search Explained:");
+    let (explained, _) = bow_search_explain(query, &knowledge_base, &vocab, ngram_range);
+    for (idx, score, terms) in explained {
+        let breakdown: Vec<String> = terms
+            .iter()
+            .map(|(term, contribution)| format!("'{term}'={contribution}"))
+            .collect();
+        println!(
+            "  Doc {idx} | Score: {score} | Terms: {}",
+            breakdown.join(", ")
+        );
+    }
+
     let embedder = SentenceEmbedder::new().await?;
 
     let emb_results = embedding_search(query, &knowledge_base, &embedder)?;
@@ -1,4 +1,6 @@
+use crate::truncate::{CJK_TERMINATORS, SentenceSplitter};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
@@ -9,29 +11,132 @@ pub struct Chunk {
     pub chunk_id: usize,
     pub category: String,
     pub text: String,
+    /// Byte offset of the chunk's first word in the source document.
+    pub start: usize,
+    /// Byte offset just past the chunk's last word in the source document.
+    pub end: usize,
 }
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Document {
-    id: usize,
+    /// Explicit document id; without one, loaders number it after the largest
+    /// explicit id (see `assign_doc_ids`).
+    id: Option<usize>,
     content: String,
     category: Option<String>,
 }
 
-/// Splits the given text into chunks of size 'chunk_size' words.
-pub fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
-    let words: Vec<&str> = text.split_whitespace().collect();
-    let mut chunks = Vec::new();
+/// Splits the given text into chunks of size 'chunk_size' words, returning each
+/// chunk with the byte range `start..end` it spans in `text`.
+///
+/// A word that holds several sentences, as unspaced Chinese or Japanese text
+/// does, is cut at the sentence ends `splitter` finds and each sentence counts
+/// as one word. Words inside a chunk are joined by single spaces (sentences cut
+/// from one word are not), so the chunk text equals `text[start..end]` up to
+/// whitespace.
+pub fn chunk_text(
+    text: &str,
+    chunk_size: usize,
+    splitter: &SentenceSplitter,
+) -> Vec<(String, usize, usize)> {
+    // Byte span of every whitespace-separated word
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                words.push((start, i));
+            }
+        } else if word_start.is_none() {
+            word_start = Some(i);
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, text.len()));
+    }
 
-    for i in (0..words.len()).step_by(chunk_size) {
-        let end = (i + chunk_size).min(words.len());
-        let chunk = words[i..end].join(" ");
-        chunks.push(chunk);
+    // Byte span of every sentence within those words
+    let mut spans = Vec::new();
+    for (start, end) in words {
+        let mut offset = start;
+        for sentence in splitter.split(&text[start..end]) {
+            spans.push((offset, offset + sentence.len()));
+            offset += sentence.len();
+        }
+    }
+
+    let mut chunks = Vec::new();
+    for group in spans.chunks(chunk_size.max(1)) {
+        // `chunks` never yields an empty group, but avoid indexing anyway
+        let (Some(&(start, _)), Some(&(_, end))) = (group.first(), group.last()) else {
+            continue;
+        };
+        let mut chunk = String::new();
+        for &(s, e) in group {
+            if !chunk.is_empty() && text[..s].ends_with(char::is_whitespace) {
+                chunk.push(' ');
+            }
+            chunk.push_str(&text[s..e]);
+        }
+        chunks.push((chunk, start, end));
     }
 
     chunks
 }
 
+/// Check that `quote` occurs within `doc_text[start..end]`.
+///
+/// Whitespace is normalized on both sides, since chunk texts collapse runs of
+/// whitespace. Returns false for an empty quote or offsets that do not fall on
+/// character boundaries inside `doc_text`.
+pub fn verify_quote(doc_text: &str, start: usize, end: usize, quote: &str) -> bool {
+    let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let quote = normalize(quote);
+    if quote.is_empty() {
+        return false;
+    }
+
+    match doc_text.get(start..end) {
+        Some(span) => normalize(span).contains(&quote),
+        None => false,
+    }
+}
+
+/// The id of each document, in file order: its explicit `id`, or for a
+/// document without one the next number after the largest explicit id, so a
+/// fallback id never collides with an explicit one.
+fn assign_doc_ids(documents: &[Document]) -> Vec<usize> {
+    let mut next_id = documents
+        .iter()
+        .filter_map(|doc| doc.id)
+        .max()
+        .map_or(0, |max| max + 1);
+    documents
+        .iter()
+        .map(|doc| {
+            doc.id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        })
+        .collect()
+}
+
+/// Loads the full text of every document in the JSON file_path, keyed by document id.
+pub fn load_document_texts(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let documents: Vec<Document> = serde_json::from_reader(reader)?;
+
+    let doc_ids = assign_doc_ids(&documents);
+    Ok(doc_ids
+        .into_iter()
+        .zip(documents)
+        .map(|(doc_id, doc)| (doc_id, doc.content))
+        .collect())
+}
+
 /// Loads a dataset from JSON file_path, then splits each document into smaller chunks.
 pub fn load_and_chunk_dataset(
     file_path: &str,
@@ -41,22 +146,82 @@ pub fn load_and_chunk_dataset(
     let reader = BufReader::new(file);
     let documents: Vec<Document> = serde_json::from_reader(reader)?;
 
+    let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
     let mut all_chunks = Vec::new();
 
-    for doc in documents {
+    let doc_ids = assign_doc_ids(&documents);
+    for (doc, doc_id) in documents.into_iter().zip(doc_ids) {
         let doc_category = doc.category.unwrap_or_else(|| "general".to_string());
-        let doc_id = doc.id;
-        let doc_chunks = chunk_text(&doc.content, chunk_size);
+        let doc_chunks = chunk_text(&doc.content, chunk_size, &splitter);
 
-        for (chunk_id, chunk_str) in doc_chunks.into_iter().enumerate() {
+        for (chunk_id, (chunk_str, start, end)) in doc_chunks.into_iter().enumerate() {
             all_chunks.push(Chunk {
                 doc_id,
                 chunk_id,
                 category: doc_category.clone(),
                 text: chunk_str,
+                start,
+                end,
             });
         }
     }
 
     Ok(all_chunks)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn unspaced_japanese_is_chunked_by_sentence() {
+        let text = "今日は晴れです。明日は雨です。 Then English follows.";
+        let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+        let chunks = chunk_text(text, 2, &splitter);
+
+        let texts: Vec<&str> = chunks.iter().map(|(chunk, _, _)| chunk.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["今日は晴れです。明日は雨です。", "Then English", "follows."]
+        );
+        for (chunk, start, end) in &chunks {
+            assert_eq!(&text[*start..*end], chunk);
+        }
+    }
+
+    proptest! {
+        // Any string, including whitespace runs, multibyte and unspaced CJK
+        // text, chunks without panicking into ranges on char boundaries
+        #[test]
+        fn chunk_text_keeps_every_word_in_valid_ranges(
+            text in any::<String>(),
+            chunk_size in 0usize..8,
+        ) {
+            let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+            let chunks = chunk_text(&text, chunk_size, &splitter);
+
+            let squeeze = |s: &str| s.split_whitespace().collect::<String>();
+            for (chunk, start, end) in &chunks {
+                let span = text.get(*start..*end);
+                prop_assert!(span.is_some());
+                prop_assert_eq!(squeeze(chunk), squeeze(span.unwrap()));
+            }
+            let chunked: String = chunks.iter().map(|(chunk, _, _)| squeeze(chunk)).collect();
+            prop_assert_eq!(chunked, squeeze(&text));
+        }
+    }
+
+    #[test]
+    fn fallback_ids_follow_the_largest_explicit_id() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": 5, "content": "a"}, {"content": "b"}, {"id": 2, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&documents), [5, 6, 2, 7]);
+
+        let documents: Vec<Document> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&documents), [0, 1]);
+    }
+}
@@ -5,12 +5,108 @@ use async_openai::types::{
     ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
     ChatCompletionRequestSystemMessageContent, ChatCompletionRequestUserMessageContent,
 };
+use async_trait::async_trait;
 use dotenv::dotenv;
+use std::collections::{HashMap, VecDeque};
 use std::env;
+use std::sync::Mutex;
+use crate::prompt::PromptTemplate;
+use crate::truncate::{
+    CJK_TERMINATORS, SentenceSplitter, approx_token_count, truncate_to_budget,
+};
+
+/// Sampling settings for one completion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingParams {
+    pub temperature: f32,
+    pub top_p: f32,
+}
+
+impl SamplingParams {
+    /// Greedy decoding, so the same prompt gives the same answer.
+    pub const DETERMINISTIC: SamplingParams = SamplingParams {
+        temperature: 0.0,
+        top_p: 1.0,
+    };
+}
+
+/// Reply used by the generation functions when there is no context to answer from.
+pub const DEFAULT_NO_CONTEXT_MESSAGE: &str = "I'm sorry, but I couldn't find any relevant information.";
+
+/// A chat model that answers prompts.
+///
+/// `LlmClient` calls the OpenAI API; `MockLlm` answers offline, so generation
+/// logic can run without an API key or network access.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// Reply to give instead of calling the model when the context is empty or
+    /// cannot answer the question.
+    fn no_context_message(&self) -> &str {
+        DEFAULT_NO_CONTEXT_MESSAGE
+    }
+
+    /// Like `get_llm_response`, but samples with `sampling` for this call only.
+    async fn get_llm_response_sampled(
+        &self,
+        prompt: &str,
+        sampling: SamplingParams,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// Like `get_llm_response`, but uses `system` as the system message for this call only.
+    async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Offline `LlmBackend` for tests and demos.
+///
+/// Returns the canned responses in order, one per call; once they run out
+/// (or if none were given) it echoes the prompt back. System messages and
+/// sampling settings are ignored.
+pub struct MockLlm {
+    responses: Mutex<VecDeque<String>>,
+}
+
+impl MockLlm {
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlm {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let canned = self.responses.lock().unwrap().pop_front();
+        Ok(canned.unwrap_or_else(|| prompt.to_string()))
+    }
+
+    async fn get_llm_response_sampled(
+        &self,
+        prompt: &str,
+        _sampling: SamplingParams,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response(prompt).await
+    }
+
+    async fn get_llm_response_with_system(
+        &self,
+        _system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response(prompt).await
+    }
+}
 
 pub struct LlmClient {
     client: Client<OpenAIConfig>,
     system_prompt: String,
+    no_context_message: String,
 }
 
 impl LlmClient {
@@ -27,115 +123,55 @@ impl LlmClient {
         Self {
             client: Client::with_config(config),
             system_prompt: "You are a helpful AI assistant. You always answer to the user's queries.".to_string(),
+            no_context_message: DEFAULT_NO_CONTEXT_MESSAGE.to_string(),
         }
     }
 
-    /// Generate an answer given a query and retrieved context, under different prompting strategies.
-    /// TODO: Add context-length validation and smart truncation if the context exceeds a limit of 4096 tokens (approx. word-based).
-    /// If truncation occurs, "[Context truncated]" should be appended to the answer.
-pub async fn generate_with_constraints(
-        &self,
-        query: &str,
-        retrieved_context: &str,
-        strategy: &str,
-    ) -> Result<(String, String), Box<dyn std::error::Error>> {
-        // Fallback if no context
-        if retrieved_context.trim().is_empty() {
-            return Ok((
-                "I'm sorry, but I couldn't find any relevant information.".to_string(),
-                "No context used.".to_string(),
-            ));
-        }
-
-        // Approximate token limit
-        const MAX_TOKENS: usize = 4096;
-
-        // Check and truncate context if too large
-        let mut context = retrieved_context.to_string();
-        let mut truncated = false;
-
-        // Approximate token count (1 token ≈ 0.75 words)
-        let word_count = context.split_whitespace().count();
-        let approx_tokens = (word_count as f32 / 0.75).ceil() as usize;
-
-        if approx_tokens > MAX_TOKENS {
-            truncated = true;
-            // Split context into sentences
-            let sentences: Vec<&str> = context
-                .split_inclusive(&['.', '!', '?'])
-                .filter(|s| !s.trim().is_empty())
-                .collect();
-            let mut truncated_context = String::new();
-            let mut current_tokens = 0;
-
-            // Add sentences until reaching token limit
-            for sentence in sentences {
-                let sentence_words = sentence.split_whitespace().count();
-                let sentence_tokens = (sentence_words as f32 / 0.75).ceil() as usize;
-                if current_tokens + sentence_tokens <= MAX_TOKENS {
-                    truncated_context.push_str(sentence);
-                    current_tokens += sentence_tokens;
-                } else {
-                    break;
-                }
-            }
-
-            context = truncated_context;
-        }
-
-        // Build the prompt according to the chosen strategy
-        let prompt = match strategy {
-            "strict" => format!(
-                "You must ONLY use the context provided below. \
-                If you cannot find the answer in the context, say: 'No sufficient data'.\n\
-                Do not provide any information not found in the context.\n\n\
-                Context:\n{}\n\
-                Question: '{}'\n\
-                Answer:",
-                context, query
-            ),
-            "cite" => format!(
-                "Answer strictly from the provided context, and list the lines you used as evidence with 'Cited lines:'.\
-                If the context does not contain the information, respond with: 'Not available in the retrieved texts.'\n\n\
-                Provided context (label lines as needed):\n{}\n\
-                Question: '{}'\n\
-                Answer:",
-                context, query
-            ),
-            _ => format!(
-                "Use the following context to answer the question in a concise manner.\n\n\
-                Context:\n{}\n\
-                Question: '{}'\n\
-                Answer:",
-                context, query
-            ),
-        };
-
-        println!("Prompt:\n{}\n", prompt);
+    /// Use `message` instead of `DEFAULT_NO_CONTEXT_MESSAGE` when there is no context.
+    pub fn with_no_context_message(mut self, message: impl Into<String>) -> Self {
+        self.no_context_message = message.into();
+        self
+    }
+}
 
-        // Call the LLM
-        let response = self.get_llm_response(&prompt).await?;
+#[async_trait]
+impl LlmBackend for LlmClient {
+    fn no_context_message(&self) -> &str {
+        &self.no_context_message
+    }
 
-        // Parse out "Cited lines:" if present
-        let parts: Vec<&str> = response.splitn(2, "Cited lines:").collect();
-        let (mut answer, cited) = if parts.len() == 2 {
-            (parts[0].trim().to_string(), parts[1].trim().to_string())
-        } else {
-            (response.trim().to_string(), "No explicit lines cited.".to_string())
-        };
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(&self.system_prompt, prompt, SamplingParams::DETERMINISTIC).await
+    }
 
-        // Append truncation warning if context was truncated
-        if truncated {
-            answer.push_str(" [Context truncated]");
-        }
+    async fn get_llm_response_sampled(
+        &self,
+        prompt: &str,
+        sampling: SamplingParams,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(&self.system_prompt, prompt, sampling).await
+    }
 
-        Ok((answer, cited))
+    async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.complete(system, prompt, SamplingParams::DETERMINISTIC).await
     }
+}
 
-    pub async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // Build messages using the default system prompt.
+impl LlmClient {
+    /// Send one chat completion request and return the first choice's text.
+    async fn complete(
+        &self,
+        system: &str,
+        prompt: &str,
+        sampling: SamplingParams,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        // Build messages using the given system prompt.
         let system_message = ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(self.system_prompt.clone()),
+            content: ChatCompletionRequestSystemMessageContent::Text(system.to_string()),
             name: None,
         };
 
@@ -152,9 +188,9 @@ pub async fn generate_with_constraints(
         let request = CreateChatCompletionRequestArgs::default()
             .model("gpt-4o-mini")
             .messages(messages)
-            .temperature(0.0)
+            .temperature(sampling.temperature)
             .max_tokens(500_u32)
-            .top_p(1.0)
+            .top_p(sampling.top_p)
             .frequency_penalty(0.0)
             .presence_penalty(0.0)
             .build()?;
@@ -168,3 +204,240 @@ pub async fn generate_with_constraints(
         Ok(answer)
     }
 }
+
+/// Generate an answer given a query and retrieved context, under different prompting strategies.
+///
+/// `strategy` selects a built-in template: "strict", "cite", "summarize", or
+/// anything else for the plain "answer" template. "strict" and "cite" always
+/// sample deterministically so factual answers are reproducible; the other
+/// strategies use `base_sampling`. See `generate_with_template`.
+pub async fn generate_with_constraints(
+    llm: &dyn LlmBackend,
+    query: &str,
+    retrieved_context: &str,
+    strategy: &str,
+    base_sampling: SamplingParams,
+    dry_run: bool,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let (name, sampling) = match strategy {
+        "strict" | "cite" => (strategy, SamplingParams::DETERMINISTIC),
+        "summarize" => (strategy, base_sampling),
+        _ => ("answer", base_sampling),
+    };
+    let template = PromptTemplate::builtin(name).expect("built-in template exists");
+    generate_with_template(llm, query, retrieved_context, &template, sampling, dry_run).await
+}
+
+/// Generate an answer by rendering `template` with `{query}` and `{context}`,
+/// sampling with `sampling`.
+/// The context is truncated to roughly 4096 tokens (word-based) at a sentence boundary.
+/// If truncation occurs, "[Context truncated]" is appended to the answer.
+///
+/// With `dry_run` the fully rendered prompt is returned as the answer and the
+/// LLM is not called.
+pub async fn generate_with_template(
+    llm: &dyn LlmBackend,
+    query: &str,
+    retrieved_context: &str,
+    template: &PromptTemplate,
+    sampling: SamplingParams,
+    dry_run: bool,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    // Fallback if no context
+    if retrieved_context.trim().is_empty() {
+        return Ok((llm.no_context_message().to_string(), "No context used.".to_string()));
+    }
+
+    // Approximate token limit
+    const MAX_TOKENS: usize = 4096;
+
+    // Truncate the context at a sentence boundary if it is too large, recognizing
+    // the full-width terminators of Chinese and Japanese text as well
+    let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+    let (context, truncated) =
+        truncate_to_budget(retrieved_context, MAX_TOKENS, approx_token_count, &splitter);
+
+    // Fill the template
+    let prompt = template.render(&HashMap::from([("query", query), ("context", context.as_str())]))?;
+
+    println!("Prompt:\n{}\n", prompt);
+
+    if dry_run {
+        return Ok((prompt, "Dry run: the LLM was not called.".to_string()));
+    }
+
+    // Call the LLM
+    let response = llm.get_llm_response_sampled(&prompt, sampling).await?;
+
+    // Parse out "Cited lines:" if present
+    let parts: Vec<&str> = response.splitn(2, "Cited lines:").collect();
+    let (mut answer, cited) = if parts.len() == 2 {
+        (parts[0].trim().to_string(), parts[1].trim().to_string())
+    } else {
+        (response.trim().to_string(), "No explicit lines cited.".to_string())
+    };
+
+    // Append truncation warning if context was truncated
+    if truncated {
+        answer.push_str(" [Context truncated]");
+    }
+
+    Ok((answer, cited))
+}
+
+/// Ask the model whether `query` can be answered solely from `context`.
+///
+/// This is a cheap yes/no call made before a full generation so that obviously
+/// unanswerable questions can be refused without spending tokens. Replies are
+/// parsed leniently ("Yes.", "**No**", "Answer: no, the context ..."). If the
+/// call fails or the reply contains neither word, the question is treated as
+/// answerable so that generation still runs.
+pub async fn is_answerable(query: &str, context: &str, llm: &dyn LlmBackend) -> bool {
+    if context.trim().is_empty() {
+        return false;
+    }
+
+    let prompt = PromptTemplate::builtin("answerable")
+        .expect("built-in template exists")
+        .render(&HashMap::from([("query", query), ("context", context)]))
+        .expect("built-in template has only query and context placeholders");
+
+    match llm
+        .get_llm_response_with_system("You judge whether a context contains the answer to a question.", &prompt)
+        .await
+    {
+        Ok(reply) => parse_yes_no(&reply).unwrap_or(true),
+        Err(e) => {
+            eprintln!("Answerability check failed ({}); generating anyway", e);
+            true
+        }
+    }
+}
+
+/// Return the first standalone "yes" or "no" in `reply`, ignoring case and punctuation.
+fn parse_yes_no(reply: &str) -> Option<bool> {
+    reply
+        .split(|c: char| !c.is_alphanumeric())
+        .find_map(|word| match word.to_lowercase().as_str() {
+            "yes" => Some(true),
+            "no" => Some(false),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: &str = "Revenue grew 12% in 2023.";
+    const CREATIVE: SamplingParams = SamplingParams {
+        temperature: 0.9,
+        top_p: 0.95,
+    };
+
+    /// Answers "ok" and remembers the sampling settings it was called with.
+    struct RecordingLlm {
+        sampling: Mutex<Option<SamplingParams>>,
+    }
+
+    #[async_trait]
+    impl LlmBackend for RecordingLlm {
+        async fn get_llm_response(
+            &self,
+            _prompt: &str,
+        ) -> Result<String, Box<dyn std::error::Error>> {
+            Ok("ok".to_string())
+        }
+
+        async fn get_llm_response_sampled(
+            &self,
+            _prompt: &str,
+            sampling: SamplingParams,
+        ) -> Result<String, Box<dyn std::error::Error>> {
+            *self.sampling.lock().unwrap() = Some(sampling);
+            Ok("ok".to_string())
+        }
+
+        async fn get_llm_response_with_system(
+            &self,
+            _system: &str,
+            prompt: &str,
+        ) -> Result<String, Box<dyn std::error::Error>> {
+            self.get_llm_response(prompt).await
+        }
+    }
+
+    #[tokio::test]
+    async fn cited_lines_are_split_from_the_answer() {
+        let llm = MockLlm::new(vec!["Revenue grew 12%.\nCited lines: line 1".to_string()]);
+        let (answer, cited) =
+            generate_with_constraints(&llm, "What grew?", CONTEXT, "cite", CREATIVE, false)
+                .await
+                .unwrap();
+        assert_eq!(answer, "Revenue grew 12%.");
+        assert_eq!(cited, "line 1");
+    }
+
+    #[tokio::test]
+    async fn blank_context_skips_the_model() {
+        let llm = MockLlm::new(vec!["should not be used".to_string()]);
+        let (answer, cited) =
+            generate_with_constraints(&llm, "What grew?", " \n", "strict", CREATIVE, false)
+                .await
+                .unwrap();
+        assert_eq!(answer, llm.no_context_message());
+        assert_eq!(cited, "No context used.");
+    }
+
+    #[tokio::test]
+    async fn dry_run_returns_the_rendered_prompt() {
+        let llm = MockLlm::new(vec!["should not be used".to_string()]);
+        let (prompt, _) =
+            generate_with_constraints(&llm, "What grew?", CONTEXT, "answer", CREATIVE, true)
+                .await
+                .unwrap();
+        assert!(prompt.contains(CONTEXT));
+        assert!(prompt.contains("What grew?"));
+    }
+
+    #[tokio::test]
+    async fn factual_strategies_sample_deterministically() {
+        for (strategy, expected) in [
+            ("strict", SamplingParams::DETERMINISTIC),
+            ("cite", SamplingParams::DETERMINISTIC),
+            ("summarize", CREATIVE),
+            ("unknown", CREATIVE),
+        ] {
+            let llm = RecordingLlm {
+                sampling: Mutex::new(None),
+            };
+            generate_with_constraints(&llm, "What grew?", CONTEXT, strategy, CREATIVE, false)
+                .await
+                .unwrap();
+            assert_eq!(*llm.sampling.lock().unwrap(), Some(expected), "{strategy}");
+        }
+    }
+
+    #[tokio::test]
+    async fn answerability_replies_are_parsed_leniently() {
+        for (reply, expected) in [
+            ("Yes.", true),
+            ("**No**", false),
+            ("Answer: no, the context does not say.", false),
+            ("It is unclear.", true),
+        ] {
+            let llm = MockLlm::new(vec![reply.to_string()]);
+            assert_eq!(
+                is_answerable("What grew?", CONTEXT, &llm).await,
+                expected,
+                "{reply:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn blank_context_is_not_answerable() {
+        let llm = MockLlm::new(vec!["Yes".to_string()]);
+        assert!(!is_answerable("What grew?", "  ", &llm).await);
+    }
+}
@@ -1,16 +1,37 @@
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 mod llm;
+mod prompt;
+mod truncate;
 
 use std::env;
 use std::error::Error;
-use data::{load_and_chunk_dataset, Chunk};
+use data::{load_and_chunk_dataset, load_document_texts, verify_quote, Chunk};
 use embeddings::SentenceEmbedder;
 use vector_db::build_chroma_collection;
-use llm::LlmClient;
+use llm::{
+    generate_with_constraints, generate_with_template, is_answerable, LlmBackend, LlmClient, MockLlm,
+    SamplingParams,
+};
+use prompt::PromptTemplate;
 use chromadb::collection::QueryOptions;
 
+/// Whether `value` switches a flag on: "1", "true", "yes" or "on", in any case.
+fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Whether the environment variable `name` is set to a true value (see
+/// `is_truthy`); unset, empty, "0" and "false" all leave the flag off.
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| is_truthy(&value))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // 1. Load and chunk documents with a chunk size of 50
@@ -23,8 +44,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let collection = build_chroma_collection(&docs, "corpus_collection", &embedder).await?;
     println!("ChromaDB collection created with {} document chunks.", collection.count().await?);
 
-    // 3. Prepare LLM client
-    let llm = LlmClient::new();
+    // 3. Prepare LLM backend
+    // --dry-run (or DRY_RUN=1) shows each prompt in place of its answer without calling the LLM
+    let dry_run = env::args().any(|arg| arg == "--dry-run") || env_flag("DRY_RUN");
+    // LLM_BACKEND=mock echoes prompts instead of calling OpenAI (no API key needed)
+    let llm: Box<dyn LlmBackend> = if dry_run || env::var("LLM_BACKEND").as_deref() == Ok("mock") {
+        Box::new(MockLlm::new(Vec::new()))
+    } else {
+        // NO_CONTEXT_MESSAGE overrides the reply given when nothing relevant is retrieved
+        let mut client = LlmClient::new();
+        if let Ok(message) = env::var("NO_CONTEXT_MESSAGE") {
+            client = client.with_no_context_message(message);
+        }
+        Box::new(client)
+    };
 
     // 4. Run sample query
     let query = "Highlight the main policies that apply to employees.";
@@ -36,7 +69,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         n_results: Some(2),
         where_metadata: None,
         where_document: None,
-        include: Some(vec!["documents".into(), "distances".into()]),
+        include: Some(vec!["documents".into(), "distances".into(), "metadatas".into()]),
     };
 
     let retrieval = collection.query(query_opts, None).await?;
@@ -58,15 +91,88 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .join("\n")
     };
 
-    // 6. Run constrained generation with all strategies
-    for strategy in &["base", "strict", "cite"] {
+    // Source spans (doc_id, start, end) of the retrieved chunks, for quote verification
+    let doc_texts = load_document_texts(dataset_file.to_str().unwrap())?;
+    let spans: Vec<(usize, usize, usize)> = retrieval
+        .metadatas
+        .as_ref()
+        .and_then(|groups| groups.first())
+        .map(|metas| {
+            metas
+                .iter()
+                .flatten()
+                .filter_map(|meta| {
+                    let field = |key: &str| meta.get(key).and_then(|v| v.as_u64()).map(|v| v as usize);
+                    Some((field("doc_id")?, field("start")?, field("end")?))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // 6. Skip generation entirely when the retrieved context cannot answer the query
+    if !dry_run && !is_answerable(query, &retrieved_context, llm.as_ref()).await {
+        println!("{}", llm.no_context_message());
+        return Ok(());
+    }
+
+    // 7. Run constrained generation with all strategies.
+    // BASE_TEMPERATURE / BASE_TOP_P loosen sampling for "base" and "summarize";
+    // "strict" and "cite" always stay deterministic.
+    let env_f32 = |name: &str, default: f32| {
+        env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+    };
+    let base_sampling = SamplingParams {
+        temperature: env_f32("BASE_TEMPERATURE", SamplingParams::DETERMINISTIC.temperature),
+        top_p: env_f32("BASE_TOP_P", SamplingParams::DETERMINISTIC.top_p),
+    };
+    for strategy in &["base", "strict", "cite", "summarize"] {
         println!("=== Strategy: {} ===", strategy);
-        let (answer, used_context) = llm
-            .generate_with_constraints(query, &retrieved_context, strategy)
-            .await?;
+        let (answer, used_context) =
+            generate_with_constraints(llm.as_ref(), query, &retrieved_context, strategy, base_sampling, dry_run).await?;
         println!("Constrained generation answer:\n{}\n", answer);
         println!("Context or lines used:\n{}\n", used_context);
+
+        // Check that every cited line really occurs in one of the retrieved source spans
+        if *strategy == "cite" && !dry_run {
+            for line in used_context.lines() {
+                let quote = line.trim().trim_start_matches(['-', '*']).trim().trim_matches('"');
+                if quote.is_empty() || quote == "No explicit lines cited." {
+                    continue;
+                }
+                let verified = spans.iter().any(|&(doc_id, start, end)| {
+                    doc_texts
+                        .get(&doc_id)
+                        .is_some_and(|text| verify_quote(text, start, end, quote))
+                });
+                println!("[{}] {}", if verified { "verified" } else { "not found in sources" }, quote);
+            }
+        }
+    }
+
+    // PROMPT_TEMPLATE=<path> also runs a user-supplied template ({query} and {context} placeholders)
+    if let Ok(path) = env::var("PROMPT_TEMPLATE") {
+        println!("=== Custom template: {} ===", path);
+        let template = PromptTemplate::from_file(&path)?;
+        let (answer, used_context) =
+            generate_with_template(llm.as_ref(), query, &retrieved_context, &template, base_sampling, dry_run).await?;
+        println!("Custom template answer:\n{}\n", answer);
+        println!("Context or lines used:\n{}\n", used_context);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_true_values_turn_a_flag_on() {
+        for value in ["1", "true", "TRUE", " yes ", "On"] {
+            assert!(is_truthy(value), "{value:?}");
+        }
+        for value in ["", "0", "false", "no", "off", "maybe"] {
+            assert!(!is_truthy(value), "{value:?}");
+        }
+    }
+}
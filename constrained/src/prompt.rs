@@ -0,0 +1,126 @@
+use crate::error::RagError;
+use crate::llm::DEFAULT_NO_CONTEXT_MESSAGE;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+/// Answer concisely from the context.
+const ANSWER: &str = "Use the following context to answer the question in a concise manner.\n\n\
+    Context:\n{context}\n\
+    Question: '{query}'\n\
+    Answer:";
+
+/// Answer only from the context, with a fixed reply when it has no answer.
+const STRICT: &str = "You must ONLY use the context provided below. \
+    If you cannot find the answer in the context, say: 'No sufficient data'.\n\
+    Do not provide any information not found in the context.\n\n\
+    Context:\n{context}\n\
+    Question: '{query}'\n\
+    Answer:";
+
+/// Answer from the context and list the lines used after "Cited lines:".
+const CITE: &str = "Answer strictly from the provided context, and list the lines you used as evidence with 'Cited lines:'.\
+    If the context does not contain the information, respond with: 'Not available in the retrieved texts.'\n\n\
+    Provided context (label lines as needed):\n{context}\n\
+    Question: '{query}'\n\
+    Answer:";
+
+/// Summarize what the context says about the question.
+const SUMMARIZE: &str = "Summarize what the following context says about the question in a few sentences.\n\n\
+    Context:\n{context}\n\
+    Question: '{query}'\n\
+    Summary:";
+
+/// Ask whether the context alone answers the question; used by `is_answerable`.
+const ANSWERABLE: &str = "Can this question be answered solely from this context? \
+    Reply with a single word: Yes or No.\n\n\
+    Context:\n{context}\n\n\
+    Question: {query}";
+
+/// A prompt with named `{placeholder}`s, filled in by `render`.
+///
+/// A placeholder is a brace-enclosed name made of letters, digits and
+/// underscores; any other brace is kept as is.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    template: String,
+}
+
+impl PromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// Read a user-supplied template from `path`.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(fs::read_to_string(path)?))
+    }
+
+    /// Look up a built-in template: "answer", "strict", "cite", "summarize",
+    /// "answerable" or "refuse".
+    ///
+    /// "refuse" is the refusal shown instead of an answer, rendered locally and
+    /// never sent to the model; it is `DEFAULT_NO_CONTEXT_MESSAGE`.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let template = match name {
+            "answer" => ANSWER,
+            "strict" => STRICT,
+            "cite" => CITE,
+            "summarize" => SUMMARIZE,
+            "answerable" => ANSWERABLE,
+            "refuse" => DEFAULT_NO_CONTEXT_MESSAGE,
+            _ => return None,
+        };
+        Some(Self::new(template))
+    }
+
+    /// Replace every placeholder with its value from `vars`.
+    ///
+    /// Fails with `RagError::MissingPromptValue` if a placeholder has no value,
+    /// rather than sending the literal `{name}` to the model. Extra entries in
+    /// `vars` are ignored.
+    pub fn render(&self, vars: &HashMap<&str, &str>) -> Result<String, RagError> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(open) = rest.find('{') {
+            rendered.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+
+            let name_len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if name_len > 0 && after[name_len..].starts_with('}') {
+                let name = &after[..name_len];
+                let value = vars.get(name).ok_or_else(|| RagError::MissingPromptValue {
+                    placeholder: name.to_string(),
+                })?;
+                rendered.push_str(value);
+                rest = &after[name_len + 1..];
+            } else {
+                // Not a placeholder: keep the brace literally
+                rendered.push('{');
+                rest = after;
+            }
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuse_template_is_the_no_context_message() {
+        let refusal = PromptTemplate::builtin("refuse")
+            .unwrap()
+            .render(&HashMap::from([("query", "anything")]))
+            .unwrap();
+        assert_eq!(refusal, DEFAULT_NO_CONTEXT_MESSAGE);
+    }
+}
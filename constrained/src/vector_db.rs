@@ -1,16 +1,43 @@
 use crate::data::Chunk;
 use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
 use chromadb::collection::{ChromaCollection, CollectionEntries};
 use serde_json::json;
 
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
 /// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
 pub async fn build_chroma_collection(
     chunks: &[Chunk],
     collection_name: &str,
     embedder: &SentenceEmbedder,
 ) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    let client = connect_chroma().await?;
     let collection = client.get_or_create_collection(collection_name, None).await?;
 
     // Skip empty collection
@@ -33,6 +60,8 @@ pub async fn build_chroma_collection(
             map.insert("doc_id".to_string(), json!(chunk.doc_id));
             map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
             map.insert("category".to_string(), chunk.category.clone().into());
+            map.insert("start".to_string(), json!(chunk.start));
+            map.insert("end".to_string(), json!(chunk.end));
             map
         })
         .collect();
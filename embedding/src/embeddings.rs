@@ -2,44 +2,316 @@ use async_openai::{Client, config::OpenAIConfig};
 use async_openai::types::CreateEmbeddingRequestArgs;
 use std::error::Error;
 use std::env;
+use std::ops::Range;
 use dotenv::dotenv;
+use crate::ratelimit::RateLimiter;
+
+/// Embedding model used unless `EMBEDDING_MODEL` names another.
+const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// Vector size of `DEFAULT_EMBEDDING_MODEL`.
+const DEFAULT_EMBEDDING_DIMENSION: usize = 1536;
+
+/// Inputs sent per embeddings request unless `EMBEDDING_BATCH_SIZE` says otherwise.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Most tokens `DEFAULT_EMBEDDING_MODEL` accepts in a single input.
+const DEFAULT_MAX_INPUT_TOKENS: usize = 8191;
+
+/// Most tokens sent in one request unless `EMBEDDING_MAX_REQUEST_TOKENS` says
+/// otherwise; the OpenAI API rejects embeddings requests over 300,000 tokens.
+const DEFAULT_MAX_REQUEST_TOKENS: usize = 300_000;
+
+/// Approximate the token count of `text` (1 token ≈ 0.75 words).
+fn approx_token_count(text: &str) -> usize {
+    let word_count = text.split_whitespace().count();
+    (word_count as f32 / 0.75).ceil() as usize
+}
+
+/// Position and approximate token count of every input estimated to be over
+/// `max_tokens`.
+fn over_long_inputs(texts: &[&str], max_tokens: usize) -> Vec<(usize, usize)> {
+    texts
+        .iter()
+        .map(|text| approx_token_count(text))
+        .enumerate()
+        .filter(|&(_, tokens)| tokens > max_tokens)
+        .collect()
+}
+
+/// Split the inputs, given by their approximate token counts, into consecutive
+/// batches of at most `max_inputs` inputs. A batch is also closed before the
+/// input that would take its total over `max_tokens`; an input over
+/// `max_tokens` on its own gets a batch to itself.
+fn batch_ranges(token_counts: &[usize], max_inputs: usize, max_tokens: usize) -> Vec<Range<usize>> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut batch_tokens = 0;
+    for (i, &tokens) in token_counts.iter().enumerate() {
+        if i > start && (i - start == max_inputs || batch_tokens + tokens > max_tokens) {
+            batches.push(start..i);
+            start = i;
+            batch_tokens = 0;
+        }
+        batch_tokens += tokens;
+    }
+    if start < token_counts.len() {
+        batches.push(start..token_counts.len());
+    }
+    batches
+}
+
+/// Put the `(input index, vector)` pairs of the request for the batch starting
+/// at `offset` into their slots. The API numbers the inputs of each request
+/// from 0; indices outside the batch are ignored.
+fn place_batch(
+    slots: &mut [Option<Vec<f32>>],
+    offset: usize,
+    batch_len: usize,
+    results: Vec<(usize, Vec<f32>)>,
+) {
+    for (index, embedding) in results {
+        if index < batch_len
+            && let Some(slot) = slots.get_mut(offset + index)
+        {
+            *slot = Some(embedding);
+        }
+    }
+}
 
 pub struct SentenceEmbedder {
     client: Client<OpenAIConfig>,
+    model: String,
+    /// Vector size every embedding must have; `None` accepts whatever the server returns.
+    expected_dimension: Option<usize>,
+    /// Largest number of inputs sent in one request.
+    batch_size: usize,
+    /// Most tokens (approximately) sent in one request.
+    max_request_tokens: usize,
+    /// Most tokens the model accepts in one input; `None` when unknown.
+    max_input_tokens: Option<usize>,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl SentenceEmbedder {
+    /// Create an embedder for the OpenAI API or an OpenAI-compatible server.
+    ///
+    /// `OPENAI_BASE_URL` points it at another server (e.g. Ollama at
+    /// `http://localhost:11434/v1` or LM Studio), where `OPENAI_API_KEY` may be
+    /// left unset. `EMBEDDING_MODEL` picks the model and `EMBEDDING_DIM` the
+    /// vector size the collection expects; for the default model the size is
+    /// known, for any other it is only checked when `EMBEDDING_DIM` is set.
+    /// `EMBEDDING_BATCH_SIZE` caps the inputs per request (default 100) and
+    /// `EMBEDDING_MAX_REQUEST_TOKENS` their total tokens (default 300,000).
+    /// `EMBEDDING_MAX_TOKENS` gives the model's per-input token limit, which
+    /// is likewise only known for the default model.
     pub async fn new() -> Result<Self, Box<dyn Error>> {
         dotenv().ok();
-        
-        println!("Loading OpenAI embedding model (text-embedding-3-small)...");
-        let api_key = env::var("OPENAI_API_KEY")
-            .expect("OPENAI_API_KEY must be set in .env file");
-        
-        let config = OpenAIConfig::new().with_api_key(api_key);
+
+        let base_url = env::var("OPENAI_BASE_URL").ok();
+        let model = env::var("EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string());
+        let expected_dimension = match env::var("EMBEDDING_DIM") {
+            Ok(dim) => Some(dim.parse().map_err(|_| format!("EMBEDDING_DIM must be a number, got '{}'", dim))?),
+            Err(_) if model == DEFAULT_EMBEDDING_MODEL => Some(DEFAULT_EMBEDDING_DIMENSION),
+            Err(_) => None,
+        };
+        let batch_size = match env::var("EMBEDDING_BATCH_SIZE") {
+            Ok(size) => size
+                .parse::<usize>()
+                .ok()
+                .filter(|&size| size > 0)
+                .ok_or_else(|| format!("EMBEDDING_BATCH_SIZE must be a positive number, got '{}'", size))?,
+            Err(_) => DEFAULT_BATCH_SIZE,
+        };
+        let max_request_tokens = match env::var("EMBEDDING_MAX_REQUEST_TOKENS") {
+            Ok(limit) => limit
+                .parse::<usize>()
+                .ok()
+                .filter(|&limit| limit > 0)
+                .ok_or_else(|| {
+                    format!("EMBEDDING_MAX_REQUEST_TOKENS must be a positive number, got '{}'", limit)
+                })?,
+            Err(_) => DEFAULT_MAX_REQUEST_TOKENS,
+        };
+        let max_input_tokens = match env::var("EMBEDDING_MAX_TOKENS") {
+            Ok(limit) => Some(
+                limit
+                    .parse()
+                    .map_err(|_| format!("EMBEDDING_MAX_TOKENS must be a number, got '{}'", limit))?,
+            ),
+            Err(_) if model == DEFAULT_EMBEDDING_MODEL => Some(DEFAULT_MAX_INPUT_TOKENS),
+            Err(_) => None,
+        };
+
+        println!("Loading OpenAI embedding model ({})...", model);
+        let api_key = match env::var("OPENAI_API_KEY") {
+            Ok(key) => key,
+            // Local OpenAI-compatible servers do not check the key
+            Err(_) if base_url.is_some() => String::new(),
+            Err(_) => panic!("OPENAI_API_KEY must be set in .env file"),
+        };
+
+        let mut config = OpenAIConfig::new().with_api_key(api_key);
+        if let Some(base_url) = base_url {
+            config = config.with_api_base(base_url);
+        }
         let client = Client::with_config(config);
-        
-        Ok(Self { client })
+
+        Ok(Self {
+            client,
+            model,
+            expected_dimension,
+            batch_size,
+            max_request_tokens,
+            max_input_tokens,
+            rate_limiter: None,
+        })
     }
-    
+
+    /// Wait for `rate_limiter` before every request to the API, so each batch
+    /// and each retry counts as one request.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Embed `texts`, returning exactly one vector per input, in input order.
+    ///
+    /// The inputs are sent in sequential requests of at most the batch size
+    /// and the request token limit (by approximate count), since the API limits
+    /// how many inputs and tokens one request may carry. An input estimated to
+    /// be over the model's per-input token limit fails the call up front, with
+    /// an error naming it, before any request is sent.
+    ///
+    /// Vectors are placed by the `index` the API reports rather than by response
+    /// order. If the API drops an input (e.g. on a content-filter hit), that input
+    /// is retried on its own; if it still gets no vector, an error naming the
+    /// input is returned instead of a shorter, misaligned result. Vectors of a
+    /// size other than the expected dimension are also an error.
     pub async fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
         println!("Embedding {} texts using OpenAI API", texts.len());
-        
+
+        if let Some(limit) = self.max_input_tokens
+            && let Some(&(i, tokens)) = over_long_inputs(texts, limit).first()
+        {
+            return Err(format!(
+                "input {} has about {} tokens, more than the model's limit of {}; \
+                 split it into smaller chunks first",
+                i, tokens, limit
+            )
+            .into());
+        }
+
+        let token_counts: Vec<usize> = texts.iter().map(|text| approx_token_count(text)).collect();
+        let mut slots: Vec<Option<Vec<f32>>> = vec![None; texts.len()];
+        for range in batch_ranges(&token_counts, self.batch_size, self.max_request_tokens) {
+            let offset = range.start;
+            let batch = &texts[range];
+            let results = self.request_embeddings(batch).await?;
+            place_batch(&mut slots, offset, batch.len(), results);
+        }
+
+        for (i, slot) in slots.iter_mut().enumerate() {
+            if slot.is_some() {
+                continue;
+            }
+            println!("No embedding returned for input {}; retrying it alone", i);
+            let retried = self.request_embeddings(&texts[i..=i]).await?;
+            match retried.into_iter().find(|(index, _)| *index == 0) {
+                Some((_, embedding)) => *slot = Some(embedding),
+                None => {
+                    let preview: String = texts[i].chars().take(60).collect();
+                    return Err(format!(
+                        "embeddings API returned no vector for input {} (\"{}\")",
+                        i, preview
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let embeddings: Vec<Vec<f32>> = slots.into_iter().flatten().collect();
+
+        if let Some(expected) = self.expected_dimension
+            && let Some(wrong) = embeddings.iter().find(|v| v.len() != expected)
+        {
+            return Err(format!(
+                "model '{}' returned {}-dimensional embeddings, but {} dimensions are expected; \
+                 set EMBEDDING_DIM to the model's size and use a collection built with it",
+                self.model,
+                wrong.len(),
+                expected
+            )
+            .into());
+        }
+
+        println!("Successfully created {} embeddings of dimension {}", 
+                embeddings.len(), 
+                embeddings.first().map_or(0, |v| v.len()));
+        Ok(embeddings)
+    }
+
+    /// Send one embeddings request and return `(input index, vector)` pairs.
+    async fn request_embeddings(&self, texts: &[&str]) -> Result<Vec<(usize, Vec<f32>)>, Box<dyn Error>> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
         let request = CreateEmbeddingRequestArgs::default()
-            .model("text-embedding-3-small")
+            .model(self.model.as_str())
             .input(texts.to_vec())
             .build()?;
-            
+
         let response = self.client.embeddings().create(request).await?;
-        
-        let embeddings: Vec<Vec<f32>> = response.data
+
+        Ok(response.data
             .into_iter()
-            .map(|embedding| embedding.embedding)
-            .collect();
-            
-        println!("Successfully created {} embeddings of dimension {}", 
-                embeddings.len(), 
-                embeddings.first().map_or(0, |v| v.len()));
-        Ok(embeddings)
+            .map(|embedding| (embedding.index as usize, embedding.embedding))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_are_placed_in_input_order() {
+        let texts = ["a", "b", "c", "d", "e"];
+        let batch_size = 2;
+        let mut slots = vec![None; texts.len()];
+        for (batch_no, batch) in texts.chunks(batch_size).enumerate() {
+            // Each request answers out of order, numbering its inputs from 0
+            let results = (0..batch.len())
+                .rev()
+                .map(|index| (index, vec![(batch_no * batch_size + index) as f32]))
+                .collect();
+            place_batch(&mut slots, batch_no * batch_size, batch.len(), results);
+        }
+        let placed: Vec<f32> = slots.into_iter().map(|slot| slot.unwrap()[0]).collect();
+        assert_eq!(placed, [0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn indices_outside_the_batch_are_ignored() {
+        let mut slots = vec![None; 4];
+        place_batch(&mut slots, 0, 2, vec![(0, vec![1.0]), (2, vec![9.0])]);
+        assert_eq!(slots, [Some(vec![1.0]), None, None, None]);
+    }
+
+    #[test]
+    fn batches_close_at_the_input_count_or_the_token_total() {
+        assert_eq!(batch_ranges(&[1, 1, 1, 1, 1], 2, 100), [0..2, 2..4, 4..5]);
+        assert_eq!(batch_ranges(&[40, 40, 40, 10], 10, 100), [0..2, 2..4]);
+        // An input over the request limit on its own is still sent, alone
+        assert_eq!(batch_ranges(&[10, 150, 10], 10, 100), [0..1, 1..2, 2..3]);
+        assert!(batch_ranges(&[], 10, 100).is_empty());
+    }
+
+    #[test]
+    fn only_inputs_over_the_limit_are_flagged() {
+        let long = "word ".repeat(20);
+        let texts = ["short text", long.as_str(), "also short"];
+        assert_eq!(over_long_inputs(&texts, 10), [(1, 27)]);
+        assert!(over_long_inputs(&texts, 100).is_empty());
     }
 }
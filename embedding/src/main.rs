@@ -1,20 +1,36 @@
 mod embeddings;
+mod ratelimit;
 
 use embeddings::SentenceEmbedder;
 use ndarray::Array1;
+use ratelimit::RateLimiter;
+use std::env;
 use std::error::Error;
 
 /// Compute cosine similarity between two vectors.
-fn cosine_similarity(vec_a: &Array1<f32>, vec_b: &Array1<f32>) -> f32 {
-    let dot = vec_a.dot(vec_b);
+///
+/// Returns an error if the vectors have different lengths. The similarity
+/// involving a zero vector is defined as 0.0.
+fn cosine_similarity(vec_a: &Array1<f32>, vec_b: &Array1<f32>) -> Result<f32, Box<dyn Error>> {
+    if vec_a.len() != vec_b.len() {
+        return Err(format!("dimension mismatch: {} vs {}", vec_a.len(), vec_b.len()).into());
+    }
     let norm_a = vec_a.dot(vec_a).sqrt();
     let norm_b = vec_b.dot(vec_b).sqrt();
-    dot / (norm_a * norm_b)
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    let dot = vec_a.dot(vec_b);
+    Ok(dot / (norm_a * norm_b))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let embedder = SentenceEmbedder::new().await?;
+    let mut embedder = SentenceEmbedder::new().await?;
+    // REQUESTS_PER_MINUTE=<n> spaces out the embedding requests
+    if let Some(requests_per_minute) = env::var("REQUESTS_PER_MINUTE").ok().and_then(|v| v.parse().ok()) {
+        embedder = embedder.with_rate_limiter(RateLimiter::new(requests_per_minute));
+    }
 
     // Example sentences
     let sentences = vec![
@@ -40,7 +56,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         let similarity = cosine_similarity(
             &Array1::from(embedding.clone()),
             &Array1::from(query_embedding.clone()),
-        );
+        )?;
         similarities.push((i, similarity, sentences[i]));
     }
 
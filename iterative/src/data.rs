@@ -13,7 +13,9 @@ pub struct Chunk {
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Document {
-    id: usize,
+    /// Explicit document id; without one, loaders number it after the largest
+    /// explicit id (see `assign_doc_ids`).
+    id: Option<usize>,
     content: String,
     category: Option<String>,
 }
@@ -23,15 +25,34 @@ pub fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
     let words: Vec<&str> = text.split_whitespace().collect();
     let mut chunks = Vec::new();
 
-    for i in (0..words.len()).step_by(chunk_size) {
-        let end = (i + chunk_size).min(words.len());
-        let chunk = words[i..end].join(" ");
-        chunks.push(chunk);
+    // A chunk size of 0 is treated as 1 so every word still lands in a chunk
+    for group in words.chunks(chunk_size.max(1)) {
+        chunks.push(group.join(" "));
     }
 
     chunks
 }
 
+/// The id of each document, in file order: its explicit `id`, or for a
+/// document without one the next number after the largest explicit id, so a
+/// fallback id never collides with an explicit one.
+fn assign_doc_ids(documents: &[Document]) -> Vec<usize> {
+    let mut next_id = documents
+        .iter()
+        .filter_map(|doc| doc.id)
+        .max()
+        .map_or(0, |max| max + 1);
+    documents
+        .iter()
+        .map(|doc| {
+            doc.id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        })
+        .collect()
+}
+
 /// Loads a dataset from JSON file_path, then splits each document into smaller chunks.
 pub fn load_and_chunk_dataset(
     file_path: &str,
@@ -43,9 +64,9 @@ pub fn load_and_chunk_dataset(
 
     let mut all_chunks = Vec::new();
 
-    for doc in documents {
+    let doc_ids = assign_doc_ids(&documents);
+    for (doc, doc_id) in documents.into_iter().zip(doc_ids) {
         let doc_category = doc.category.unwrap_or_else(|| "general".to_string());
-        let doc_id = doc.id;
         let doc_chunks = chunk_text(&doc.content, chunk_size);
 
         for (chunk_id, chunk_str) in doc_chunks.into_iter().enumerate() {
@@ -60,3 +81,21 @@ pub fn load_and_chunk_dataset(
 
     Ok(all_chunks)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_ids_follow_the_largest_explicit_id() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": 5, "content": "a"}, {"content": "b"}, {"id": 2, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&documents), [5, 6, 2, 7]);
+
+        let documents: Vec<Document> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&documents), [0, 1]);
+    }
+}
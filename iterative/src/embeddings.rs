@@ -1,5 +1,6 @@
 use rust_bert::pipelines::sentence_embeddings::{SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType};
 use std::error::Error;
+use tracing::{debug, info};
 
 pub struct SentenceEmbedder {
     model: rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel,
@@ -7,7 +8,7 @@ pub struct SentenceEmbedder {
 
 impl SentenceEmbedder {
     pub async fn new() -> Result<Self, Box<dyn Error>> {
-        println!("Loading sentence embedding model (all-MiniLM-L6-v2)...");
+        info!("Loading sentence embedding model (all-MiniLM-L6-v2)...");
         let model = tokio::task::spawn_blocking(|| {
             SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
                 .create_model()
@@ -17,9 +18,9 @@ impl SentenceEmbedder {
     }
     
     pub fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
-        println!("Embedding {} texts", texts.len());
+        debug!("Embedding {} texts", texts.len());
         let embeddings = self.model.encode(texts)?;
-        println!("Successfully created {} embeddings of dimension {}", 
+        debug!("Successfully created {} embeddings of dimension {}", 
                 embeddings.len(), 
                 embeddings.first().map_or(0, |v| v.len()));
         Ok(embeddings)
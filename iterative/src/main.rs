@@ -1,34 +1,42 @@
 
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 mod retrieval;
 mod llm;
 
 use std::env;
 use std::error::Error;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 
 
 use data::{load_and_chunk_dataset, Chunk};
 use embeddings::SentenceEmbedder;
-use retrieval::{build_final_context, iterative_retrieval};
+use retrieval::{build_final_context, iterative_retrieval, ContextOptions, ContextOrder};
 use vector_db::build_chroma_collection;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // RUST_LOG=debug shows retrieval internals; by default only warnings are logged
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")))
+        .init();
+
     // Load full documents
     let dataset_file = env::current_dir()?.join("data").join("corpus.json");
-    println!("Loading data from: {}", dataset_file.display());
+    info!("Loading data from: {}", dataset_file.display());
     let docs: Vec<Chunk> = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 50)?;
 
     // Build collection
     let embedder = SentenceEmbedder::new().await?;
     let collection = build_chroma_collection(&docs, "iterative_collection", &embedder).await?;
-    println!("ChromaDB collection created with {} documents.", collection.count().await?);
+    info!("ChromaDB collection created with {} documents.", collection.count().await?);
 
     // Iterative retrieval demo
     let initial_query = "What internal policies apply specifically to employees?";
-    let iter_results = iterative_retrieval(
+    let (iter_results, stop_reason) = iterative_retrieval(
         &collection,
         &embedder,
         initial_query,
@@ -36,9 +44,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         /*improvement_threshold=*/0.02,
         /*max_chunks=*/3,
     ).await?;
+    println!("Iterative retrieval stopped: {:?}", stop_reason);
 
     // Build and print final context
-    let final_context = build_final_context(&iter_results);
+    // CONTEXT_ORDER=score puts the best iterations first and shows their weights;
+    // CONTEXT_MAX_TOKENS drops the weakest iterations until the context fits
+    let score_ordered = env::var("CONTEXT_ORDER").as_deref() == Ok("score");
+    let options = ContextOptions {
+        order: if score_ordered { ContextOrder::Score } else { ContextOrder::Step },
+        annotate_weights: score_ordered,
+        max_tokens: env::var("CONTEXT_MAX_TOKENS").ok().and_then(|v| v.parse().ok()),
+    };
+    let final_context = build_final_context(&iter_results, &options);
     println!("\nFinal combined context:\n{}", final_context);
     Ok(())
 }
@@ -2,6 +2,8 @@ use serde_json::Value;
 use crate::vector_db::retrieve_best_chunk;
 use crate::embeddings::SentenceEmbedder;
 use chromadb::collection::ChromaCollection;
+use std::collections::HashSet;
+use tracing::{debug, info, instrument};
 
 /// A small set of English stopwords.
 const STOPWORDS: &[&str] = &[
@@ -24,7 +26,7 @@ pub fn extract_refinement_keywords(chunk_text: &str, current_query: &str) -> Vec
         .filter(|w: &String| !w.is_empty())
         .collect();
 
-    let query_words: std::collections::HashSet<String> = query_lower
+    let query_words: HashSet<String> = query_lower
         .split_whitespace()
         .map(|w| w.chars().filter(|c| c.is_alphanumeric()).collect())
         .filter(|w: &String| !w.is_empty())
@@ -39,6 +41,46 @@ pub fn extract_refinement_keywords(chunk_text: &str, current_query: &str) -> Vec
     candidates.into_iter().take(2).collect()
 }
 
+/// Characters shown for a retrieved chunk in the trace.
+const SNIPPET_CHARS: usize = 80;
+
+/// Lowercase `word` and keep only its letters and digits, as keywords are normalized.
+fn normalize_word(word: &str) -> String {
+    word.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// A window of about `width` characters of `text` centered on the first
+/// occurrence of a keyword, with "…" marking cut ends.
+///
+/// `keywords` are tried in order, so earlier ones take priority. Falls back to
+/// the leading `width` characters if none occurs.
+pub fn focus_snippet(text: &str, keywords: &[String], width: usize) -> String {
+    let hit = keywords.iter().find_map(|keyword| {
+        text.split_whitespace()
+            .find(|word| normalize_word(word) == *keyword)
+    });
+    // Byte offset of the matched word within `text`
+    let center = hit.map_or(0, |word| word.as_ptr() as usize - text.as_ptr() as usize);
+
+    let start = text[..center]
+        .char_indices()
+        .rev()
+        .take(width / 2)
+        .last()
+        .map_or(center, |(i, _)| i);
+    let window: String = text[start..].chars().take(width).collect();
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(window.trim());
+    if start + window.len() < text.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
 /// Append multiple keywords to the `current_query`, if non-empty.
 pub fn refine_query(current_query: &str, refine_words: &[String]) -> String {
     if refine_words.is_empty() {
@@ -53,13 +95,36 @@ pub fn refine_query(current_query: &str, refine_words: &[String]) -> String {
 pub struct IterationResult {
     pub step: usize,
     pub query: String,
+    pub chunk_id: String,
     pub retrieved_text: String,
     pub metadata: Value,
     pub score: f32,
 }
 
+/// Why `iterative_retrieval` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// All requested steps were run.
+    StepsCompleted,
+    /// The collection returned no chunks at all.
+    NoResults,
+    /// Every remaining candidate had already been retrieved in an earlier step.
+    Exhausted,
+    /// The refined query did not improve the score enough.
+    ThresholdNotMet,
+    /// `max_chunks` results were collected.
+    MaxChunks,
+    /// The last chunk offered no keywords to refine the query with.
+    NoKeywords,
+}
+
 /// Perform up to `steps` rounds of retrieve→extract keywords→refine.
 /// Stops if the number of retrieved chunks reaches `max_chunks`.
+///
+/// Chunks retrieved in earlier steps are excluded from later ones, so every
+/// step surfaces a new chunk; the reason for stopping is returned alongside
+/// the results.
+#[instrument(skip_all, fields(query = %initial_query, steps = steps, max_chunks = max_chunks))]
 pub async fn iterative_retrieval(
     collection: &ChromaCollection,
     embedder: &SentenceEmbedder,
@@ -67,33 +132,56 @@ pub async fn iterative_retrieval(
     steps: usize,
     improvement_threshold: f32,
     max_chunks: usize,
-) -> Result<Vec<IterationResult>, Box<dyn std::error::Error>> {
+) -> Result<(Vec<IterationResult>, StopReason), Box<dyn std::error::Error>> {
     let mut results = Vec::new();
     let mut current_query = initial_query.to_string();
     let mut best_score = 0.0;
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    let mut stop_reason = StopReason::StepsCompleted;
+
+    // Embed the initial query once; afterwards only refined queries are embedded.
+    let mut query_embedding = embedder.embed_texts(&[initial_query])?.remove(0);
+
+    // Words the trace snippet centers on: the latest refinement keywords
+    // first, then the content words of the initial query
+    let query_words: Vec<String> = initial_query
+        .split_whitespace()
+        .map(normalize_word)
+        .filter(|w| !w.is_empty() && !STOPWORDS.contains(&w.as_str()))
+        .collect();
+    let mut focus_words = query_words.clone();
 
     for step in 1..=steps {
-        println!("Iteration {}, current query: '{}'", step, current_query);
-        let opt = retrieve_best_chunk(collection, embedder, &current_query, 1).await?;
-        let (text, score, metadata) = match opt {
+        info!(step, query = %current_query, "iteration started");
+        let opt = retrieve_best_chunk(collection, embedder, query_embedding.as_slice(), 1, &seen_ids).await?;
+        let (chunk_id, text, score, metadata) = match opt {
             Some(t) => t,
+            None if seen_ids.is_empty() => {
+                info!(step, "No chunks found at this step. Ending.");
+                stop_reason = StopReason::NoResults;
+                break;
+            }
             None => {
-                println!("No chunks found at this step. Ending.");
+                info!(step, "No unseen chunks left to retrieve. Ending.");
+                stop_reason = StopReason::Exhausted;
                 break;
             }
         };
 
-        println!("Best chunk (50 chars): '{}' | Score: {:.4}", &text[..text.len().min(50)], score);
+        debug!(step, id = %chunk_id, score, best_score, "best chunk: '{}'", focus_snippet(&text, &focus_words, SNIPPET_CHARS));
 
         if score - best_score < improvement_threshold {
-            println!("Improvement threshold not met. Stopping.");
+            info!(step, "Improvement threshold not met. Stopping.");
+            stop_reason = StopReason::ThresholdNotMet;
             break;
         }
         best_score = score;
+        seen_ids.insert(chunk_id.clone());
 
         results.push(IterationResult {
             step,
             query: current_query.clone(),
+            chunk_id,
             retrieved_text: text.clone(),
             metadata,
             score,
@@ -101,29 +189,100 @@ pub async fn iterative_retrieval(
 
         // Stop if we've hit the max_chunks limit
         if results.len() >= max_chunks {
-            println!("Reached maximum number of chunks ({}). Stopping.", max_chunks);
+            info!(step, "Reached maximum number of chunks ({}). Stopping.", max_chunks);
+            stop_reason = StopReason::MaxChunks;
             break;
         }
 
         let keywords = extract_refinement_keywords(&text, &current_query);
         if keywords.is_empty() {
-            println!("No suitable keywords for further refinement.");
+            info!(step, "No suitable keywords for further refinement.");
+            stop_reason = StopReason::NoKeywords;
             break;
         }
-        println!("Refining query with keywords: {:?}", keywords);
+        debug!(step, ?keywords, "refining query");
         current_query = refine_query(&current_query, &keywords);
+        focus_words = keywords.into_iter().chain(query_words.iter().cloned()).collect();
+        query_embedding = embedder.embed_texts(&[current_query.as_str()])?.remove(0);
     }
 
-    Ok(results)
+    Ok((results, stop_reason))
 }
 
-/// Combine all iteration texts into one bullet-list context.
-pub fn build_final_context(results: &[IterationResult]) -> String {
+/// Order of the iterations in the final context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContextOrder {
+    /// In retrieval order, step by step.
+    #[default]
+    Step,
+    /// Highest score first; later, more refined steps are often more on-target.
+    Score,
+}
+
+/// How `build_final_context` assembles the iteration results.
+#[derive(Debug, Clone, Default)]
+pub struct ContextOptions {
+    pub order: ContextOrder,
+    /// Annotate each entry with its share of the total score.
+    pub annotate_weights: bool,
+    /// Approximate token budget for the whole context. Iterations are dropped,
+    /// lowest score first, until it fits; the best one is always kept.
+    pub max_tokens: Option<usize>,
+}
+
+/// Approximate the token count of `text` (1 token ≈ 0.75 words).
+fn approx_token_count(text: &str) -> usize {
+    let word_count = text.split_whitespace().count();
+    (word_count as f32 / 0.75).ceil() as usize
+}
+
+/// Combine the iteration texts into one bullet-list context.
+///
+/// `ContextOptions::default()` lists every iteration in step order.
+pub fn build_final_context(results: &[IterationResult], options: &ContextOptions) -> String {
     if results.is_empty() {
         return "No relevant information was found after iterative retrieval.".to_string();
     }
-    results.iter()
-        .map(|r| format!("- Step {} (Score={:.4}): {}", r.step, r.score, r.retrieved_text))
+
+    let total_score: f32 = results.iter().map(|r| r.score).sum();
+    let format_entry = |r: &IterationResult| {
+        if options.annotate_weights && total_score > 0.0 {
+            format!(
+                "- Step {} (Score={:.4}, weight {:.0}%): {}",
+                r.step,
+                r.score,
+                100.0 * r.score / total_score,
+                r.retrieved_text
+            )
+        } else {
+            format!("- Step {} (Score={:.4}): {}", r.step, r.score, r.retrieved_text)
+        }
+    };
+
+    // Iterations ranked best first, so the budget drops from the end
+    let mut kept: Vec<&IterationResult> = results.iter().collect();
+    kept.sort_by(|a, b| b.score.total_cmp(&a.score));
+    if let Some(budget) = options.max_tokens {
+        let mut used = 0;
+        let mut fitting = 0;
+        for r in &kept {
+            // +1 for the newline joining the entries
+            let cost = approx_token_count(&format_entry(r)) + 1;
+            if fitting > 0 && used + cost > budget {
+                break;
+            }
+            used += cost;
+            fitting += 1;
+        }
+        kept.truncate(fitting);
+    }
+
+    if options.order == ContextOrder::Step {
+        kept.sort_by_key(|r| r.step);
+    }
+
+    kept.into_iter()
+        .map(format_entry)
         .collect::<Vec<_>>()
         .join("\n")
 }
@@ -1,22 +1,98 @@
+use std::collections::HashSet;
 use std::error::Error;
 use crate::data::Chunk;
 use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
 use chromadb::collection::{ChromaCollection, CollectionEntries, QueryOptions};
 use serde_json::{json, Value};
 
-/// Returns `(chunk_text, inverted_score, metadata)` for the top match, or `None` if no documents.
-pub async fn retrieve_best_chunk(
+/// Distance function of a Chroma collection (its `hnsw:space` setting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Squared Euclidean distance, Chroma's default.
+    L2,
+    /// Cosine distance, `1 - cos(a, b)`.
+    Cosine,
+    /// Inner-product distance, `1 - a·b`.
+    InnerProduct,
+}
+
+impl DistanceMetric {
+    /// Read the metric from the collection's metadata, defaulting to L2 as Chroma does.
+    pub fn of_collection(collection: &ChromaCollection) -> Self {
+        match collection
+            .metadata()
+            .and_then(|meta| meta.get("hnsw:space"))
+            .and_then(|space| space.as_str())
+        {
+            Some("cosine") => DistanceMetric::Cosine,
+            Some("ip") => DistanceMetric::InnerProduct,
+            _ => DistanceMetric::L2,
+        }
+    }
+}
+
+/// Convert a Chroma distance to a similarity where higher means more similar.
+///
+/// Cosine and inner-product distances are `1 - similarity`, so they are
+/// inverted directly. L2 distances are unbounded, so they map to
+/// `1 / (1 + d)` in (0, 1] instead of going negative past a distance of 1.
+pub fn distance_to_similarity(distance: f32, metric: DistanceMetric) -> f32 {
+    match metric {
+        DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+        DistanceMetric::L2 => 1.0 / (1.0 + distance),
+    }
+}
+
+/// A query given either as raw text or as an already computed embedding.
+pub enum QueryInput<'a> {
+    Text(&'a str),
+    Embedding(&'a [f32]),
+}
+
+impl<'a> From<&'a str> for QueryInput<'a> {
+    fn from(text: &'a str) -> Self {
+        QueryInput::Text(text)
+    }
+}
+
+impl<'a> From<&'a [f32]> for QueryInput<'a> {
+    fn from(embedding: &'a [f32]) -> Self {
+        QueryInput::Embedding(embedding)
+    }
+}
+
+impl QueryInput<'_> {
+    /// Return the query embedding, embedding the text only when needed.
+    pub fn to_embedding(&self, embedder: &SentenceEmbedder) -> Result<Vec<f32>, Box<dyn Error>> {
+        match self {
+            QueryInput::Text(text) => Ok(embedder.embed_texts(&[text])?.remove(0)),
+            QueryInput::Embedding(embedding) => Ok(embedding.to_vec()),
+        }
+    }
+}
+
+/// Returns `(chunk_id, chunk_text, similarity, metadata)` for the top match, or `None` if no documents.
+///
+/// The similarity comes from `distance_to_similarity` for the collection's metric.
+///
+/// Chunks whose Chroma ID is in `exclude_ids` are skipped; the query over-fetches
+/// by the number of excluded IDs so a fresh chunk can still be returned. A
+/// result Chroma returned without an ID or a distance is skipped too, rather
+/// than scored as a perfect match.
+pub async fn retrieve_best_chunk<'a>(
     collection: &ChromaCollection,
     embedder: &SentenceEmbedder,
-    query: &str,
+    query: impl Into<QueryInput<'a>>,
     n_results: usize,
-) -> Result<Option<(String, f32, Value)>, Box<dyn Error>> {
-    let query_embeddings = embedder.embed_texts(&[query])?;
+    exclude_ids: &HashSet<String>,
+) -> Result<Option<(String, String, f32, Value)>, Box<dyn Error>> {
+    let query_embedding = query.into().to_embedding(embedder)?;
     let opts = QueryOptions {
         query_texts: None,
-        query_embeddings: Some(query_embeddings),
-        n_results: Some(n_results),
+        query_embeddings: Some(vec![query_embedding]),
+        n_results: Some(n_results + exclude_ids.len()),
         where_metadata: None,
         where_document: None,
         include: Some(vec!["documents".into(), "distances".into(), "metadatas".into()]),
@@ -29,36 +105,69 @@ pub async fn retrieve_best_chunk(
     let docs = res
         .documents
         .as_ref()
-        .and_then(|groups| groups.get(0))
+        .and_then(|groups| groups.first())
         .unwrap_or(&empty_vec);
+    let ids = res.ids.first().unwrap_or(&empty_vec);
+    let distance_at = |i: usize| -> Option<f32> {
+        res.distances
+            .as_ref()
+            .and_then(|groups| groups.first())
+            .and_then(|row| row.get(i))
+            .copied()
+    };
 
-    if docs.is_empty() {
+    // Pick the best-ranked chunk that hasn't been seen yet; a result without an
+    // id could not be excluded later, and one without a distance cannot be
+    // scored, so both are skipped
+    let Some((i, distance)) = (0..docs.len())
+        .filter(|&i| ids.get(i).is_some_and(|id| !exclude_ids.contains(id)))
+        .find_map(|i| distance_at(i).map(|distance| (i, distance)))
+    else {
         return Ok(None);
-    }
+    };
 
-    let text = docs[0].clone();
-    let distance = res
-        .distances
-        .as_ref()
-        .and_then(|groups| groups.get(0))
-        .and_then(|row| row.get(0))
-        .copied()
-        .unwrap_or(0.0);
+    let id = ids[i].clone();
+    let text = docs[i].clone();
 
-    // Inverted-distance similarity score formula.
-    // Use 1.0 / (1.0 + distance) to convert distance to similarity.
-    let score = 1.0 / (1.0 + distance);
+    // Score on the scale of the collection's metric, so improvement thresholds mean the same thing
+    let score = distance_to_similarity(distance, DistanceMetric::of_collection(collection));
 
     // Handle metadata conversion
     let metadata = res
         .metadatas
         .as_ref()
-        .and_then(|groups| groups.get(0))
-        .and_then(|row| row.get(0))
+        .and_then(|groups| groups.first())
+        .and_then(|row| row.get(i))
         .and_then(|m| serde_json::to_value(m).ok())
         .unwrap_or(Value::Null);
 
-    Ok(Some((text, score, metadata)))
+    Ok(Some((id, text, score, metadata)))
+}
+
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
 }
 
 /// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
@@ -67,7 +176,7 @@ pub async fn build_chroma_collection(
     collection_name: &str,
     embedder: &SentenceEmbedder,
 ) -> Result<ChromaCollection, Box<dyn Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    let client = connect_chroma().await?;
     let collection = client.get_or_create_collection(collection_name, None).await?;
 
     // Skip empty collection
@@ -0,0 +1,48 @@
+//! Compares `top_k_by_score` with sorting every score, at the corpus size the
+//! heap was introduced for (n = 100k, k = 10).
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+
+#[path = "../src/topk.rs"]
+#[allow(unused_imports)] // its unit tests are compiled but not run here
+mod topk;
+
+use topk::top_k_by_score;
+
+const N: usize = 100_000;
+const K: usize = 10;
+
+/// Deterministic pseudo-random scores in [0, 1).
+fn scores() -> Vec<f32> {
+    let mut state: u32 = 0x9e37_79b9;
+    (0..N)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 8) as f32 / (1u32 << 24) as f32
+        })
+        .collect()
+}
+
+fn sort_top_k(scores: &[f32], k: usize) -> Vec<(usize, f32)> {
+    let mut ranked: Vec<(usize, f32)> = scores.iter().copied().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(k);
+    ranked
+}
+
+fn bench_top_k(c: &mut Criterion) {
+    let scores = scores();
+    let mut group = c.benchmark_group("top_k n=100k k=10");
+    group.bench_function("heap", |b| {
+        b.iter(|| top_k_by_score(black_box(&scores).iter().copied().enumerate(), K))
+    });
+    group.bench_function("full sort", |b| {
+        b.iter(|| sort_top_k(black_box(&scores), K))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_top_k);
+criterion_main!(benches);
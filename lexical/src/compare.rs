@@ -0,0 +1,215 @@
+use crate::data::Chunk;
+use crate::embeddings::Embedder;
+use crate::fusion::MergeMethod;
+use crate::hybrid::{Bm25Index, hybrid_retrieval};
+use crate::topk::top_k_by_score;
+use crate::vector_db::chroma_id;
+use chromadb::collection::{ChromaCollection, QueryOptions};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// BM25 weight used for the hybrid column: an even mix of both retrievers.
+const HYBRID_ALPHA: f32 = 0.5;
+
+/// Top-k chunk ids of each retriever for one query, side by side.
+#[derive(Debug, Clone)]
+pub struct ComparisonTable {
+    pub query: String,
+    /// Retriever name and its ranked chunk ids, best first.
+    pub columns: Vec<(String, Vec<String>)>,
+}
+
+impl ComparisonTable {
+    /// Ids ranked by every retriever.
+    pub fn agreed(&self) -> Vec<&str> {
+        let Some((_, first)) = self.columns.first() else {
+            return Vec::new();
+        };
+        first
+            .iter()
+            .filter(|id| self.columns.iter().all(|(_, ids)| ids.contains(id)))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// How many retrievers ranked `id`.
+    fn votes(&self, id: &str) -> usize {
+        self.columns
+            .iter()
+            .filter(|(_, ids)| ids.iter().any(|other| other == id))
+            .count()
+    }
+}
+
+/// Prints one row per rank. An id ranked by every retriever is marked `=`,
+/// an id only one retriever found is marked `!`.
+impl fmt::Display for ComparisonTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WIDTH: usize = 20;
+        writeln!(f, "Query: {}", self.query)?;
+
+        write!(f, "{:<6}", "rank")?;
+        for (name, _) in &self.columns {
+            write!(f, "{:<WIDTH$}", name)?;
+        }
+        writeln!(f)?;
+
+        let depth = self
+            .columns
+            .iter()
+            .map(|(_, ids)| ids.len())
+            .max()
+            .unwrap_or(0);
+        for rank in 0..depth {
+            write!(f, "{:<6}", rank + 1)?;
+            for (_, ids) in &self.columns {
+                let cell = match ids.get(rank) {
+                    Some(id) => {
+                        let votes = self.votes(id);
+                        let mark = if votes == self.columns.len() {
+                            "="
+                        } else if votes == 1 {
+                            "!"
+                        } else {
+                            " "
+                        };
+                        format!("{}{}", mark, id)
+                    }
+                    None => " -".to_string(),
+                };
+                write!(f, "{:<WIDTH$}", cell)?;
+            }
+            writeln!(f)?;
+        }
+
+        let agreed = self.agreed();
+        write!(
+            f,
+            "Agreed by all: {}",
+            if agreed.is_empty() {
+                "none".to_string()
+            } else {
+                agreed.join(", ")
+            }
+        )
+    }
+}
+
+/// Run `query` through the bag-of-words, BM25, dense and hybrid retrievers
+/// over the same chunks and collect each one's `top_k` chunk ids.
+///
+/// The bag-of-words column ranks by cosine similarity of raw word counts, as
+/// in the `bow` crate; the hybrid column fuses BM25 and dense scores with equal
+/// weight and no score floor.
+pub async fn compare_retrievers(
+    query: &str,
+    chunks: &[Chunk],
+    collection: &ChromaCollection,
+    bm25: &Bm25Index,
+    embedder: &dyn Embedder,
+    top_k: usize,
+) -> Result<ComparisonTable, Box<dyn Error>> {
+    let ids_of = |indices: Vec<usize>| -> Vec<String> {
+        indices
+            .into_iter()
+            .map(|idx| chroma_id(&chunks[idx]))
+            .collect()
+    };
+
+    let bow = ids_of(top_indices(&bow_scores(query, chunks), top_k));
+    let lexical = ids_of(top_indices(&bm25.score(query), top_k));
+    let dense = dense_ids(query, collection, embedder, top_k).await?;
+    let hybrid = hybrid_retrieval(
+        query,
+        chunks,
+        bm25,
+        collection,
+        top_k,
+        HYBRID_ALPHA,
+        None,
+        MergeMethod::WeightedSum,
+        0,
+        embedder,
+    )
+    .await?
+    .into_iter()
+    .map(|result| result.id)
+    .collect();
+
+    Ok(ComparisonTable {
+        query: query.to_string(),
+        columns: vec![
+            ("BOW".to_string(), bow),
+            ("BM25".to_string(), lexical),
+            ("dense".to_string(), dense),
+            ("hybrid".to_string(), hybrid),
+        ],
+    })
+}
+
+/// Lowercased words with surrounding punctuation removed, counted.
+fn word_counts(text: &str) -> HashMap<String, f32> {
+    let mut counts = HashMap::new();
+    for word in text.to_lowercase().split_whitespace() {
+        let clean = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if !clean.is_empty() {
+            *counts.entry(clean.to_string()).or_insert(0.0) += 1.0;
+        }
+    }
+    counts
+}
+
+/// Cosine similarity of word-count vectors between the query and every chunk.
+fn bow_scores(query: &str, chunks: &[Chunk]) -> Vec<f32> {
+    let q = word_counts(query);
+    let q_norm = q.values().map(|v| v * v).sum::<f32>().sqrt();
+    chunks
+        .iter()
+        .map(|chunk| {
+            let d = word_counts(&chunk.text);
+            let d_norm = d.values().map(|v| v * v).sum::<f32>().sqrt();
+            let dot: f32 = q
+                .iter()
+                .filter_map(|(w, qv)| d.get(w).map(|dv| qv * dv))
+                .sum();
+            if q_norm == 0.0 || d_norm == 0.0 {
+                0.0
+            } else {
+                dot / (q_norm * d_norm)
+            }
+        })
+        .collect()
+}
+
+/// Indices of the `top_k` highest positive scores, best first.
+fn top_indices(scores: &[f32], top_k: usize) -> Vec<usize> {
+    let positive = scores
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|&(_, score)| score > 0.0);
+    top_k_by_score(positive, top_k)
+        .into_iter()
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Chroma ids of the `top_k` nearest chunks.
+async fn dense_ids(
+    query: &str,
+    collection: &ChromaCollection,
+    embedder: &dyn Embedder,
+    top_k: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let opts = QueryOptions {
+        query_texts: None,
+        query_embeddings: Some(embedder.embed_texts(&[query])?),
+        n_results: Some(top_k),
+        where_metadata: None,
+        where_document: None,
+        include: Some(vec!["distances"]),
+    };
+    let res = collection.query(opts, None).await?;
+    Ok(res.ids.into_iter().next().unwrap_or_default())
+}
@@ -2,6 +2,15 @@ use rust_bert::pipelines::sentence_embeddings::{
     SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
 };
 use std::error::Error;
+use tracing::{debug, info};
+
+/// Turns texts into dense vectors, one per input text.
+///
+/// `SentenceEmbedder` runs a sentence-transformer model; `HashEmbedder` works
+/// offline and deterministically, for tests and demos without the model.
+pub trait Embedder {
+    fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>>;
+}
 
 pub struct SentenceEmbedder {
     model: rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel,
@@ -9,7 +18,7 @@ pub struct SentenceEmbedder {
 
 impl SentenceEmbedder {
     pub async fn new() -> Result<Self, Box<dyn Error>> {
-        println!("Loading sentence embedding model (all-MiniLM-L6-v2)...");
+        info!("Loading sentence embedding model (all-MiniLM-L6-v2)...");
         let model = tokio::task::spawn_blocking(|| {
             SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
                 .create_model()
@@ -18,11 +27,13 @@ impl SentenceEmbedder {
 
         Ok(Self { model })
     }
+}
 
-    pub fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
-        println!("Embedding {} texts", texts.len());
+impl Embedder for SentenceEmbedder {
+    fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+        debug!("Embedding {} texts", texts.len());
         let embeddings = self.model.encode(texts)?;
-        println!(
+        debug!(
             "Successfully created {} embeddings of dimension {}",
             embeddings.len(),
             embeddings.first().map_or(0, |v| v.len())
@@ -30,3 +41,94 @@ impl SentenceEmbedder {
         Ok(embeddings)
     }
 }
+
+/// Deterministic, offline embedder: a hashed bag of words.
+///
+/// Each lowercased alphanumeric word is hashed (FNV-1a) to one of `dim`
+/// buckets with a +1/-1 sign, and the vector is L2-normalized. Texts sharing
+/// more words get a higher cosine similarity, so rankings over a toy corpus are
+/// predictable. There is no notion of synonyms or word order.
+pub struct HashEmbedder {
+    dim: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dim: usize) -> Self {
+        Self { dim: dim.max(1) }
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dim];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            let hash = fnv1a(&word.to_lowercase());
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dim as u64) as usize] += sign;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn embed_texts(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+/// 64-bit FNV-1a hash; unlike `DefaultHasher` it is stable across Rust releases.
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn hash_embeddings_are_deterministic() {
+        let texts = ["Quarterly revenue grew", "Cloud sales were strong"];
+        let first = HashEmbedder::new(64).embed_texts(&texts).unwrap();
+        let second = HashEmbedder::new(64).embed_texts(&texts).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first[0].len(), 64);
+    }
+
+    #[test]
+    fn hash_embeddings_have_unit_length() {
+        let embedder = HashEmbedder::new(64);
+        let vectors = embedder
+            .embed_texts(&["Quarterly revenue grew", "a a a b", "x"])
+            .unwrap();
+        for vector in &vectors {
+            assert!((dot(vector, vector) - 1.0).abs() < 1e-5);
+        }
+        // Nothing to hash: the zero vector, not NaNs
+        let empty = embedder.embed_texts(&["  ,. "]).unwrap().remove(0);
+        assert!(empty.iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn texts_sharing_words_score_closer() {
+        let vectors = HashEmbedder::new(256)
+            .embed_texts(&[
+                "quarterly revenue grew strongly",
+                "Quarterly Revenue grew slowly",
+                "the weather was rainy today",
+            ])
+            .unwrap();
+        assert!(dot(&vectors[0], &vectors[1]) > dot(&vectors[0], &vectors[2]));
+    }
+}
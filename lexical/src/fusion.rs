@@ -0,0 +1,91 @@
+use crate::topk::top_k_by_score;
+use std::collections::HashMap;
+
+/// One entry of a ranked list: an item id (a chunk index) and its retriever score.
+#[derive(Debug, Clone, Copy)]
+pub struct ItemWithScore {
+    pub id: usize,
+    pub score: f32,
+}
+
+/// How `merge_rankings` turns each ranked list into per-item contributions.
+#[derive(Debug, Clone, Copy)]
+pub enum MergeMethod {
+    /// `weight * score`. Scores should already be on a comparable scale,
+    /// e.g. normalized to [0, 1].
+    WeightedSum,
+    /// Reciprocal rank fusion: `weight / (k + rank)`, with ranks starting at 1.
+    /// Only the order of each list matters, so raw scores need no normalization.
+    Rrf { k: f32 },
+}
+
+/// What one ranked list contributed to a merged item.
+#[derive(Debug, Clone)]
+pub struct SourceContribution {
+    /// Index of the list in `rankings`.
+    pub source: usize,
+    /// 1-based position of the item in that list.
+    pub rank: usize,
+    /// The item's score in that list.
+    pub score: f32,
+    /// What this list added to the merged score.
+    pub contribution: f32,
+}
+
+/// An item of the merged ranking, with the lists it came from.
+#[derive(Debug, Clone)]
+pub struct MergedItem {
+    pub id: usize,
+    pub score: f32,
+    pub sources: Vec<SourceContribution>,
+}
+
+/// Merge any number of ranked lists into one.
+///
+/// List `i` is weighted by `weights[i]` (1.0 if `weights` is shorter than
+/// `rankings`). Items are deduplicated by id and their contributions summed;
+/// if an id repeats within one list, only its first (best) position counts.
+/// Lists are taken in the order given, so for `Rrf` they must be sorted best
+/// first. Only the `limit` best items are returned (pass `usize::MAX` for all),
+/// sorted by merged score, highest first, ties broken by id.
+pub fn merge_rankings(
+    rankings: &[Vec<ItemWithScore>],
+    weights: &[f32],
+    method: MergeMethod,
+    limit: usize,
+) -> Vec<MergedItem> {
+    let mut merged: HashMap<usize, MergedItem> = HashMap::new();
+
+    for (source, ranking) in rankings.iter().enumerate() {
+        let weight = weights.get(source).copied().unwrap_or(1.0);
+
+        for (pos, item) in ranking.iter().enumerate() {
+            let entry = merged.entry(item.id).or_insert_with(|| MergedItem {
+                id: item.id,
+                score: 0.0,
+                sources: Vec::new(),
+            });
+            if entry.sources.iter().any(|s| s.source == source) {
+                continue;
+            }
+
+            let rank = pos + 1;
+            let contribution = match method {
+                MergeMethod::WeightedSum => weight * item.score,
+                MergeMethod::Rrf { k } => weight / (k + rank as f32),
+            };
+            entry.score += contribution;
+            entry.sources.push(SourceContribution {
+                source,
+                rank,
+                score: item.score,
+                contribution,
+            });
+        }
+    }
+
+    let best = top_k_by_score(merged.values().map(|item| (item.id, item.score)), limit);
+    best.into_iter()
+        .filter_map(|(id, _)| merged.remove(&id))
+        .collect()
+}
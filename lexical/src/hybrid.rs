@@ -1,14 +1,46 @@
 use crate::data::Chunk;
-use crate::embeddings::SentenceEmbedder;
-use bm25::{Embedder, EmbedderBuilder, Embedding, Language, TokenEmbedding};
+use crate::embeddings::{Embedder as DenseEmbedder, fnv1a};
+use crate::error::RagError;
+use crate::fusion::{ItemWithScore, MergeMethod, merge_rankings};
+use crate::vector_db::{DistanceMetric, chroma_id, distance_to_similarity};
+use bm25::{
+    DefaultTokenizer, Embedder, EmbedderBuilder, Embedding, Language, TokenEmbedding, Tokenizer,
+};
 use chromadb::collection::QueryOptions;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use tracing::{debug, instrument};
+
+/// On-disk format version of a saved `Bm25Index`; bump when `SavedBm25Index` changes.
+const BM25_INDEX_FORMAT_VERSION: u32 = 2;
+
+/// Serialized form of a fitted `Bm25Index`.
+///
+/// The `bm25` embedder weights terms by saturated frequency and document
+/// length only (there is no IDF), so the fitted state is just the average
+/// document length plus the precomputed `(token index, weight)` embeddings.
+/// Each document's text fingerprint is saved too, to detect a changed corpus.
+#[derive(Serialize, Deserialize)]
+struct SavedBm25Index {
+    version: u32,
+    avgdl: f32,
+    doc_count: usize,
+    doc_embeddings: Vec<Vec<(u32, f32)>>,
+    doc_fingerprints: Vec<u64>,
+}
 
 /// A BM25 “index” that precomputes sparse embeddings for every chunk.
 pub struct Bm25Index {
     embedder: Embedder,
+    /// Same tokenizer as the embedder, kept to map embedding entries back to terms.
+    tokenizer: DefaultTokenizer,
     doc_embeddings: Vec<Embedding>,
+    /// `fnv1a` of each document's text, aligned with `doc_embeddings`.
+    doc_fingerprints: Vec<u64>,
 }
 
 impl Bm25Index {
@@ -35,8 +67,135 @@ impl Bm25Index {
         // Return the Bm25Index
         Bm25Index {
             embedder,
+            tokenizer: DefaultTokenizer::new(Language::English),
             doc_embeddings,
+            doc_fingerprints: chunks.iter().map(|c| fnv1a(&c.text)).collect(),
+        }
+    }
+
+    /// Number of documents in the index.
+    pub fn doc_count(&self) -> usize {
+        self.doc_embeddings.len()
+    }
+
+    /// Whether the indexed documents are the first `doc_count()` of `chunks`,
+    /// text for text, so the index can be used for them after adding the rest.
+    ///
+    /// Texts are compared by fingerprint; an index built from another corpus
+    /// or chunk size does not match.
+    pub fn is_prefix_of(&self, chunks: &[Chunk]) -> bool {
+        self.doc_fingerprints.len() <= chunks.len()
+            && self
+                .doc_fingerprints
+                .iter()
+                .zip(chunks)
+                .all(|(&fingerprint, chunk)| fingerprint == fnv1a(&chunk.text))
+    }
+
+    /// Add one document without refitting the whole corpus.
+    ///
+    /// The average document length is updated to include the new document and
+    /// its embedding is appended, so its index is the previous `doc_count()`.
+    /// There is no IDF to update, but embeddings already in the index keep the
+    /// average length they were computed with, so their scores drift slightly
+    /// from those of a full refit. After many incremental adds (say, a tenth of
+    /// the corpus), rebuild the index with `Bm25Index::new`.
+    pub fn add_document(&mut self, text: &str) {
+        self.doc_fingerprints.push(fnv1a(text));
+        let text = text.to_lowercase();
+        let doc_count = self.doc_embeddings.len() as f32;
+        let doc_len = self.tokenizer.tokenize(&text).len() as f32;
+        let avgdl = (self.embedder.avgdl() * doc_count + doc_len) / (doc_count + 1.0);
+
+        self.embedder = EmbedderBuilder::with_avgdl(avgdl)
+            .language_mode(Language::English)
+            .build();
+        self.doc_embeddings.push(self.embedder.embed(&text));
+    }
+
+    /// Save the fitted index to `path` so it can be reloaded without refitting.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let saved = SavedBm25Index {
+            version: BM25_INDEX_FORMAT_VERSION,
+            avgdl: self.embedder.avgdl(),
+            doc_count: self.doc_embeddings.len(),
+            doc_embeddings: self
+                .doc_embeddings
+                .iter()
+                .map(|emb| emb.0.iter().map(|t| (t.index, t.value)).collect())
+                .collect(),
+            doc_fingerprints: self.doc_fingerprints.clone(),
+        };
+
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, &saved)?;
+        Ok(())
+    }
+
+    /// Load an index previously written by `save`.
+    ///
+    /// Fails if the file was written by an incompatible version, or if
+    /// `expected_docs` is given and does not match the saved document count
+    /// (e.g. the corpus or chunk size changed since the index was saved).
+    pub fn load(path: &Path, expected_docs: Option<usize>) -> Result<Self, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let saved: SavedBm25Index = serde_json::from_reader(reader)?;
+
+        if saved.version != BM25_INDEX_FORMAT_VERSION {
+            return Err(format!(
+                "BM25 index {} has format version {}, expected {}; rebuild it",
+                path.display(),
+                saved.version,
+                BM25_INDEX_FORMAT_VERSION
+            )
+            .into());
         }
+        if saved.doc_count != saved.doc_embeddings.len()
+            || saved.doc_count != saved.doc_fingerprints.len()
+        {
+            return Err(format!(
+                "BM25 index {} is corrupt: {} documents but {} embeddings and {} fingerprints",
+                path.display(),
+                saved.doc_count,
+                saved.doc_embeddings.len(),
+                saved.doc_fingerprints.len()
+            )
+            .into());
+        }
+        if let Some(expected) = expected_docs {
+            if saved.doc_count != expected {
+                return Err(format!(
+                    "BM25 index {} holds {} documents, expected {}; rebuild it",
+                    path.display(),
+                    saved.doc_count,
+                    expected
+                )
+                .into());
+            }
+        }
+
+        let embedder: Embedder = EmbedderBuilder::with_avgdl(saved.avgdl)
+            .language_mode(Language::English)
+            .build();
+        let doc_embeddings = saved
+            .doc_embeddings
+            .into_iter()
+            .map(|tokens| {
+                Embedding(
+                    tokens
+                        .into_iter()
+                        .map(|(index, value)| TokenEmbedding { index, value })
+                        .collect(),
+                )
+            })
+            .collect();
+
+        Ok(Bm25Index {
+            embedder,
+            tokenizer: DefaultTokenizer::new(Language::English),
+            doc_embeddings,
+            doc_fingerprints: saved.doc_fingerprints,
+        })
     }
 
     /// Compute a BM25‐style score for the query against every chunk.
@@ -50,6 +209,58 @@ impl Bm25Index {
             .map(|doc_emb| dot(&q_emb, doc_emb))
             .collect()
     }
+
+    /// Number of distinct query terms (after tokenization) present in each chunk.
+    pub fn matched_term_counts(&self, query: &str) -> Vec<usize> {
+        let q_emb = self.embedder.embed(query);
+        let mut q_indices: Vec<u32> = q_emb.0.iter().map(|tok| tok.index).collect();
+        q_indices.sort_unstable();
+        q_indices.dedup();
+
+        self.doc_embeddings
+            .iter()
+            .map(|doc_emb| {
+                q_indices
+                    .iter()
+                    .filter(|&&qi| doc_emb.0.iter().any(|d_tok| d_tok.index == qi))
+                    .count()
+            })
+            .collect()
+    }
+
+    /// Break down the BM25 score of chunk `doc_idx` by query term.
+    ///
+    /// Returns one `(term, contribution)` pair per distinct query term that
+    /// survives tokenization (terms are stemmed and stopwords removed), in query
+    /// order. Contributions sum to `score(query)[doc_idx]`; terms absent from the
+    /// chunk contribute 0.0. An out-of-range `doc_idx` yields an empty vector.
+    pub fn explain(&self, query: &str, doc_idx: usize) -> Vec<(String, f32)> {
+        let Some(doc_emb) = self.doc_embeddings.get(doc_idx) else {
+            return Vec::new();
+        };
+
+        // The query embedding has one entry per token, in tokenizer order
+        let q_emb = self.embedder.embed(query);
+        let terms = self.tokenizer.tokenize(query);
+
+        let mut breakdown: Vec<(String, f32)> = Vec::new();
+        for (term, q_tok) in terms.into_iter().zip(&q_emb.0) {
+            let doc_value: f32 = doc_emb
+                .0
+                .iter()
+                .filter(|d_tok| d_tok.index == q_tok.index)
+                .map(|d_tok| d_tok.value)
+                .sum();
+            let contribution = q_tok.value * doc_value;
+
+            match breakdown.iter_mut().find(|(t, _)| *t == term) {
+                Some((_, total)) => *total += contribution,
+                None => breakdown.push((term, contribution)),
+            }
+        }
+
+        breakdown
+    }
 }
 
 /// Dot‐product of two sparse embeddings.
@@ -73,6 +284,99 @@ fn dot(a: &Embedding, b: &Embedding) -> f32 {
     sum
 }
 
+/// One result of `hybrid_retrieval`.
+#[derive(Debug, Clone)]
+pub struct HybridResult {
+    /// Position of the chunk in the `chunks` slice.
+    pub index: usize,
+    /// Chroma id of the chunk, for follow-up updates or deletes.
+    pub id: String,
+    pub score: f32,
+    /// The numbers behind `score`.
+    pub trace: FusionTrace,
+}
+
+/// How the fused score of one `HybridResult` was put together, to see what
+/// `alpha` does on a given corpus.
+///
+/// With `MergeMethod::WeightedSum` the fused score is
+/// `alpha * bm25_normalized + (1 - alpha) * dense_similarity`; with
+/// `MergeMethod::Rrf` the contributions come from ranks instead, so they no
+/// longer follow from the scores shown here.
+#[derive(Debug, Clone)]
+pub struct FusionTrace {
+    /// BM25 score as computed by `Bm25Index::score`.
+    pub bm25_raw: f32,
+    /// BM25 score min-max normalized over the corpus to [0, 1].
+    pub bm25_normalized: f32,
+    /// Distance reported by Chroma, `None` if the chunk was not among the
+    /// dense candidates.
+    pub dense_distance: Option<f32>,
+    /// `dense_distance` converted to a similarity for the collection's metric.
+    pub dense_similarity: Option<f32>,
+    /// Weight on BM25; the dense side gets `1 - alpha`.
+    pub alpha: f32,
+    /// What the BM25 ranking added to the fused score.
+    pub bm25_contribution: f32,
+    /// What the dense ranking added to the fused score.
+    pub dense_contribution: f32,
+}
+
+/// Render the fusion math of `results` as a table, one row per result.
+pub fn fusion_trace_table(results: &[HybridResult]) -> String {
+    let optional =
+        |value: Option<f32>| value.map_or_else(|| "-".to_string(), |v| format!("{:.4}", v));
+
+    let mut table = format!(
+        "{:<16}{:>12}{:>12}{:>12}{:>12}{:>8}{:>12}{:>12}{:>12}\n",
+        "chunk",
+        "bm25 raw",
+        "bm25 norm",
+        "dense dist",
+        "dense sim",
+        "alpha",
+        "bm25 part",
+        "dense part",
+        "fused"
+    );
+    for result in results {
+        let t = &result.trace;
+        table.push_str(&format!(
+            "{:<16}{:>12.4}{:>12.4}{:>12}{:>12}{:>8.2}{:>12.4}{:>12.4}{:>12.4}\n",
+            result.id,
+            t.bm25_raw,
+            t.bm25_normalized,
+            optional(t.dense_distance),
+            optional(t.dense_similarity),
+            t.alpha,
+            t.bm25_contribution,
+            t.dense_contribution,
+            result.score
+        ));
+    }
+    table
+}
+
+/// Position in `chunks` of the dense result stored under `id`.
+///
+/// Results carry their position as `chunk_index` metadata, which is used when
+/// the chunk at that position still has this id. A stale or reused collection,
+/// or chunks loaded in another order, can leave it pointing elsewhere; then,
+/// as for collections built before the field existed, the id is looked up.
+fn resolve_chunk_index(
+    id: &str,
+    metadata: Option<&serde_json::Map<String, serde_json::Value>>,
+    ids: &[String],
+    index_of: &HashMap<&str, usize>,
+) -> Option<usize> {
+    metadata
+        .and_then(|metadata| metadata.get("chunk_index"))
+        .and_then(|value| value.as_u64())
+        .map(|idx| idx as usize)
+        .filter(|&idx| ids.get(idx).is_some_and(|known| known == id))
+        .or_else(|| index_of.get(id).copied())
+}
+
 /// Perform hybrid retrieval combining BM25 scores and dense‐embedding similarity.
 ///
 /// The score for each chunk is a weighted sum of its BM25 score and the
@@ -82,8 +386,41 @@ fn dot(a: &Embedding, b: &Embedding) -> f32 {
 /// to [0, 1] over the entire corpus.  The final score is a weighted sum of
 /// these two normalized scores.
 ///
-/// The function returns a sorted list of (chunk index, score) pairs, with
-/// the highest‐scoring pairs first.  The top `top_k` pairs are returned.
+/// With `MergeMethod::Rrf` the two rankings are fused by rank instead: only
+/// chunks with a positive BM25 score enter the BM25 ranking, and `alpha`
+/// still weights the BM25 list against the dense one.
+///
+/// The function returns a sorted list of results (chunk index, Chroma id and
+/// score), with the highest‐scoring first.  The top `top_k` are returned.
+/// Each carries a `FusionTrace` of the scores it was fused from; see
+/// `fusion_trace_table`.
+///
+/// `top_k` is an upper bound: when `min_score` is set, results whose fused
+/// score is below it are dropped before truncating, so fewer than `top_k`
+/// (possibly zero) may come back rather than padding with weak matches.
+///
+/// With `min_bm25_terms > 0`, only chunks containing at least that many
+/// distinct query terms are eligible: others are removed from both rankings
+/// before fusion, however close their embedding. 0 disables the gate.
+///
+/// Dense results are mapped back to `chunks` through the `chunk_index`
+/// metadata that `build_chroma_collection` stores, checked against the
+/// result's Chroma id; entries without that field, or whose index points at a
+/// chunk with another id, are matched by their Chroma id instead.
+///
+/// A query of nothing but stopwords (see `is_blank_query`) fails with
+/// `RagError::EmptyQuery` before BM25 scoring or any embedding call.
+#[instrument(
+    skip_all,
+    fields(
+        query = %query,
+        top_k = top_k,
+        alpha = alpha,
+        min_score = ?min_score,
+        fusion = ?fusion,
+        min_bm25_terms = min_bm25_terms
+    )
+)]
 pub async fn hybrid_retrieval(
     query: &str,
     chunks: &[Chunk],
@@ -91,8 +428,15 @@ pub async fn hybrid_retrieval(
     collection: &chromadb::collection::ChromaCollection,
     top_k: usize,
     alpha: f32, // weight on BM25 [0..1]
-    embedder: &SentenceEmbedder,
-) -> Result<Vec<(usize, f32)>, Box<dyn Error>> {
+    min_score: Option<f32>,
+    fusion: MergeMethod,
+    min_bm25_terms: usize,
+    embedder: &dyn DenseEmbedder,
+) -> Result<Vec<HybridResult>, Box<dyn Error>> {
+    if is_blank_query(query) {
+        return Err(RagError::EmptyQuery.into());
+    }
+
     // 1) BM25 scores + normalization range
     let b_scores = bm25.score(query);
     let (b_min, b_max) = b_scores
@@ -102,6 +446,7 @@ pub async fn hybrid_retrieval(
             (mn.min(v), mx.max(v))
         });
     let denom = (b_max - b_min).max(f32::EPSILON);
+    debug!(b_min, b_max, "BM25 score range");
 
     // 2) Dense retrieval via ChromaDB (we ask only for distances)
     let q_emb = embedder.embed_texts(&[query])?;
@@ -111,44 +456,251 @@ pub async fn hybrid_retrieval(
         n_results: Some((top_k * 5).min(chunks.len())),
         where_metadata: None,
         where_document: None,
-        include: Some(vec!["distances".into()]),
+        include: Some(vec!["distances".into(), "metadatas".into()]),
     };
     let res = collection.query(opts, None).await?;
 
-    // 3) Build a map from chunk index → dense similarity
-    let mut embed_sim = HashMap::new();
+    // 3) Dense ranking in Chroma's order (most similar first)
+    let metric = DistanceMetric::of_collection(collection);
+    let ids: Vec<String> = chunks.iter().map(chroma_id).collect();
+    let index_of: HashMap<&str, usize> = ids
+        .iter()
+        .enumerate()
+        .map(|(idx, id)| (id.as_str(), idx))
+        .collect();
+    let metadatas = res.metadatas.and_then(|groups| groups.into_iter().next());
+    let chunk_index = |i: usize, id: &str| {
+        let metadata = metadatas
+            .as_ref()
+            .and_then(|row| row.get(i))
+            .and_then(|metadata| metadata.as_ref());
+        resolve_chunk_index(id, metadata, &ids, &index_of)
+    };
+    let mut dense_ranking = Vec::new();
+    let mut dense_distances: HashMap<usize, f32> = HashMap::new();
     if let (ids_groups, Some(dist_groups)) = (res.ids, res.distances) {
-        if let (Some(ids0), Some(d0)) = (ids_groups.get(0), dist_groups.get(0)) {
+        if let (Some(ids0), Some(d0)) = (ids_groups.first(), dist_groups.first()) {
             for (i, id_str) in ids0.iter().enumerate() {
-                if let Ok(idx) = id_str.parse::<usize>() {
-                    let dist = d0.get(i).copied().unwrap_or(0.0);
-                    embed_sim.insert(idx, 1.0 / (1.0 + dist));
+                // A chunk without a distance is left out of the dense ranking
+                // rather than treated as a perfect match
+                if let (Some(idx), Some(&dist)) = (chunk_index(i, id_str), d0.get(i)) {
+                    dense_distances.insert(idx, dist);
+                    dense_ranking.push(ItemWithScore {
+                        id: idx,
+                        score: distance_to_similarity(dist, metric),
+                    });
                 }
             }
         }
     }
 
-    // 4) Combine BM25 (normalized) and dense sim into final scores
-    let mut merged: Vec<(usize, f32)> = b_scores
-        .into_iter()
+    // Lexical gate: drop chunks without enough query terms from both rankings
+    let eligible: Option<Vec<bool>> = (min_bm25_terms > 0).then(|| {
+        bm25.matched_term_counts(query)
+            .into_iter()
+            .map(|count| count >= min_bm25_terms)
+            .collect()
+    });
+    let is_eligible = |idx: usize| {
+        eligible
+            .as_ref()
+            .is_none_or(|e| e.get(idx).copied().unwrap_or(false))
+    };
+    dense_ranking.retain(|item| is_eligible(item.id));
+
+    // 4) BM25 ranking (normalized) and fusion of the two lists
+    let mut bm25_ranking: Vec<ItemWithScore> = b_scores
+        .iter()
+        .copied()
         .enumerate()
-        .map(|(i, b_raw)| {
-            let b_norm = (b_raw - b_min) / denom;
-            let e_sim = *embed_sim.get(&i).unwrap_or(&0.0);
-            (i, alpha * b_norm + (1.0 - alpha) * e_sim)
+        .filter(|&(i, b_raw)| {
+            is_eligible(i) && (!matches!(fusion, MergeMethod::Rrf { .. }) || b_raw > 0.0)
+        })
+        .map(|(i, b_raw)| ItemWithScore {
+            id: i,
+            score: (b_raw - b_min) / denom,
         })
         .collect();
+    bm25_ranking.sort_by(|a, b| b.score.total_cmp(&a.score));
 
-    // 5) Sort descending and take top_k
-    merged.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    merged.truncate(top_k);
+    let dense_hits = dense_ranking.len();
+    // Only the top_k best are kept, without sorting every scored chunk
+    let mut fused = merge_rankings(
+        &[bm25_ranking, dense_ranking],
+        &[alpha, 1.0 - alpha],
+        fusion,
+        top_k,
+    );
 
-    // 6) Print results
-    println!("Top {} hybrid results for '{}':", top_k, query);
-    for &(idx, score) in &merged {
-        let snippet: String = chunks[idx].text.chars().take(50).collect();
-        println!("  Chunk {} (score {:.4}): {}…", idx, score, snippet);
+    // 5) Drop weak matches; they sit at the end, so this equals filtering
+    // before taking the top_k
+    if let Some(floor) = min_score {
+        fused.retain(|item| item.score >= floor);
     }
 
+    // 6) Log results with the contribution of each retriever (0 = BM25, 1 = dense)
+    debug!(dense_hits, "hybrid scores merged");
+    for item in &fused {
+        let snippet: String = chunks[item.id].text.chars().take(50).collect();
+        let sources: Vec<String> = item
+            .sources
+            .iter()
+            .map(|s| {
+                format!(
+                    "{}#{}: {:.3}→{:.3}",
+                    s.source, s.rank, s.score, s.contribution
+                )
+            })
+            .collect();
+        debug!(chunk = item.id, score = item.score, sources = %sources.join(", "), "{}…", snippet);
+    }
+
+    let merged: Vec<HybridResult> = fused
+        .into_iter()
+        .map(|item| {
+            let contribution_of = |source: usize| {
+                item.sources
+                    .iter()
+                    .filter(|s| s.source == source)
+                    .map(|s| s.contribution)
+                    .sum()
+            };
+            let dense_distance = dense_distances.get(&item.id).copied();
+            HybridResult {
+                index: item.id,
+                id: ids[item.id].clone(),
+                score: item.score,
+                trace: FusionTrace {
+                    bm25_raw: b_scores[item.id],
+                    bm25_normalized: (b_scores[item.id] - b_min) / denom,
+                    dense_distance,
+                    dense_similarity: dense_distance.map(|d| distance_to_similarity(d, metric)),
+                    alpha,
+                    bm25_contribution: contribution_of(0),
+                    dense_contribution: contribution_of(1),
+                },
+            }
+        })
+        .collect();
     Ok(merged)
 }
+
+/// Common English words that carry no meaning on their own; a query made only
+/// of these is treated as empty.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "is", "in", "of", "to", "a", "an", "that", "for", "on", "with", "as", "it", "by",
+    "this", "are", "was", "at", "from", "or", "be", "which", "not", "can", "also", "have", "has",
+    "had", "we", "they", "you", "he", "she", "his", "her", "its", "our", "us", "their", "them",
+    "i", "do", "does", "did", "just", "so", "if", "may", "will", "shall", "more", "most", "some",
+    "many", "any", "all", "what", "about", "would", "could", "should", "where", "when", "why",
+    "how",
+];
+
+/// True if `query` has no words but stopwords, e.g. "", "   " or "what is
+/// the". Embedding such a query would return arbitrary nearest neighbours.
+fn is_blank_query(query: &str) -> bool {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOPWORDS.contains(&word.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_db::chunk_metadata;
+
+    fn chunks() -> Vec<Chunk> {
+        (0..3)
+            .map(|doc_id| Chunk {
+                doc_id,
+                chunk_id: 0,
+                category: "general".to_string(),
+                text: format!("document {}", doc_id),
+            })
+            .collect()
+    }
+
+    fn lookup(ids: &[String]) -> HashMap<&str, usize> {
+        ids.iter()
+            .enumerate()
+            .map(|(idx, id)| (id.as_str(), idx))
+            .collect()
+    }
+
+    #[test]
+    fn stored_chunk_index_maps_every_result_back_to_its_chunk() {
+        let chunks = chunks();
+        let ids: Vec<String> = chunks.iter().map(chroma_id).collect();
+        let index_of = lookup(&ids);
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let metadata = chunk_metadata(idx, chunk);
+            assert_eq!(metadata.get("chunk_index"), Some(&serde_json::json!(idx)));
+            assert_eq!(
+                resolve_chunk_index(&ids[idx], Some(&metadata), &ids, &index_of),
+                Some(idx)
+            );
+        }
+    }
+
+    #[test]
+    fn stale_chunk_index_falls_back_to_the_id() {
+        let chunks = chunks();
+        // The collection was built with the chunks in reverse order
+        let stale = chunk_metadata(0, &chunks[2]);
+        let ids: Vec<String> = chunks.iter().map(chroma_id).collect();
+        let index_of = lookup(&ids);
+        assert_eq!(
+            resolve_chunk_index("doc_2_chunk_0", Some(&stale), &ids, &index_of),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn unknown_ids_are_not_mapped() {
+        let chunks = chunks();
+        let ids: Vec<String> = chunks.iter().map(chroma_id).collect();
+        let index_of = lookup(&ids);
+        let metadata = chunk_metadata(1, &chunks[1]);
+        assert_eq!(
+            resolve_chunk_index("doc_9_chunk_0", Some(&metadata), &ids, &index_of),
+            None
+        );
+        assert_eq!(
+            resolve_chunk_index("doc_1_chunk_0", None, &ids, &index_of),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn index_matches_only_corpora_that_start_with_its_chunks() {
+        let mut chunks = chunks();
+        let index = Bm25Index::new(&chunks[..2]);
+        assert!(index.is_prefix_of(&chunks));
+        assert!(!index.is_prefix_of(&chunks[..1]));
+
+        chunks[1].text = "document 1, edited".to_string();
+        assert!(!index.is_prefix_of(&chunks));
+    }
+
+    #[test]
+    fn fingerprints_survive_add_save_and_load() {
+        let chunks = chunks();
+        let mut index = Bm25Index::new(&chunks[..2]);
+        index.add_document(&chunks[2].text);
+
+        let path = std::env::temp_dir().join("bm25_fingerprint_test.json");
+        index.save(&path).unwrap();
+        let loaded = Bm25Index::load(&path, Some(3)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_prefix_of(&chunks));
+    }
+
+    #[test]
+    fn empty_whitespace_and_stopword_queries_are_blank() {
+        for query in ["", "   ", "what is the?"] {
+            assert!(is_blank_query(query), "{query:?}");
+        }
+        assert!(!is_blank_query("what is the NVDA price"));
+    }
+}
@@ -1,32 +1,113 @@
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 mod hybrid;
+mod query;
+mod fusion;
+mod compare;
+mod topk;
 
+use compare::compare_retrievers;
 use data::load_and_chunk_dataset;
-use embeddings::SentenceEmbedder;
-use hybrid::{hybrid_retrieval, Bm25Index};
+use embeddings::{Embedder, HashEmbedder, SentenceEmbedder};
+use fusion::MergeMethod;
+use hybrid::{fusion_trace_table, hybrid_retrieval, Bm25Index};
+use query::{preprocess_query, Lowercase, SpellCorrector, StripPunctuation};
 use std::env;
 use std::error::Error;
-use vector_db::build_chroma_collection;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+use vector_db::{build_chroma_collection, CollectionMode};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // RUST_LOG=debug shows retrieval internals; by default only warnings are logged
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")))
+        .init();
+
     // 1) Load & chunk
     let dataset_file = env::current_dir()?.join("data").join("corpus.json");
-    println!("Loading data from: {}", dataset_file.display());
+    info!("Loading data from: {}", dataset_file.display());
     let chunks = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 40)?;
 
-    // 2) Build BM25 index
-    let bm25 = Bm25Index::new(&chunks);
+    // 2) Load the saved BM25 index, or fit and save a new one.
+    // Chunks appended to the corpus since the index was saved are added incrementally;
+    // if any saved chunk's text changed (an edited corpus or another chunk size), it is rebuilt.
+    let bm25_file = env::current_dir()?.join("data").join("bm25_index.json");
+    let bm25 = match Bm25Index::load(&bm25_file, None) {
+        Ok(mut index) if index.is_prefix_of(&chunks) => {
+            let saved = index.doc_count();
+            if saved < chunks.len() {
+                for chunk in &chunks[saved..] {
+                    index.add_document(&chunk.text);
+                }
+                info!("Added {} new chunks to the BM25 index", chunks.len() - saved);
+                index.save(&bm25_file)?;
+            } else {
+                info!("Loaded BM25 index from {}", bm25_file.display());
+            }
+            index
+        }
+        loaded => {
+            match loaded {
+                Ok(index) => warn!(
+                    "BM25 index of {} chunks does not match the corpus ({} chunks); rebuilding",
+                    index.doc_count(),
+                    chunks.len()
+                ),
+                Err(e) if bm25_file.exists() => warn!("Could not load BM25 index ({}); rebuilding", e),
+                Err(_) => {}
+            }
+            let index = Bm25Index::new(&chunks);
+            index.save(&bm25_file)?;
+            index
+        }
+    };
 
     // 3) Build dense collection & embedder
-    let embedder = SentenceEmbedder::new().await?;
-    let collection = build_chroma_collection(&chunks, "hybrid_collection", &embedder).await?;
-    println!("Hybrid collection has {} documents.", collection.count().await?);
+    // EMBEDDER=hash uses an offline hashed bag-of-words embedder instead of the model.
+    // Its vectors live in their own collection so they never mix with model vectors.
+    let use_hash_embedder = env::var("EMBEDDER").as_deref() == Ok("hash");
+    let embedder: Box<dyn Embedder> = if use_hash_embedder {
+        Box::new(HashEmbedder::new(384))
+    } else {
+        Box::new(SentenceEmbedder::new().await?)
+    };
+    let embedder = embedder.as_ref();
+    let collection_name = if use_hash_embedder { "hybrid_collection_hash" } else { "hybrid_collection" };
+    // Start from an empty collection so chunks from runs with another chunk size never
+    // mix in; COLLECTION_MODE=sync or reuse keeps the collection
+    let mode = env::var("COLLECTION_MODE").ok().and_then(|name| CollectionMode::from_name(&name)).unwrap_or_default();
+    let collection = build_chroma_collection(&chunks, collection_name, embedder, mode).await?;
+    info!("Hybrid collection has {} documents.", collection.count().await?);
+
+    // 4) Normalize the query (typos are corrected against the corpus vocabulary)
+    let spell_corrector = SpellCorrector::from_chunks(&chunks);
+    let preprocessed = preprocess_query(
+        "What do our internal company policies state?",
+        &[&Lowercase, &StripPunctuation, &spell_corrector],
+    );
+    println!("Query: {}", preprocessed.original);
+    if preprocessed.normalized != preprocessed.original {
+        info!("Normalized query: {}", preprocessed.normalized);
+    }
+    let query = preprocessed.normalized.as_str();
 
-    // 4) Perform hybrid retrieval
-    let query = "What do our internal company policies state?";
+    // 5) Perform hybrid retrieval (HYBRID_FUSION=rrf fuses by rank instead of score)
+    let fusion = if env::var("HYBRID_FUSION").as_deref() == Ok("rrf") {
+        MergeMethod::Rrf { k: 60.0 }
+    } else {
+        MergeMethod::WeightedSum
+    };
+    let min_score = match fusion {
+        MergeMethod::WeightedSum => Some(0.3),
+        // RRF scores are at most 1/61, so a weighted-sum floor would drop everything
+        MergeMethod::Rrf { .. } => None,
+    };
+    // MIN_BM25_TERMS=n keeps only chunks containing at least n of the query's terms
+    let min_bm25_terms = env::var("MIN_BM25_TERMS").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
     let results = hybrid_retrieval(
         query,
         &chunks,
@@ -34,18 +115,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
         &collection,
         /* top_k */ 3,
         /* alpha  */ 0.6,
-        &embedder,
+        min_score,
+        fusion,
+        min_bm25_terms,
+        embedder,
     )
         .await?;
 
     if results.is_empty() {
-        println!("No chunks found. Fallback to apology.");
+        warn!("No chunks found. Fallback to apology.");
     } else {
+        // HYBRID_TRACE=1 shows how each result's score was fused, to help tune alpha
+        if env::var("HYBRID_TRACE").is_ok() {
+            println!("Hybrid fusion trace (alpha 0.6):\n{}", fusion_trace_table(&results));
+        }
         println!("Final hybrid top‑k results:");
-        for (idx, score) in results {
-            println!(" → [{}] (score {:.4}) {}", idx, score, chunks[idx].text);
+        for result in results {
+            println!(" → [{}] {} (score {:.4}) {}", result.index, result.id, result.score, chunks[result.index].text);
+            let terms: Vec<String> = bm25
+                .explain(query, result.index)
+                .into_iter()
+                .filter(|(_, contribution)| *contribution > 0.0)
+                .map(|(term, contribution)| format!("{}={:.3}", term, contribution))
+                .collect();
+            println!("    BM25 terms: {}", if terms.is_empty() { "none".to_string() } else { terms.join(", ") });
         }
     }
 
+    // COMPARE_RETRIEVERS=1 shows every retriever's top-k for the query side by side
+    if env::var("COMPARE_RETRIEVERS").is_ok() {
+        let table = compare_retrievers(query, &chunks, &collection, &bm25, embedder, 5).await?;
+        println!("\n{}", table);
+    }
+
     Ok(())
 }
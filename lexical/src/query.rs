@@ -0,0 +1,163 @@
+use crate::data::Chunk;
+use std::collections::HashMap;
+
+/// A query before and after preprocessing.
+///
+/// `normalized` is what gets scored and embedded; `original` is kept for
+/// display so users see what they typed.
+pub struct PreprocessedQuery {
+    pub original: String,
+    pub normalized: String,
+}
+
+/// One step of query preprocessing. Implement it to add a custom normalizer.
+pub trait QueryNormalizer {
+    fn normalize(&self, query: &str) -> String;
+}
+
+/// Lowercases the query.
+pub struct Lowercase;
+
+impl QueryNormalizer for Lowercase {
+    fn normalize(&self, query: &str) -> String {
+        query.to_lowercase()
+    }
+}
+
+/// Replaces punctuation with spaces and collapses runs of whitespace.
+///
+/// Apostrophes and hyphens inside words are kept ("don't", "e-mail").
+pub struct StripPunctuation;
+
+impl QueryNormalizer for StripPunctuation {
+    fn normalize(&self, query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|word| {
+                word.trim_matches(|c: char| !c.is_alphanumeric())
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || c == '\'' || c == '-' {
+                            c
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Corrects misspelled words against the corpus vocabulary.
+///
+/// Because the dictionary is built from the corpus itself, domain terms are
+/// known words and are never "corrected" away. Words of three letters or
+/// fewer, words containing non-letters and words already in the vocabulary are
+/// left unchanged. Otherwise the closest vocabulary word within an edit
+/// distance of 1 (2 for words longer than five letters) is used, preferring
+/// the more frequent word on ties.
+pub struct SpellCorrector {
+    vocabulary: HashMap<String, usize>,
+}
+
+impl SpellCorrector {
+    /// Build the dictionary from the lowercased words of every chunk.
+    pub fn from_chunks(chunks: &[Chunk]) -> Self {
+        let mut vocabulary = HashMap::new();
+        for chunk in chunks {
+            for word in chunk.text.split_whitespace() {
+                let word = word
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase();
+                if !word.is_empty() && word.chars().all(char::is_alphabetic) {
+                    *vocabulary.entry(word).or_insert(0) += 1;
+                }
+            }
+        }
+        SpellCorrector { vocabulary }
+    }
+
+    /// Return the correction for a single word, or the word itself.
+    pub fn correct_word(&self, word: &str) -> String {
+        let lower = word.to_lowercase();
+        if lower.chars().count() <= 3
+            || !lower.chars().all(char::is_alphabetic)
+            || self.vocabulary.contains_key(&lower)
+        {
+            return word.to_string();
+        }
+
+        let max_distance = if lower.chars().count() > 5 { 2 } else { 1 };
+        self.vocabulary
+            .iter()
+            .filter_map(|(candidate, &count)| {
+                let distance = edit_distance(&lower, candidate);
+                (distance <= max_distance).then_some((distance, count, candidate))
+            })
+            // Smallest distance, then highest frequency, then alphabetical for determinism
+            .min_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)))
+            .map(|(_, _, candidate)| candidate.clone())
+            .unwrap_or_else(|| word.to_string())
+    }
+}
+
+impl QueryNormalizer for SpellCorrector {
+    fn normalize(&self, query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|word| self.correct_word(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Edit distance counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters ("retreival" → "retrieval" is 1).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        d[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Run `query` through `normalizers` in order.
+///
+/// Use the returned `normalized` text for both BM25 scoring and embedding so
+/// the two retrievers see the same query.
+pub fn preprocess_query(query: &str, normalizers: &[&dyn QueryNormalizer]) -> PreprocessedQuery {
+    let normalized = normalizers
+        .iter()
+        .fold(query.to_string(), |current, normalizer| {
+            normalizer.normalize(&current)
+        });
+
+    PreprocessedQuery {
+        original: query.to_string(),
+        normalized,
+    }
+}
@@ -0,0 +1,96 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// An item and its score, ordered so that "greater" means "ranks higher":
+/// higher score first, then the smaller item on ties.
+struct Ranked<T> {
+    item: T,
+    score: f32,
+}
+
+impl<T: Ord> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.item.cmp(&self.item))
+    }
+}
+
+impl<T: Ord> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Ranked<T> {}
+
+/// The `k` highest-scoring items, best first.
+///
+/// Equal scores are ordered by item, smallest first, so the result does not
+/// depend on the input order. Runs in O(n log k) with a heap of at most `k`
+/// items, instead of sorting all `n`.
+pub fn top_k_by_score<T: Ord>(
+    items: impl IntoIterator<Item = (T, f32)>,
+    k: usize,
+) -> Vec<(T, f32)> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    // Min-heap on rank: the root is the weakest item kept so far. `k` may be
+    // `usize::MAX` for "all", so size the heap by the input where it is smaller
+    let items = items.into_iter();
+    let capacity = k
+        .saturating_add(1)
+        .min(items.size_hint().0.saturating_add(1));
+    let mut heap: BinaryHeap<Reverse<Ranked<T>>> = BinaryHeap::with_capacity(capacity);
+    for (item, score) in items {
+        let candidate = Ranked { item, score };
+        if heap.len() < k {
+            heap.push(Reverse(candidate));
+        } else if heap
+            .peek()
+            .is_some_and(|Reverse(weakest)| candidate > *weakest)
+        {
+            heap.pop();
+            heap.push(Reverse(candidate));
+        }
+    }
+
+    // Ascending in `Reverse` order is descending in rank
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| (ranked.item, ranked.score))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_k_best_with_ties_broken_by_item() {
+        let items = vec![(3, 0.5), (1, 0.9), (2, 0.5), (0, 0.1)];
+        assert_eq!(top_k_by_score(items, 3), [(1, 0.9), (2, 0.5), (3, 0.5)]);
+    }
+
+    #[test]
+    fn usize_max_returns_everything_without_overflowing() {
+        let items = vec![(0, 0.1), (1, 0.9), (2, 0.5)];
+        assert_eq!(
+            top_k_by_score(items, usize::MAX),
+            [(1, 0.9), (2, 0.5), (0, 0.1)]
+        );
+    }
+
+    #[test]
+    fn zero_k_is_empty() {
+        assert!(top_k_by_score(vec![(0, 1.0)], 0).is_empty());
+    }
+}
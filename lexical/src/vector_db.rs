@@ -1,21 +1,186 @@
 use crate::data::Chunk;
-use crate::embeddings::SentenceEmbedder;
+use crate::embeddings::Embedder;
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
-use chromadb::collection::{ChromaCollection, CollectionEntries};
+use chromadb::collection::{ChromaCollection, CollectionEntries, GetOptions};
 use serde_json::json;
 use std::error::Error;
 
-/// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
-pub async fn build_chroma_collection(
-    chunks: &[Chunk],
+/// Distance function of a Chroma collection (its `hnsw:space` setting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Squared Euclidean distance, Chroma's default.
+    L2,
+    /// Cosine distance, `1 - cos(a, b)`.
+    Cosine,
+    /// Inner-product distance, `1 - a·b`.
+    InnerProduct,
+}
+
+impl DistanceMetric {
+    /// Read the metric from the collection's metadata, defaulting to L2 as Chroma does.
+    pub fn of_collection(collection: &ChromaCollection) -> Self {
+        match collection
+            .metadata()
+            .and_then(|meta| meta.get("hnsw:space"))
+            .and_then(|space| space.as_str())
+        {
+            Some("cosine") => DistanceMetric::Cosine,
+            Some("ip") => DistanceMetric::InnerProduct,
+            _ => DistanceMetric::L2,
+        }
+    }
+}
+
+/// Convert a Chroma distance to a similarity where higher means more similar.
+///
+/// Cosine and inner-product distances are `1 - similarity`, so they are
+/// inverted directly. L2 distances are unbounded, so they map to
+/// `1 / (1 + d)` in (0, 1] instead of going negative past a distance of 1.
+pub fn distance_to_similarity(distance: f32, metric: DistanceMetric) -> f32 {
+    match metric {
+        DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+        DistanceMetric::L2 => 1.0 / (1.0 + distance),
+    }
+}
+
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// Chroma id of a chunk: unique per chunk, combining its `doc_id` and `chunk_id`.
+pub fn chroma_id(chunk: &Chunk) -> String {
+    format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id)
+}
+
+/// Metadata stored with the chunk at position `idx` of the collection's chunks.
+pub fn chunk_metadata(idx: usize, chunk: &Chunk) -> serde_json::Map<String, serde_json::Value> {
+    let mut map = serde_json::Map::new();
+    // Position in `chunks`, which `hybrid_retrieval` maps dense results back by
+    map.insert("chunk_index".to_string(), json!(idx));
+    map.insert("doc_id".to_string(), json!(chunk.doc_id));
+    map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
+    map.insert("category".to_string(), chunk.category.clone().into());
+    map
+}
+
+/// What `build_chroma_collection` does with a collection that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollectionMode {
+    /// Upsert into the existing entries. Entries whose ids the new chunks no
+    /// longer use, such as those left by a run with another chunk size, stay
+    /// and are retrieved alongside the new ones.
+    Reuse,
+    /// Delete the collection and create it anew, so it holds exactly the new chunks.
+    #[default]
+    Rebuild,
+    /// Keep the collection but delete the entries whose ids are not among the
+    /// new chunks' ids before upserting.
+    Sync,
+}
+
+impl CollectionMode {
+    /// Parse "reuse", "rebuild" or "sync", ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "reuse" => Some(CollectionMode::Reuse),
+            "rebuild" => Some(CollectionMode::Rebuild),
+            "sync" => Some(CollectionMode::Sync),
+            _ => None,
+        }
+    }
+}
+
+/// Ids listed or deleted per request when syncing a collection.
+const SYNC_PAGE_SIZE: usize = 100;
+
+/// Get or create `collection_name`, first dropping it (`Rebuild`) or the
+/// entries whose ids are not in `ids` (`Sync`), as `mode` says.
+async fn open_collection(
+    client: &ChromaClient,
     collection_name: &str,
-    embedder: &SentenceEmbedder,
+    mode: CollectionMode,
+    ids: &[String],
 ) -> Result<ChromaCollection, Box<dyn Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    if mode == CollectionMode::Rebuild
+        && client
+            .list_collections()
+            .await?
+            .iter()
+            .any(|existing| existing.name() == collection_name)
+    {
+        client.delete_collection(collection_name).await?;
+    }
     let collection = client
         .get_or_create_collection(collection_name, None)
         .await?;
 
+    if mode == CollectionMode::Sync {
+        let mut existing: Vec<String> = Vec::new();
+        loop {
+            let page = collection
+                .get(GetOptions {
+                    ids: Vec::new(),
+                    where_metadata: None,
+                    limit: Some(SYNC_PAGE_SIZE),
+                    offset: Some(existing.len()),
+                    where_document: None,
+                    include: Some(Vec::new()),
+                })
+                .await?;
+            let fetched = page.ids.len();
+            existing.extend(page.ids);
+            if fetched < SYNC_PAGE_SIZE {
+                break;
+            }
+        }
+        let stale: Vec<&str> = existing
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !ids.iter().any(|wanted| wanted == id))
+            .collect();
+        for page in stale.chunks(SYNC_PAGE_SIZE) {
+            collection.delete(Some(page.to_vec()), None, None).await?;
+        }
+    }
+    Ok(collection)
+}
+
+/// Create (or retrieve) a ChromaDB collection and upsert the full document
+/// texts, first handling existing entries according to `mode`.
+pub async fn build_chroma_collection(
+    chunks: &[Chunk],
+    collection_name: &str,
+    embedder: &dyn Embedder,
+    mode: CollectionMode,
+) -> Result<ChromaCollection, Box<dyn Error>> {
+    let ids_owned: Vec<String> = chunks.iter().map(chroma_id).collect();
+
+    let client = connect_chroma().await?;
+    let collection = open_collection(&client, collection_name, mode, &ids_owned).await?;
+
     // Skip empty collection
     if chunks.is_empty() {
         return Ok(collection);
@@ -24,22 +189,12 @@ pub async fn build_chroma_collection(
     let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
     let documents: Vec<&str> = texts.iter().map(AsRef::as_ref).collect();
 
-    // Create unique IDs by combining doc_id and chunk_id
-    let ids_owned: Vec<String> = chunks
-        .iter()
-        .map(|chunk| format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
-        .collect();
     let ids: Vec<&str> = ids_owned.iter().map(AsRef::as_ref).collect();
 
     let metadatas = chunks
         .iter()
-        .map(|chunk| {
-            let mut map = serde_json::Map::new();
-            map.insert("doc_id".to_string(), json!(chunk.doc_id));
-            map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
-            map.insert("category".to_string(), chunk.category.clone().into());
-            map
-        })
+        .enumerate()
+        .map(|(idx, chunk)| chunk_metadata(idx, chunk))
         .collect();
 
     let embeddings = embedder.embed_texts(&documents)?;
@@ -0,0 +1,52 @@
+use crate::embeddings::SentenceEmbedder;
+use std::error::Error;
+
+/// How much closer to the query the best category label must be than the
+/// runner-up, in cosine similarity, for `infer_category` to pick it.
+pub const CATEGORY_MARGIN: f32 = 0.05;
+
+/// Guess which of `known_categories` the query is about.
+///
+/// The query and every category label are embedded, and the label with the
+/// highest cosine similarity to the query wins if it beats the runner-up by
+/// at least `CATEGORY_MARGIN`. When it does not, the query is ambiguous and
+/// `None` is returned, since filtering on the wrong category would hide the
+/// relevant chunks; with a single known category there is nothing to choose
+/// between and `None` is returned as well.
+pub fn infer_category(
+    query: &str,
+    known_categories: &[String],
+    embedder: &SentenceEmbedder,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if known_categories.len() < 2 || !query.chars().any(char::is_alphanumeric) {
+        return Ok(None);
+    }
+
+    let mut texts: Vec<&str> = vec![query];
+    texts.extend(known_categories.iter().map(String::as_str));
+    let mut embeddings = embedder.embed_texts(&texts)?;
+    let query_embedding = embeddings.remove(0);
+
+    let mut scored: Vec<(&String, f32)> = known_categories
+        .iter()
+        .zip(&embeddings)
+        .map(|(category, emb)| (category, cosine_similarity(&query_embedding, emb)))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let (best, best_score) = scored[0];
+    let runner_up_score = scored[1].1;
+    Ok((best_score - runner_up_score >= CATEGORY_MARGIN).then(|| best.clone()))
+}
+
+/// Cosine similarity between two dense vectors (0.0 if either is all zeros).
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
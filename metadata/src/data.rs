@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
@@ -9,35 +11,94 @@ pub struct Chunk {
     pub chunk_id: usize,
     pub category: String,
     pub text: String,
+    /// Title of the source document, if it has one.
+    pub title: Option<String>,
     pub date: Option<String>,
+    /// Fields of the source document beyond the ones above (`author`, `url`,
+    /// `tags`, ...), copied into the Chroma metadata of every chunk.
+    pub extra: Map<String, Value>,
+}
+
+impl Chunk {
+    /// Text to embed for this chunk: its text, preceded on a document's first
+    /// chunk by the document title, so that title keywords count in
+    /// retrieval without being repeated in every chunk.
+    pub fn embedding_text(&self) -> Cow<'_, str> {
+        match &self.title {
+            Some(title) if self.chunk_id == 0 => Cow::Owned(format!("{}\n{}", title, self.text)),
+            _ => Cow::Borrowed(&self.text),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct Document {
-    id: usize,
+    /// Explicit document id; without one, loaders number it after the largest
+    /// explicit id (see `assign_doc_ids`).
+    id: Option<usize>,
+    title: Option<String>,
     content: String,
     category: Option<String>,
     date: Option<String>,
+    /// Every other field of the document, kept as is.
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 /// Splits the given text into chunks of size 'chunk_size' words.
-pub fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
+///
+/// Each chunk repeats the last `overlap` words of the previous one, so a fact
+/// that straddles a boundary appears whole in at least one chunk. An overlap
+/// of `chunk_size` or more is reduced so that each chunk still advances by
+/// one word; an overlap of 0 gives disjoint chunks.
+pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
     let words: Vec<&str> = text.split_whitespace().collect();
     let mut chunks = Vec::new();
 
-    for i in (0..words.len()).step_by(chunk_size) {
-        let end = (i + chunk_size).min(words.len());
-        let chunk = words[i..end].join(" ");
-        chunks.push(chunk);
+    // A chunk size of 0 is treated as 1 so every word still lands in a chunk
+    let chunk_size = chunk_size.max(1);
+    let step = chunk_size - overlap.min(chunk_size - 1);
+
+    let mut start = 0;
+    while start < words.len() {
+        let end = (start + chunk_size).min(words.len());
+        chunks.push(words[start..end].join(" "));
+        if end == words.len() {
+            break;
+        }
+        start += step;
     }
 
     chunks
 }
 
-/// Loads a dataset from JSON file_path, then splits each document into smaller chunks.
+/// The id of each document, in file order: its explicit `id`, or for a
+/// document without one the next number after the largest explicit id, so a
+/// fallback id never collides with an explicit one.
+fn assign_doc_ids(documents: &[Document]) -> Vec<usize> {
+    let mut next_id = documents
+        .iter()
+        .filter_map(|doc| doc.id)
+        .max()
+        .map_or(0, |max| max + 1);
+    documents
+        .iter()
+        .map(|doc| {
+            doc.id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        })
+        .collect()
+}
+
+/// Loads a dataset from JSON file_path, then splits each document into smaller,
+/// overlapping chunks (see `chunk_text`). Every chunk carries its document's
+/// date, so date filters also see the text repeated in the overlap.
 pub fn load_and_chunk_dataset(
     file_path: &str,
     chunk_size: usize,
+    overlap: usize,
 ) -> Result<Vec<Chunk>, Box<dyn Error>> {
     let file = File::open(file_path)?;
     let reader = BufReader::new(file);
@@ -45,10 +106,10 @@ pub fn load_and_chunk_dataset(
 
     let mut all_chunks = Vec::new();
 
-    for doc in documents {
+    let doc_ids = assign_doc_ids(&documents);
+    for (doc, doc_id) in documents.into_iter().zip(doc_ids) {
         let doc_category = doc.category.unwrap_or_else(|| "general".to_string());
-        let doc_id = doc.id;
-        let doc_chunks = chunk_text(&doc.content, chunk_size);
+        let doc_chunks = chunk_text(&doc.content, chunk_size, overlap);
 
         for (chunk_id, chunk_str) in doc_chunks.into_iter().enumerate() {
             all_chunks.push(Chunk {
@@ -56,10 +117,30 @@ pub fn load_and_chunk_dataset(
                 chunk_id,
                 category: doc_category.clone(),
                 text: chunk_str,
+                title: doc.title.clone(),
                 date: doc.date.clone(),
+                extra: doc.extra.clone(),
             });
         }
     }
 
     Ok(all_chunks)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_ids_follow_the_largest_explicit_id() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": 5, "content": "a"}, {"content": "b"}, {"id": 2, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&documents), [5, 6, 2, 7]);
+
+        let documents: Vec<Document> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&documents), [0, 1]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// One entry of a ranked list: an item id (a candidate index) and its retriever score.
+#[derive(Debug, Clone, Copy)]
+pub struct ItemWithScore {
+    pub id: usize,
+    pub score: f32,
+}
+
+/// How `merge_rankings` turns each ranked list into per-item contributions.
+#[derive(Debug, Clone, Copy)]
+pub enum MergeMethod {
+    /// `weight * score`. Scores should already be on a comparable scale,
+    /// e.g. normalized to [0, 1].
+    WeightedSum,
+    /// Reciprocal rank fusion: `weight / (k + rank)`, with ranks starting at 1.
+    /// Only the order of each list matters, so raw scores need no normalization.
+    Rrf { k: f32 },
+}
+
+/// An item of the merged ranking.
+#[derive(Debug, Clone)]
+pub struct MergedItem {
+    pub id: usize,
+    pub score: f32,
+}
+
+/// Merge any number of ranked lists into one.
+///
+/// List `i` is weighted by `weights[i]` (1.0 if `weights` is shorter than
+/// `rankings`). Items are deduplicated by id and their contributions summed;
+/// if an id repeats within one list, only its first (best) position counts.
+/// Lists are taken in the order given, so for `Rrf` they must be sorted best
+/// first. The result is sorted by merged score, highest first, ties broken by id.
+pub fn merge_rankings(
+    rankings: &[Vec<ItemWithScore>],
+    weights: &[f32],
+    method: MergeMethod,
+) -> Vec<MergedItem> {
+    let mut merged: HashMap<usize, f32> = HashMap::new();
+
+    for (source, ranking) in rankings.iter().enumerate() {
+        let weight = weights.get(source).copied().unwrap_or(1.0);
+        let mut seen = Vec::new();
+
+        for (pos, item) in ranking.iter().enumerate() {
+            if seen.contains(&item.id) {
+                continue;
+            }
+            seen.push(item.id);
+
+            let rank = pos + 1;
+            let contribution = match method {
+                MergeMethod::WeightedSum => weight * item.score,
+                MergeMethod::Rrf { k } => weight / (k + rank as f32),
+            };
+            *merged.entry(item.id).or_insert(0.0) += contribution;
+        }
+    }
+
+    let mut merged: Vec<MergedItem> = merged
+        .into_iter()
+        .map(|(id, score)| MergedItem { id, score })
+        .collect();
+    merged.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(Ordering::Equal)
+            .then(a.id.cmp(&b.id))
+    });
+    merged
+}
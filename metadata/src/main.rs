@@ -1,12 +1,20 @@
+mod category;
 mod data;
 mod embeddings;
+mod error;
+mod fusion;
 mod vector_db;
 
+use category::infer_category;
 use data::load_and_chunk_dataset;
 use embeddings::SentenceEmbedder;
+use fusion::MergeMethod;
 use std::env;
 use std::error::Error;
-use vector_db::{build_chroma_collection, metadata_enhanced_search};
+use vector_db::{
+    CollectionMode, LexicalFusion, apply_recency_decay, array_contains_any_clause,
+    build_chroma_collection, format_distance, metadata_enhanced_search,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -19,11 +27,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("Loading data from: {}", dataset_file.display());
 
     // Load and chunk the documents
-    let chunked_docs = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 30)?;
+    let chunked_docs = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 30, 5)?;
 
-    // Create or get collection and add documents
+    // Create the collection and add documents
+    // Start from an empty collection so chunks from runs with another chunk
+    // size never mix in; COLLECTION_MODE=sync or reuse keeps the collection
+    let mode = env::var("COLLECTION_MODE")
+        .ok()
+        .and_then(|name| CollectionMode::from_name(&name))
+        .unwrap_or_default();
     let collection =
-        build_chroma_collection(&chunked_docs, "metadata_demo_collection", &embedder).await?;
+        build_chroma_collection(&chunked_docs, "metadata_demo_collection", &embedder, mode).await?;
     println!(
         "ChromaDB collection created with {} documents.",
         collection.count().await?
@@ -34,19 +48,108 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Search WITHOUT category filtering
     println!("\n======== WITHOUT CATEGORY FILTER ========");
-    let no_filter_results =
-        metadata_enhanced_search(&collection, query_input, None, 3, &embedder).await?;
+    let no_filter_results = metadata_enhanced_search(
+        &collection,
+        query_input,
+        None,
+        None,
+        None,
+        3,
+        &embedder,
+        Some(5),
+        &LexicalFusion::default(),
+    )
+    .await?;
 
     for chunk in no_filter_results {
         println!(
-            "Doc ID: {}, Category: {}, Distance: {:.4}",
+            "Doc ID: {} ({}), Category: {}, Distance: {}",
             chunk.doc_id,
+            chunk.id,
             chunk.category.unwrap_or_else(|| "Unknown".to_string()),
-            chunk.distance
+            format_distance(chunk.distance)
         );
+        if let Some(title) = &chunk.title {
+            println!("Title: {}", title);
+        }
         println!("Chunk: {}\n", chunk.chunk);
     }
 
+    // Same search, re-ranked so that recent documents score higher (half-life of 90 days)
+    println!("\n======== WITH RECENCY DECAY (half-life 90 days) ========");
+    // Over-fetch so that recent documents just outside the plain top 3 can move up
+    let recent_results = metadata_enhanced_search(
+        &collection,
+        query_input,
+        None,
+        None,
+        None,
+        10,
+        &embedder,
+        Some(5),
+        &LexicalFusion::default(),
+    )
+    .await?;
+    let today = chrono::Local::now().date_naive();
+    for (chunk, score) in apply_recency_decay(recent_results, 90.0, today)
+        .into_iter()
+        .take(3)
+    {
+        println!(
+            "Doc ID: {}, Date: {}, Distance: {}, Decayed score: {:.4}",
+            chunk.doc_id,
+            chunk
+                .date
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            format_distance(chunk.distance),
+            score
+        );
+    }
+
+    // Let the query pick its own category filter, if one stands out
+    let mut known_categories: Vec<String> =
+        chunked_docs.iter().map(|c| c.category.clone()).collect();
+    known_categories.sort();
+    known_categories.dedup();
+    match infer_category(query_input, &known_categories, &embedder)? {
+        Some(category) => {
+            println!(
+                "\n======== WITH INFERRED CATEGORY FILTER ({}) ========",
+                category
+            );
+            let inferred_results = metadata_enhanced_search(
+                &collection,
+                query_input,
+                Some(vec![category]),
+                None,
+                None,
+                3,
+                &embedder,
+                Some(5),
+                &LexicalFusion::default(),
+            )
+            .await?;
+
+            for chunk in inferred_results {
+                println!(
+                    "Doc ID: {}, Category: {}, Distance: {}",
+                    chunk.doc_id,
+                    chunk.category.unwrap_or_else(|| "Unknown".to_string()),
+                    format_distance(chunk.distance)
+                );
+                if let Some(title) = &chunk.title {
+                    println!("Title: {}", title);
+                }
+                println!("Chunk: {}\n", chunk.chunk);
+            }
+        }
+        None => println!(
+            "\n======== NO CATEGORY INFERRED (no category clearly closest among {:?}) ========",
+            known_categories
+        ),
+    }
+
     // Search WITH a very strict category filter (demonstrating fallback)
     let very_strict_category = "NonExistentCategory";
     println!(
@@ -57,18 +160,123 @@ async fn main() -> Result<(), Box<dyn Error>> {
         &collection,
         query_input,
         Some(vec![very_strict_category.to_string()]),
+        None,
+        None,
         3,
         &embedder,
+        Some(5),
+        &LexicalFusion::default(),
     )
     .await?;
 
     for chunk in strict_filter_results {
         println!(
-            "Doc ID: {}, Category: {}, Distance: {:.4}",
+            "Doc ID: {}, Category: {}, Distance: {}",
+            chunk.doc_id,
+            chunk.category.unwrap_or_else(|| "Unknown".to_string()),
+            format_distance(chunk.distance)
+        );
+        if let Some(title) = &chunk.title {
+            println!("Title: {}", title);
+        }
+        println!("Chunk: {}\n", chunk.chunk);
+    }
+
+    // Search that leaves out whole categories
+    let excluded_categories = vec!["Technology".to_string(), "Business".to_string()];
+    println!(
+        "\n======== EXCLUDING CATEGORIES {:?} ========",
+        excluded_categories
+    );
+    let excluded_results = metadata_enhanced_search(
+        &collection,
+        query_input,
+        None,
+        Some(excluded_categories),
+        None,
+        3,
+        &embedder,
+        Some(5),
+        &LexicalFusion::default(),
+    )
+    .await?;
+
+    for chunk in excluded_results {
+        println!(
+            "Doc ID: {}, Category: {}, Distance: {}",
+            chunk.doc_id,
+            chunk.category.unwrap_or_else(|| "Unknown".to_string()),
+            format_distance(chunk.distance)
+        );
+        if let Some(title) = &chunk.title {
+            println!("Title: {}", title);
+        }
+        println!("Chunk: {}\n", chunk.chunk);
+    }
+
+    // Filter on a metadata field that only exists in the corpus file
+    let tags = ["AI", "robotics"];
+    println!("\n======== WITH ANY OF THE TAGS {:?} ========", tags);
+    let tagged_results = metadata_enhanced_search(
+        &collection,
+        query_input,
+        None,
+        None,
+        array_contains_any_clause("tags", &tags),
+        3,
+        &embedder,
+        Some(5),
+        &LexicalFusion::default(),
+    )
+    .await?;
+
+    for chunk in tagged_results {
+        println!(
+            "Doc ID: {}, Category: {}, Distance: {}",
+            chunk.doc_id,
+            chunk.category.unwrap_or_else(|| "Unknown".to_string()),
+            format_distance(chunk.distance)
+        );
+        if let Some(title) = &chunk.title {
+            println!("Title: {}", title);
+        }
+        println!("Chunk: {}\n", chunk.chunk);
+    }
+
+    // Same query with BM25 fused in, which helps when exact terms matter
+    // (HYBRID_FUSION=rrf fuses by rank instead of score)
+    let method = if env::var("HYBRID_FUSION").as_deref() == Ok("rrf") {
+        MergeMethod::Rrf { k: 60.0 }
+    } else {
+        MergeMethod::WeightedSum
+    };
+    println!(
+        "\n======== WITH BM25 FUSION (alpha 0.5, {:?}) ========",
+        method
+    );
+    let fused_results = metadata_enhanced_search(
+        &collection,
+        query_input,
+        None,
+        None,
+        None,
+        3,
+        &embedder,
+        Some(5),
+        &LexicalFusion { alpha: 0.5, method },
+    )
+    .await?;
+
+    for chunk in fused_results {
+        println!(
+            "Doc ID: {}, Category: {}, Distance: {}",
             chunk.doc_id,
             chunk.category.unwrap_or_else(|| "Unknown".to_string()),
-            chunk.distance
+            format_distance(chunk.distance)
         );
+        if let Some(title) = &chunk.title {
+            println!("Title: {}", title);
+        }
         println!("Chunk: {}\n", chunk.chunk);
     }
 
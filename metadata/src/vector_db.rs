@@ -1,69 +1,398 @@
 use crate::data::Chunk;
 use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
+use crate::fusion::{ItemWithScore, MergeMethod, merge_rankings};
+use bm25::{Embedder, EmbedderBuilder, Embedding, Language};
 use chromadb::client::{ChromaClient, ChromaClientOptions};
-use chromadb::collection::{ChromaCollection, CollectionEntries, QueryOptions};
-use serde_json::json;
+use chromadb::collection::{
+    ChromaCollection, CollectionEntries, GetOptions, QueryOptions, QueryResult,
+};
+use chrono::NaiveDate;
+use serde_json::{Value, json};
+
+/// Optional BM25 re-ranking of the dense candidates in `metadata_enhanced_search`.
+#[derive(Debug, Clone, Copy)]
+pub struct LexicalFusion {
+    /// Weight on the BM25 score in [0, 1]; 0.0 keeps the pure dense order.
+    pub alpha: f32,
+    /// How the BM25 and dense rankings are merged.
+    pub method: MergeMethod,
+}
+
+impl Default for LexicalFusion {
+    /// Pure dense retrieval.
+    fn default() -> Self {
+        LexicalFusion {
+            alpha: 0.0,
+            method: MergeMethod::WeightedSum,
+        }
+    }
+}
 
 pub struct RetrievedChunk {
+    /// Chroma id of the entry, for follow-up updates or deletes.
+    pub id: String,
     pub chunk: String,
     pub doc_id: usize,
-    pub distance: f32,
+    /// Distance from the query, or `None` if Chroma returned none for this
+    /// chunk; an unknown distance counts as no similarity at all.
+    pub distance: Option<f32>,
     pub category: Option<String>,
+    /// Title of the source document, if it had one.
+    pub title: Option<String>,
+    /// Publication date of the source document, if it had a valid `YYYY-MM-DD` date.
+    pub date: Option<NaiveDate>,
+}
+
+/// Common English words that carry no meaning on their own; a query made only
+/// of these is treated as empty.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "is", "in", "of", "to", "a", "an", "that", "for", "on", "with", "as", "it", "by",
+    "this", "are", "was", "at", "from", "or", "be", "which", "not", "can", "also", "have", "has",
+    "had", "we", "they", "you", "he", "she", "his", "her", "its", "our", "us", "their", "them",
+    "i", "do", "does", "did", "just", "so", "if", "may", "will", "shall", "more", "most", "some",
+    "many", "any", "all", "what", "about", "would", "could", "should", "where", "when", "why",
+    "how",
+];
+
+/// True if `query` has no words but stopwords, e.g. "", "   " or "what is
+/// the". Embedding such a query would return arbitrary nearest neighbours.
+fn is_blank_query(query: &str) -> bool {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOPWORDS.contains(&word.to_lowercase().as_str()))
 }
 
+/// Search the collection, optionally restricted to `categories`.
+///
+/// If the category filter leaves no results, the search is repeated without
+/// it. With `min_chunk_words` set, chunks shorter than that many words are
+/// dropped (over-fetching to compensate); if that empties a filtered search,
+/// the same fallback applies.
+///
+/// Categories in `exclude_categories` never appear in the results, fallback
+/// included. A category that is both included and excluded is excluded, with
+/// a warning; if that leaves no category to include, nothing is returned.
+///
+/// A query of nothing but stopwords (see `is_blank_query`) fails with
+/// `RagError::EmptyQuery` before anything is embedded.
+///
+/// `extra_where` is a further Chroma `where` filter on any metadata field
+/// (for example `array_contains_any_clause("tags", &["AI"])`). Unlike the
+/// category filter it is kept in the fallback search.
+///
+/// With `lexical.alpha > 0`, more candidates are fetched and re-ranked by
+/// fusing a BM25 score, computed over those (already filtered) candidates
+/// only, with the dense similarity. `LexicalFusion::default()` keeps the pure
+/// dense order.
+#[allow(clippy::too_many_arguments)]
 pub async fn metadata_enhanced_search(
     collection: &ChromaCollection,
     query: &str,
     categories: Option<Vec<String>>,
+    exclude_categories: Option<Vec<String>>,
+    extra_where: Option<Value>,
     top_k: usize,
     embedder: &SentenceEmbedder,
+    min_chunk_words: Option<usize>,
+    lexical: &LexicalFusion,
 ) -> Result<Vec<RetrievedChunk>, Box<dyn std::error::Error>> {
+    if is_blank_query(query) {
+        return Err(RagError::EmptyQuery.into());
+    }
+
+    let excluded = exclude_categories.unwrap_or_default();
+
+    // Exclusion wins over inclusion
+    let categories = match categories {
+        Some(cats) => {
+            let (conflicting, kept): (Vec<String>, Vec<String>) =
+                cats.into_iter().partition(|c| excluded.contains(c));
+            if !conflicting.is_empty() {
+                eprintln!(
+                    "Warning: categories {:?} are both included and excluded; excluding them",
+                    conflicting
+                );
+            }
+            if kept.is_empty() {
+                return Ok(Vec::new());
+            }
+            Some(kept)
+        }
+        None => None,
+    };
+
     // Create query embedding
     let query_embedding = embedder.embed_texts(&[query])?;
 
-    // Create where_clause using as_ref to avoid moving categories
-    let where_clause = categories.as_ref().map(|cats| {
-        json!({
-            "category": { "$in": cats }
-        })
-        .as_object()
-        .cloned()
-        .unwrap_or_default()
-    });
+    let where_clause = and_clauses(
+        category_where_clause(categories.as_deref(), &excluded),
+        extra_where.clone(),
+    );
+
+    // Request more results when short chunks will be filtered out, and a wider
+    // candidate pool when BM25 may reorder it
+    let fetch_n = if lexical.alpha > 0.0 {
+        top_k * 5
+    } else if min_chunk_words.is_some() {
+        top_k * 3
+    } else {
+        top_k
+    };
 
     // Build initial QueryOptions
     let query_options = QueryOptions {
         query_texts: None,
         query_embeddings: Some(query_embedding.clone()),
-        n_results: Some(top_k),
-        where_metadata: where_clause.map(serde_json::Value::Object),
+        n_results: Some(fetch_n),
+        where_metadata: where_clause,
         where_document: None,
         include: Some(vec!["documents", "distances", "metadatas"]),
     };
 
     // Execute initial query
-    let mut result = collection.query(query_options, None).await?;
-
-    // Check if initial search returned no results
-    if categories.is_some()
-        && result
-            .documents
-            .as_ref()
-            .and_then(|d| d.first())
-            .map_or(true, |docs| docs.is_empty())
-    {
-        // Perform fallback search without filter
+    let result = collection.query(query_options, None).await?;
+    let mut chunks = collect_chunks(result, min_chunk_words);
+
+    // Check if initial search returned no (long enough) results
+    if categories.is_some() && chunks.is_empty() {
+        // Perform fallback search without the inclusive filter
         let fallback_options = QueryOptions {
             query_texts: None,
             query_embeddings: Some(query_embedding),
-            n_results: Some(top_k),
-            where_metadata: None,
+            n_results: Some(fetch_n),
+            where_metadata: and_clauses(category_where_clause(None, &excluded), extra_where),
             where_document: None,
             include: Some(vec!["documents", "distances", "metadatas"]),
         };
-        result = collection.query(fallback_options, None).await?;
+        let result = collection.query(fallback_options, None).await?;
+        chunks = collect_chunks(result, min_chunk_words);
+    }
+
+    if lexical.alpha > 0.0 {
+        chunks = fuse_with_bm25(query, chunks, lexical.alpha, lexical.method);
+    }
+
+    chunks.truncate(top_k);
+    Ok(chunks)
+}
+
+/// A distance for display: four decimals, or "unknown".
+pub fn format_distance(distance: Option<f32>) -> String {
+    distance.map_or_else(|| "unknown".to_string(), |d| format!("{:.4}", d))
+}
+
+/// `1 / (1 + distance)`, or 0.0 when the distance is unknown.
+fn similarity(distance: Option<f32>) -> f32 {
+    distance.map_or(0.0, |d| 1.0 / (1.0 + d))
+}
+
+/// Re-rank dense candidates by fusing their similarity with a BM25 score.
+///
+/// BM25 is fitted to the candidate texts alone and min-max normalized to
+/// [0, 1]; the dense score is `1 / (1 + distance)`. Candidates with no query
+/// term in common keep only their dense contribution, and candidates without
+/// a distance only their BM25 one.
+fn fuse_with_bm25(
+    query: &str,
+    chunks: Vec<RetrievedChunk>,
+    alpha: f32,
+    fusion: MergeMethod,
+) -> Vec<RetrievedChunk> {
+    if chunks.is_empty() {
+        return chunks;
+    }
+
+    let corpus: Vec<String> = chunks.iter().map(|c| c.chunk.to_lowercase()).collect();
+    let corpus_refs: Vec<&str> = corpus.iter().map(String::as_str).collect();
+    let bm25: Embedder =
+        EmbedderBuilder::with_fit_to_corpus(Language::English, &corpus_refs).build();
+    let query_embedding = bm25.embed(&query.to_lowercase());
+    let b_scores: Vec<f32> = corpus_refs
+        .iter()
+        .map(|text| dot(&query_embedding, &bm25.embed(text)))
+        .collect();
+
+    let (b_min, b_max) = b_scores
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(mn, mx), &v| {
+            (mn.min(v), mx.max(v))
+        });
+    let denom = (b_max - b_min).max(f32::EPSILON);
+
+    // Chroma already returned the candidates most similar first
+    let dense_ranking: Vec<ItemWithScore> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.distance.is_some())
+        .map(|(i, chunk)| ItemWithScore {
+            id: i,
+            score: similarity(chunk.distance),
+        })
+        .collect();
+    let mut bm25_ranking: Vec<ItemWithScore> = b_scores
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, b_raw)| b_raw > 0.0)
+        .map(|(i, b_raw)| ItemWithScore {
+            id: i,
+            score: (b_raw - b_min) / denom,
+        })
+        .collect();
+    bm25_ranking.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let merged = merge_rankings(
+        &[bm25_ranking, dense_ranking],
+        &[alpha, 1.0 - alpha],
+        fusion,
+    );
+
+    let mut slots: Vec<Option<RetrievedChunk>> = chunks.into_iter().map(Some).collect();
+    merged
+        .into_iter()
+        .filter_map(|item| slots.get_mut(item.id).and_then(Option::take))
+        .collect()
+}
+
+/// Dot product of two sparse BM25 embeddings.
+fn dot(a: &Embedding, b: &Embedding) -> f32 {
+    a.0.iter()
+        .map(|q| {
+            b.0.iter()
+                .filter(|d| d.index == q.index)
+                .map(|d| q.value * d.value)
+                .sum::<f32>()
+        })
+        .sum()
+}
+
+/// Metadata keys set by `build_chroma_collection` itself; extra document
+/// fields with these names are dropped rather than overwrite them.
+const RESERVED_METADATA_KEYS: [&str; 5] = ["doc_id", "chunk_id", "category", "date", "title"];
+
+/// Copy one extra document field into Chroma metadata, which only holds
+/// strings, numbers and booleans.
+///
+/// An array of scalars such as `"tags": ["AI", "robotics"]` is stored twice:
+/// joined into one string under its own key, for display, and as one `true`
+/// flag per element (`"tags:AI"`, `"tags:robotics"`), for filtering with
+/// `array_contains_any_clause`. Objects and mixed arrays are stored as their
+/// JSON text, and nulls are skipped.
+fn insert_extra_metadata(map: &mut serde_json::Map<String, Value>, key: &str, value: &Value) {
+    if RESERVED_METADATA_KEYS.contains(&key) {
+        return;
+    }
+    match value {
+        Value::Null => {}
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => {
+            map.insert(key.to_string(), value.clone());
+        }
+        Value::Array(items) if items.iter().all(|item| scalar_text(item).is_some()) => {
+            let texts: Vec<String> = items.iter().filter_map(scalar_text).collect();
+            for text in &texts {
+                map.insert(format!("{}:{}", key, text), Value::Bool(true));
+            }
+            map.insert(key.to_string(), texts.join(", ").into());
+        }
+        _ => {
+            map.insert(key.to_string(), value.to_string().into());
+        }
+    }
+}
+
+/// The text of a string, number or boolean.
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Chroma `where` filter matching chunks whose array field `field` contains
+/// any of `values`: the `$in` of a scalar field, for the per-element flags
+/// that `build_chroma_collection` stores for arrays. `None` if `values` is empty.
+pub fn array_contains_any_clause(field: &str, values: &[&str]) -> Option<Value> {
+    let mut clauses: Vec<Value> = values
+        .iter()
+        .map(|value| json!({ format!("{}:{}", field, value): { "$eq": true } }))
+        .collect();
+    match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(json!({ "$or": clauses })),
+    }
+}
+
+/// Build the Chroma `where` filter for the included and excluded categories.
+///
+/// Each non-empty list becomes one clause (`$in` / `$nin`); several clauses are
+/// combined with `$and`. Returns `None` when there is nothing to filter on.
+fn category_where_clause(include: Option<&[String]>, exclude: &[String]) -> Option<Value> {
+    let mut clauses = Vec::new();
+    if let Some(cats) = include {
+        clauses.push(json!({ "category": { "$in": cats } }));
+    }
+    if !exclude.is_empty() {
+        clauses.push(json!({ "category": { "$nin": exclude } }));
+    }
+
+    match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(json!({ "$and": clauses })),
+    }
+}
+
+/// Combine two optional `where` filters with `$and`.
+fn and_clauses(a: Option<Value>, b: Option<Value>) -> Option<Value> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(json!({ "$and": [a, b] })),
+        (a, b) => a.or(b),
     }
+}
+
+/// Re-score chunks so that recent documents rank higher.
+///
+/// Each chunk's similarity (`1 / (1 + distance)`, for the collection's default
+/// L2 space) is multiplied by `0.5^(age_days / half_life_days)`, where the age is
+/// counted from the chunk's date to `now`. Chunks without a date, or dated after
+/// `now`, keep a multiplier of 1.0, as does every chunk if `half_life_days` is
+/// not positive. A chunk without a distance scores 0.0. Returns the chunks
+/// with their decayed scores, highest first.
+pub fn apply_recency_decay(
+    chunks: Vec<RetrievedChunk>,
+    half_life_days: f32,
+    now: NaiveDate,
+) -> Vec<(RetrievedChunk, f32)> {
+    let mut scored: Vec<(RetrievedChunk, f32)> = chunks
+        .into_iter()
+        .map(|chunk| {
+            let similarity = similarity(chunk.distance);
+            let decay = match chunk.date {
+                Some(date) if half_life_days > 0.0 => {
+                    let age_days = (now - date).num_days().max(0) as f32;
+                    0.5f32.powf(age_days / half_life_days)
+                }
+                _ => 1.0,
+            };
+            (chunk, similarity * decay)
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored
+}
 
+/// Turn the first result group into `RetrievedChunk`s, dropping chunks shorter
+/// than `min_chunk_words` words.
+fn collect_chunks(result: QueryResult, min_chunk_words: Option<usize>) -> Vec<RetrievedChunk> {
     // Process results
     let documents = result
         .documents
@@ -80,10 +409,16 @@ pub async fn metadata_enhanced_search(
         .and_then(|m| m.first().cloned())
         .unwrap_or_default();
 
-    Ok(documents
+    let ids = result.ids.first().cloned().unwrap_or_default();
+
+    documents
         .iter()
         .enumerate()
+        .filter(|(_, chunk)| {
+            min_chunk_words.is_none_or(|min| chunk.split_whitespace().count() >= min)
+        })
         .map(|(i, chunk)| RetrievedChunk {
+            id: ids.get(i).cloned().unwrap_or_default(),
             chunk: chunk.clone(),
             doc_id: metadatas
                 .get(i)
@@ -98,22 +433,148 @@ pub async fn metadata_enhanced_search(
                 .and_then(|m| m.get("category"))
                 .and_then(|v| v.as_str())
                 .map(String::from),
-            distance: distances.get(i).copied().unwrap_or(0.0),
+            title: metadatas
+                .get(i)
+                .and_then(|m| m.as_ref())
+                .and_then(|m| m.get("title"))
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            date: metadatas
+                .get(i)
+                .and_then(|m| m.as_ref())
+                .and_then(|m| m.get("date"))
+                .and_then(|v| v.as_str())
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()),
+            distance: distances.get(i).copied(),
         })
-        .collect())
+        .collect()
 }
 
-/// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
-pub async fn build_chroma_collection(
-    chunks: &[Chunk],
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// What `build_chroma_collection` does with a collection that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollectionMode {
+    /// Upsert into the existing entries. Entries whose ids the new chunks no
+    /// longer use, such as those left by a run with another chunk size, stay
+    /// and are retrieved alongside the new ones.
+    Reuse,
+    /// Delete the collection and create it anew, so it holds exactly the new chunks.
+    #[default]
+    Rebuild,
+    /// Keep the collection but delete the entries whose ids are not among the
+    /// new chunks' ids before upserting.
+    Sync,
+}
+
+impl CollectionMode {
+    /// Parse "reuse", "rebuild" or "sync", ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "reuse" => Some(CollectionMode::Reuse),
+            "rebuild" => Some(CollectionMode::Rebuild),
+            "sync" => Some(CollectionMode::Sync),
+            _ => None,
+        }
+    }
+}
+
+/// Ids listed or deleted per request when syncing a collection.
+const SYNC_PAGE_SIZE: usize = 100;
+
+/// Get or create `collection_name`, first dropping it (`Rebuild`) or the
+/// entries whose ids are not in `ids` (`Sync`), as `mode` says.
+async fn open_collection(
+    client: &ChromaClient,
     collection_name: &str,
-    embedder: &SentenceEmbedder,
+    mode: CollectionMode,
+    ids: &[String],
 ) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    if mode == CollectionMode::Rebuild
+        && client
+            .list_collections()
+            .await?
+            .iter()
+            .any(|existing| existing.name() == collection_name)
+    {
+        client.delete_collection(collection_name).await?;
+    }
     let collection = client
         .get_or_create_collection(collection_name, None)
         .await?;
 
+    if mode == CollectionMode::Sync {
+        let mut existing: Vec<String> = Vec::new();
+        loop {
+            let page = collection
+                .get(GetOptions {
+                    ids: Vec::new(),
+                    where_metadata: None,
+                    limit: Some(SYNC_PAGE_SIZE),
+                    offset: Some(existing.len()),
+                    where_document: None,
+                    include: Some(Vec::new()),
+                })
+                .await?;
+            let fetched = page.ids.len();
+            existing.extend(page.ids);
+            if fetched < SYNC_PAGE_SIZE {
+                break;
+            }
+        }
+        let stale: Vec<&str> = existing
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !ids.iter().any(|wanted| wanted == id))
+            .collect();
+        for page in stale.chunks(SYNC_PAGE_SIZE) {
+            collection.delete(Some(page.to_vec()), None, None).await?;
+        }
+    }
+    Ok(collection)
+}
+
+/// Create (or retrieve) a ChromaDB collection and upsert the full document
+/// texts, first handling existing entries according to `mode`.
+pub async fn build_chroma_collection(
+    chunks: &[Chunk],
+    collection_name: &str,
+    embedder: &SentenceEmbedder,
+    mode: CollectionMode,
+) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
+    // Create unique IDs by combining doc_id and chunk_id
+    let ids_owned: Vec<String> = chunks
+        .iter()
+        .map(|chunk| format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
+        .collect();
+
+    let client = connect_chroma().await?;
+    let collection = open_collection(&client, collection_name, mode, &ids_owned).await?;
+
     // Skip empty collection
     if chunks.is_empty() {
         return Ok(collection);
@@ -122,11 +583,6 @@ pub async fn build_chroma_collection(
     let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
     let documents: Vec<&str> = texts.iter().map(AsRef::as_ref).collect();
 
-    // Create unique IDs by combining doc_id and chunk_id
-    let ids_owned: Vec<String> = chunks
-        .iter()
-        .map(|chunk| format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
-        .collect();
     let ids: Vec<&str> = ids_owned.iter().map(AsRef::as_ref).collect();
 
     let metadatas = chunks
@@ -136,11 +592,23 @@ pub async fn build_chroma_collection(
             map.insert("doc_id".to_string(), json!(chunk.doc_id));
             map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
             map.insert("category".to_string(), chunk.category.clone().into());
+            if let Some(date) = &chunk.date {
+                map.insert("date".to_string(), date.clone().into());
+            }
+            if let Some(title) = &chunk.title {
+                map.insert("title".to_string(), title.clone().into());
+            }
+            for (key, value) in &chunk.extra {
+                insert_extra_metadata(&mut map, key, value);
+            }
             map
         })
         .collect();
 
-    let embeddings = embedder.embed_texts(&documents)?;
+    // Embed each chunk with its document title on the first chunk
+    let embedding_texts: Vec<_> = chunks.iter().map(Chunk::embedding_text).collect();
+    let embedding_refs: Vec<&str> = embedding_texts.iter().map(AsRef::as_ref).collect();
+    let embeddings = embedder.embed_texts(&embedding_refs)?;
 
     let entries = CollectionEntries {
         ids,
@@ -152,3 +620,16 @@ pub async fn build_chroma_collection(
     collection.upsert(entries, None).await?;
     Ok(collection)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_whitespace_and_stopword_queries_are_blank() {
+        for query in ["", "   ", "what is the?"] {
+            assert!(is_blank_query(query), "{query:?}");
+        }
+        assert!(!is_blank_query("what is the NVDA price"));
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Errors the pipeline reports with a typed cause instead of a raw client error.
+pub enum RagError {
+    /// The Chroma server could not be reached at `url`.
+    VectorDbUnavailable { url: String, reason: String },
+    /// A prompt template uses `{placeholder}` but no value was given for it.
+    MissingPromptValue { placeholder: String },
+}
+
+impl fmt::Display for RagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RagError::VectorDbUnavailable { url, reason } => write!(
+                f,
+                "could not reach ChromaDB at {} ({}).\n\
+                 Start a local server with `docker run -p 8000:8000 chromadb/chroma` and try again.",
+                url, reason
+            ),
+            RagError::MissingPromptValue { placeholder } => write!(
+                f,
+                "prompt template placeholder {{{}}} has no value",
+                placeholder
+            ),
+        }
+    }
+}
+
+// `main` prints returned errors with `{:?}`, so show the actionable message there too.
+impl fmt::Debug for RagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RagError {}
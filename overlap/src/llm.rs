@@ -5,12 +5,73 @@ use async_openai::types::{
     ChatCompletionRequestUserMessageContent, CreateChatCompletionRequestArgs,
 };
 use async_openai::Client;
+use async_trait::async_trait;
+use crate::prompt::render_builtin;
 use dotenv::dotenv;
+use std::collections::VecDeque;
 use std::env;
+use std::sync::Mutex;
+
+/// Reply used by the generation functions when there is no context to answer from.
+pub const DEFAULT_NO_CONTEXT_MESSAGE: &str = "I'm sorry, but I couldn't find any relevant information.";
+
+/// A chat model that answers prompts.
+///
+/// `LlmClient` calls the OpenAI API; `MockLlm` answers offline, so generation
+/// logic can run without an API key or network access.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// Reply to give instead of calling the model when the context is empty.
+    fn no_context_message(&self) -> &str {
+        DEFAULT_NO_CONTEXT_MESSAGE
+    }
+
+    /// Like `get_llm_response`, but uses `system` as the system message for this call only.
+    async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Offline `LlmBackend` for tests and demos.
+///
+/// Returns the canned responses in order, one per call; once they run out
+/// (or if none were given) it echoes the prompt back. System messages are ignored.
+pub struct MockLlm {
+    responses: Mutex<VecDeque<String>>,
+}
+
+impl MockLlm {
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlm {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let canned = self.responses.lock().unwrap().pop_front();
+        Ok(canned.unwrap_or_else(|| prompt.to_string()))
+    }
+
+    async fn get_llm_response_with_system(
+        &self,
+        _system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response(prompt).await
+    }
+}
 
 pub struct LlmClient {
     client: Client<OpenAIConfig>,
     system_prompt: String,
+    no_context_message: String,
 }
 
 impl LlmClient {
@@ -27,33 +88,21 @@ impl LlmClient {
         Self {
             client: Client::with_config(config),
             system_prompt: "You are a helpful AI assistant. You always answer to the user's queries.".to_string(),
+            no_context_message: DEFAULT_NO_CONTEXT_MESSAGE.to_string(),
         }
     }
 
-    /// Generates answers based on context
-    ///
-    /// Checks if context is empty, then create a prompt with query and context
-    /// If context is empty or whitespace-only, returns a default message
-    /// Otherwise, formulates the prompt with query and context and generates a response using get_llm_response
-    pub async fn generate_final_answer(
-        &self,
-        query: &str,
-        context: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        // Handle empty or whitespace-only context
-        if context.trim().is_empty() {
-            return Ok("I'm sorry, but I couldn't find any relevant information.".to_string());
-        }
-
-        // Formulate the prompt with query and context
-        let prompt = format!(
-            "Question: {}\nContext:\n{}\nAnswer:",
-            query, context
-        );
+    /// Use `message` instead of `DEFAULT_NO_CONTEXT_MESSAGE` when there is no context.
+    pub fn with_no_context_message(mut self, message: impl Into<String>) -> Self {
+        self.no_context_message = message.into();
+        self
+    }
+}
 
-        // Generate response using get_llm_response
-        let response = self.get_llm_response(&prompt).await?;
-        Ok(response)
+#[async_trait]
+impl LlmBackend for LlmClient {
+    fn no_context_message(&self) -> &str {
+        &self.no_context_message
     }
 
     /// Generates a response using the given prompt with the LLM client.
@@ -63,10 +112,18 @@ impl LlmClient {
     /// It then creates a `CreateChatCompletionRequest` with the two messages, and
     /// calls the `chat().create()` method of the client to generate a response.
     /// The response is then extracted from the result and returned as a string.
-    pub async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // Build messages using the default system prompt.
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response_with_system(&self.system_prompt, prompt).await
+    }
+
+    async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        // Build messages using the given system prompt.
         let system_message = ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(self.system_prompt.clone()),
+            content: ChatCompletionRequestSystemMessageContent::Text(system.to_string()),
             name: None,
         };
 
@@ -99,3 +156,48 @@ impl LlmClient {
         Ok(answer)
     }
 }
+
+/// Generates answers based on context
+///
+/// Checks if context is empty, then create a prompt with query and context
+/// If context is empty or whitespace-only, returns the backend's `no_context_message`
+/// Otherwise, formulates the prompt with query and context and generates a response using get_llm_response
+pub async fn generate_final_answer(
+    llm: &dyn LlmBackend,
+    query: &str,
+    context: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Handle empty or whitespace-only context
+    if context.trim().is_empty() {
+        return Ok(llm.no_context_message().to_string());
+    }
+
+    // Formulate the prompt with query and context
+    let prompt = render_builtin("answer", &[("query", query), ("context", context)]);
+
+    // Generate response using get_llm_response
+    let response = llm.get_llm_response(&prompt).await?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn final_answer_prompt_carries_query_and_context() {
+        // No canned replies: the mock echoes the prompt back
+        let llm = MockLlm::new(Vec::new());
+        let prompt = generate_final_answer(&llm, "What grew?", "Revenue grew 12%.")
+            .await
+            .unwrap();
+        assert_eq!(prompt, "Question: What grew?\nContext:\nRevenue grew 12%.\nAnswer:");
+    }
+
+    #[tokio::test]
+    async fn blank_context_skips_the_model() {
+        let llm = MockLlm::new(vec!["should not be used".to_string()]);
+        let answer = generate_final_answer(&llm, "What grew?", "  \n ").await.unwrap();
+        assert_eq!(answer, llm.no_context_message());
+    }
+}
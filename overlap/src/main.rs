@@ -1,16 +1,21 @@
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 mod summary;
 mod llm;
+mod prompt;
+mod sentences;
 
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
-use data::load_and_chunk_dataset;
+use data::{load_and_chunk_dataset, Chunk};
 use embeddings::SentenceEmbedder;
-use vector_db::build_chroma_collection;
-use llm::LlmClient;
-use summary::{are_chunks_overlapping, summarize_chunks};
+use vector_db::{build_chroma_collection, build_chroma_collection_returning_embeddings, chunk_ids};
+use llm::{generate_final_answer, LlmBackend, LlmClient, MockLlm};
+use sentences::{SentenceSplitter, CJK_TERMINATORS};
+use summary::{build_context, ContextPolicy, SimilarityMeasure, SummaryMode};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -19,13 +24,29 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("Loading data from: {}", dataset_file.display());
     let chunks = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 40)?;
 
-    // 2) Build collection
+    // 2) Build collection, keeping the chunk embeddings when the overlap check needs them
+    // (OVERLAP_METRIC=semantic compares the chunk embeddings instead of their words)
+    let semantic_overlap = env::var("OVERLAP_METRIC").as_deref() == Ok("semantic");
     let embedder = SentenceEmbedder::new().await?;
-    let collection = build_chroma_collection(&chunks, "summary_demo_collection", &embedder).await?;
+    let (collection, chunk_embeddings) = if semantic_overlap {
+        build_chroma_collection_returning_embeddings(&chunks, "summary_demo_collection", &embedder).await?
+    } else {
+        (build_chroma_collection(&chunks, "summary_demo_collection", &embedder).await?, Vec::new())
+    };
     println!("Collection has {} documents.", collection.count().await?);
 
     // 3) Query top 5
-    let llm = LlmClient::new();
+    // LLM_BACKEND=mock echoes prompts instead of calling OpenAI (no API key needed)
+    let llm: Box<dyn LlmBackend> = if env::var("LLM_BACKEND").as_deref() == Ok("mock") {
+        Box::new(MockLlm::new(Vec::new()))
+    } else {
+        // NO_CONTEXT_MESSAGE overrides the reply given when nothing relevant is retrieved
+        let mut client = LlmClient::new();
+        if let Ok(message) = env::var("NO_CONTEXT_MESSAGE") {
+            client = client.with_no_context_message(message);
+        }
+        Box::new(client)
+    };
     let query = "Provide an overview of our internal policies.";
     let query_embeddings = embedder.embed_texts(&[query])?;
     let opts = chromadb::collection::QueryOptions {
@@ -41,22 +62,69 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .and_then(|g| g.into_iter().next())
         .unwrap_or_default();
 
+    // Recover each retrieved chunk, and its embedding when they were kept, from its
+    // Chroma id instead of re-embedding
+    let index_by_id: HashMap<String, usize> = chunk_ids(&chunks)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, id)| (id, idx))
+        .collect();
+    let mut retrieved: Vec<Chunk> = Vec::new();
+    let mut doc_embeddings: Vec<Vec<f32>> = Vec::new();
+    for id in result.ids.first().into_iter().flatten() {
+        match index_by_id.get(id) {
+            Some(&idx) => {
+                retrieved.push(chunks[idx].clone());
+                if let Some(embedding) = chunk_embeddings.get(idx) {
+                    doc_embeddings.push(embedding.clone());
+                }
+            }
+            None => eprintln!(
+                "Retrieved chunk {} is not in the local dataset; it is left out of the context.",
+                id
+            ),
+        }
+    }
+
     if docs.is_empty() {
         println!("No chunks were retrieved for the query.");
-        println!("Final answer:\nNo relevant information found.");
+        println!("Final answer:\n{}", llm.no_context_message());
         return Ok(());
     }
 
     // 4) Decide summary vs list
-    let texts: Vec<String> = docs.into_iter().collect();
-    let context = if texts.len() > 3 || are_chunks_overlapping(&texts, 0.8) {
-        summarize_chunks(&llm, &texts).await?
-    } else {
-        texts.into_iter().map(|t| format!("- {}", t)).collect::<Vec<_>>().join("\n")
+    // OVERLAP_THRESHOLD and MAX_LISTED_CHUNKS tune when the chunks are summarized
+    // rather than listed (semantic overlap defaults to a stricter 0.9)
+    let default_policy = ContextPolicy::default();
+    let policy = ContextPolicy {
+        overlap_threshold: env::var("OVERLAP_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(if semantic_overlap { 0.9 } else { default_policy.overlap_threshold }),
+        max_listed_chunks: env::var("MAX_LISTED_CHUNKS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_policy.max_listed_chunks),
+        // OVERLAP_MEASURE=jaccard scores word overlap more strictly than the default, dice more loosely
+        measure: match env::var("OVERLAP_MEASURE").as_deref() {
+            Ok("jaccard") => SimilarityMeasure::Jaccard,
+            Ok("dice") => SimilarityMeasure::Dice,
+            _ => default_policy.measure,
+        },
+    };
+    // SUMMARY_MODE=extractive quotes the best sentences instead of asking the LLM to rewrite them
+    // (cutting at Chinese and Japanese sentence ends too)
+    let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+    let mode = match env::var("SUMMARY_MODE").as_deref() {
+        Ok("extractive") => SummaryMode::Extractive { embedder: &embedder, max_sentences: 5, splitter: &splitter },
+        _ => SummaryMode::Abstractive,
     };
+    let chunk_embeddings = semantic_overlap.then_some(doc_embeddings.as_slice());
+    let (context, kind) = build_context(llm.as_ref(), &retrieved, query, mode, &policy, chunk_embeddings).await?;
+    println!("Context built as: {:?}", kind);
 
     // 5) Final answer
-    let final_answer = llm.generate_final_answer(query, &context).await?;
+    let final_answer = generate_final_answer(llm.as_ref(), query, &context).await?;
     println!("Final answer:\n{}", final_answer);
 
     Ok(())
@@ -0,0 +1,117 @@
+use crate::error::RagError;
+use std::collections::HashMap;
+
+/// Answer from the retrieved (and possibly summarized) context.
+const ANSWER: &str = "Question: {query}\nContext:\n{context}\nAnswer:";
+
+/// Condense the retrieved chunks; "Summary not possible" makes the caller
+/// fall back to the full text.
+const SUMMARIZE: &str = "Please generate a concise summary of the following text.\n\
+    Do not omit critical details that might answer the user's query.\n\
+    If you cannot produce a meaningful summary, just say 'Summary not possible'.\n\n\
+    Text:\n{text}\n\nSummary:";
+
+/// A prompt with named `{placeholder}`s, filled in by `render`.
+///
+/// A placeholder is a brace-enclosed name made of letters, digits and
+/// underscores; any other brace is kept as is.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    template: String,
+}
+
+impl PromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// Look up a built-in template: "answer" or "summarize".
+    pub fn builtin(name: &str) -> Option<Self> {
+        let template = match name {
+            "answer" => ANSWER,
+            "summarize" => SUMMARIZE,
+            _ => return None,
+        };
+        Some(Self::new(template))
+    }
+
+    /// Replace every placeholder with its value from `vars`.
+    ///
+    /// Fails with `RagError::MissingPromptValue` if a placeholder has no value,
+    /// rather than sending the literal `{name}` to the model. Extra entries in
+    /// `vars` are ignored.
+    pub fn render(&self, vars: &HashMap<&str, &str>) -> Result<String, RagError> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(open) = rest.find('{') {
+            rendered.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+
+            let name_len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if name_len > 0 && after[name_len..].starts_with('}') {
+                let name = &after[..name_len];
+                let value = vars.get(name).ok_or_else(|| RagError::MissingPromptValue {
+                    placeholder: name.to_string(),
+                })?;
+                rendered.push_str(value);
+                rest = &after[name_len + 1..];
+            } else {
+                // Not a placeholder: keep the brace literally
+                rendered.push('{');
+                rest = after;
+            }
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
+
+/// Render the built-in template `name` with `vars`.
+///
+/// Panics if there is no such template or `vars` lacks one of its
+/// placeholders; either is a mistake in this crate, not in its input.
+pub fn render_builtin(name: &str, vars: &[(&str, &str)]) -> String {
+    PromptTemplate::builtin(name)
+        .unwrap_or_else(|| panic!("no built-in prompt template {:?}", name))
+        .render(&vars.iter().copied().collect())
+        .unwrap_or_else(|e| panic!("built-in prompt template {:?}: {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled_and_other_braces_kept() {
+        let template = PromptTemplate::new("{query} in {context} {not a placeholder} {}");
+        let vars = HashMap::from([("query", "q"), ("context", "c {query}")]);
+        assert_eq!(
+            template.render(&vars).unwrap(),
+            "q in c {query} {not a placeholder} {}"
+        );
+    }
+
+    #[test]
+    fn a_missing_value_is_an_error() {
+        let template = PromptTemplate::new("Answer: {query}");
+        assert!(matches!(
+            template.render(&HashMap::new()),
+            Err(RagError::MissingPromptValue { placeholder }) if placeholder == "query"
+        ));
+    }
+
+    #[test]
+    fn answer_template_renders_as_before() {
+        let prompt = render_builtin(
+            "answer",
+            &[("query", "What grew?"), ("context", "Revenue.")],
+        );
+        assert_eq!(prompt, "Question: What grew?\nContext:\nRevenue.\nAnswer:");
+    }
+}
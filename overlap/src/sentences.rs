@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+/// Abbreviations (lowercase, with their trailing period) that do not end a sentence.
+pub const DEFAULT_ABBREVIATIONS: &[&str] = &[
+    "mr.", "mrs.", "ms.", "dr.", "prof.", "sr.", "jr.", "st.", "inc.", "ltd.", "co.", "corp.",
+    "vs.", "etc.", "e.g.", "i.e.", "u.s.", "u.k.", "no.", "fig.", "approx.",
+];
+
+/// Sentence terminators every splitter knows.
+pub const DEFAULT_TERMINATORS: &[char] = &['.', '!', '?'];
+
+/// Full-width terminators of Chinese and Japanese text, for
+/// `SentenceSplitter::with_terminators`.
+pub const CJK_TERMINATORS: &[char] = &['。', '！', '？', '｡', '．'];
+
+/// Closing quotes and brackets kept with the sentence they end.
+const CLOSERS: &[char] = &['"', '\'', ')', ']', '”', '’', '」', '』', '）', '】'];
+
+/// Splits text into sentences on '.', '!' and '?', plus any terminators added
+/// with `with_terminators`.
+///
+/// An ASCII terminator only ends a sentence when it is followed by whitespace
+/// or the end of the text (closing quotes and brackets are kept with the
+/// sentence), so decimals such as "3.5" and dotted forms such as "U.S." are
+/// never split inside. Other terminators, such as the full-width ones in
+/// `CJK_TERMINATORS`, end a sentence wherever they occur, since those scripts
+/// do not put spaces between sentences, except between two digits ("３．５").
+/// A period ending a known abbreviation ("Inc.", "e.g.") does not end the
+/// sentence either; add domain-specific ones with `SentenceSplitter::new`.
+pub struct SentenceSplitter {
+    abbreviations: HashSet<String>,
+    terminators: Vec<char>,
+}
+
+impl SentenceSplitter {
+    /// A splitter that knows `DEFAULT_ABBREVIATIONS` plus `extra_abbreviations`,
+    /// which are matched ignoring case and with or without their trailing
+    /// period ("Corp" and "corp." are the same).
+    pub fn new(extra_abbreviations: &[&str]) -> Self {
+        let abbreviations = DEFAULT_ABBREVIATIONS
+            .iter()
+            .chain(extra_abbreviations)
+            .map(|abbr| format!("{}.", abbr.trim().trim_end_matches('.').to_lowercase()))
+            .collect();
+        Self {
+            abbreviations,
+            terminators: DEFAULT_TERMINATORS.to_vec(),
+        }
+    }
+
+    /// Also end sentences at `terminators`, e.g. `CJK_TERMINATORS`.
+    pub fn with_terminators(mut self, terminators: &[char]) -> Self {
+        for &terminator in terminators {
+            if !self.terminators.contains(&terminator) {
+                self.terminators.push(terminator);
+            }
+        }
+        self
+    }
+
+    /// Split `text` into sentences, each keeping its terminator and leading
+    /// whitespace, so concatenating them gives back `text`.
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            if !self.terminators.contains(&c) {
+                continue;
+            }
+
+            // Keep closing quotes/brackets and repeated terminators ("?!", "...")
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, next)) = chars.peek() {
+                if self.terminators.contains(&next) || CLOSERS.contains(&next) {
+                    end = j + next.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let at_boundary = if c.is_ascii() {
+                chars.peek().is_none_or(|&(_, next)| next.is_whitespace())
+            } else {
+                // A full-width period between digits is a decimal point ("３．５")
+                let after_digit = text[..i].chars().next_back().is_some_and(char::is_numeric);
+                !(after_digit && chars.peek().is_some_and(|&(_, next)| next.is_numeric()))
+            };
+            if at_boundary && !(c == '.' && self.ends_with_abbreviation(&text[start..end])) {
+                sentences.push(&text[start..end]);
+                start = end;
+            }
+        }
+
+        if start < text.len() {
+            sentences.push(&text[start..]);
+        }
+        sentences
+    }
+
+    /// Whether the last word of `sentence` is a known abbreviation.
+    fn ends_with_abbreviation(&self, sentence: &str) -> bool {
+        sentence
+            .split_whitespace()
+            .next_back()
+            .map(|word| {
+                word.trim_start_matches(['(', '"', '\'', '“', '‘'])
+                    .to_lowercase()
+            })
+            .is_some_and(|word| self.abbreviations.contains(&word))
+    }
+}
+
+impl Default for SentenceSplitter {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
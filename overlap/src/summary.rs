@@ -1,16 +1,56 @@
-use crate::llm::LlmClient;
+use crate::data::Chunk;
+use crate::embeddings::SentenceEmbedder;
+use crate::llm::LlmBackend;
+use crate::prompt::render_builtin;
+use crate::sentences::SentenceSplitter;
 use std::collections::HashSet;
 
 
+/// How `are_chunks_overlapping` scores the overlap of two word sets.
+///
+/// For the same pair of sets, Jaccard ≤ overlap coefficient ≤ Dice, so a
+/// threshold that suits one measure is looser or stricter under the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimilarityMeasure {
+    /// Intersection over the size of the larger set.
+    #[default]
+    OverlapCoefficient,
+    /// Intersection over union; the strictest of the three.
+    Jaccard,
+    /// Twice the intersection over the sum of the set sizes.
+    Dice,
+}
+
+impl SimilarityMeasure {
+    /// Score two word sets; two empty sets score 0.0.
+    pub fn score(self, a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+        let intersection = a.intersection(b).count() as f32;
+        let denominator = match self {
+            SimilarityMeasure::OverlapCoefficient => a.len().max(b.len()) as f32,
+            SimilarityMeasure::Jaccard => a.union(b).count() as f32,
+            SimilarityMeasure::Dice => (a.len() + b.len()) as f32 / 2.0,
+        };
+        if denominator > 0.0 {
+            intersection / denominator
+        } else {
+            0.0 // Avoid division by zero if both sets are empty
+        }
+    }
+}
+
 /// Determine if there is significant lexical overlap between the given chunks.
 ///
 /// This function takes a vector of strings, splits each string into words,
 /// and checks for significant overlap between any two strings. The overlap
-/// is computed as the size of the intersection divided by the size of the
-/// larger set. If the overlap ratio is above the given threshold, the
-/// function returns true. Otherwise, it returns false.
+/// of two word sets is scored with `measure`. If the score of any pair is
+/// above the given threshold, the function returns true. Otherwise, it
+/// returns false.
 ///
-pub fn are_chunks_overlapping(chunks: &[String], similarity_threshold: f32) -> bool {
+pub fn are_chunks_overlapping(
+    chunks: &[String],
+    similarity_threshold: f32,
+    measure: SimilarityMeasure,
+) -> bool {
     if chunks.len() < 2 {
         return false;
     }
@@ -27,25 +67,27 @@ pub fn are_chunks_overlapping(chunks: &[String], similarity_threshold: f32) -> b
         .collect();
 
     // Step 2: Compare each pair of word sets
-    for i in 0..word_sets.len() - 1 {
+    for i in 0..word_sets.len() {
         for j in i + 1..word_sets.len() {
-            // Step 3: Compute the intersection size and the size of the larger set
-            let intersection_size = word_sets[i]
-                .intersection(&word_sets[j])
-                .count() as f32; // Size of common words
-            let max_len = word_sets[i]
-                .len()
-                .max(word_sets[j].len()) as f32; // Size of larger set
-
-            // Step 4: Calculate overlap ratio as intersection / max_len
-            let overlap: f32 = if max_len > 0.0 {
-                intersection_size / max_len
-            } else {
-                0.0 // Avoid division by zero if both sets are empty
-            };
+            // Step 3: Score the pair with the chosen measure and compare with threshold
+            if measure.score(&word_sets[i], &word_sets[j]) > similarity_threshold {
+                return true;
+            }
+        }
+    }
+    false
+}
 
-            // Step 5: Compare with threshold
-            if overlap > similarity_threshold {
+/// Determine if any two chunks are semantically redundant.
+///
+/// Takes the chunks' precomputed embeddings (e.g. those returned alongside the
+/// documents by Chroma) and returns true if the cosine similarity of any pair
+/// is above `similarity_threshold`. Unlike `are_chunks_overlapping`, this also
+/// catches chunks that say the same thing in different words.
+pub fn are_chunks_overlapping_semantic(embeddings: &[Vec<f32>], similarity_threshold: f32) -> bool {
+    for i in 0..embeddings.len() {
+        for j in (i + 1)..embeddings.len() {
+            if cosine_similarity(&embeddings[i], &embeddings[j]) > similarity_threshold {
                 return true;
             }
         }
@@ -53,28 +95,233 @@ pub fn are_chunks_overlapping(chunks: &[String], similarity_threshold: f32) -> b
     false
 }
 
-/// Summarize the given chunks of text using the LLM.
+/// How `summarize_chunks` condenses the retrieved chunks.
+pub enum SummaryMode<'a> {
+    /// Ask the LLM to rewrite the chunks into a concise summary.
+    Abstractive,
+    /// Return the `max_sentences` sentences most similar to the query, verbatim
+    /// and in their original order, the chunks cut into sentences with
+    /// `splitter`. No LLM call is made.
+    Extractive {
+        embedder: &'a SentenceEmbedder,
+        max_sentences: usize,
+        splitter: &'a SentenceSplitter,
+    },
+}
+
+/// A summary together with the documents whose chunks went into it.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub text: String,
+    /// Ids of the contributing documents, in order of first appearance.
+    pub doc_ids: Vec<usize>,
+}
+
+impl Summary {
+    /// The summary followed by a "Sources:" line listing the contributing
+    /// documents, so the final answer can cite them. Unchanged if there are none.
+    pub fn annotated(&self) -> String {
+        if self.doc_ids.is_empty() {
+            return self.text.clone();
+        }
+        let sources: Vec<String> = self.doc_ids.iter().map(|id| format!("doc {}", id)).collect();
+        format!("{}\n\nSources: {}", self.text, sources.join(", "))
+    }
+}
+
+/// Distinct document ids of the given chunks, in order of first appearance.
+fn distinct_doc_ids<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.doc_id)
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+/// Summarize the given chunks of text.
 ///
-/// If the summary is shorter than 20 characters or signals that a summary is not possible,
-/// return the full text of the chunks instead.
+/// In `Abstractive` mode the LLM writes the summary; if it is shorter than 20
+/// characters or signals that a summary is not possible, the full text of the
+/// chunks is returned instead. In `Extractive` mode the top sentences by
+/// embedding similarity to `query` are returned unchanged, and only the
+/// documents those sentences came from are reported as sources.
 pub async fn summarize_chunks(
-    llm: &LlmClient,
-    chunks: &[String],
-) -> Result<String, Box<dyn std::error::Error>> {
+    llm: &dyn LlmBackend,
+    chunks: &[Chunk],
+    query: &str,
+    mode: SummaryMode<'_>,
+) -> Result<Summary, Box<dyn std::error::Error>> {
     if chunks.is_empty() {
-        return Ok("No relevant chunks were retrieved.".to_string());
+        return Ok(Summary {
+            text: "No relevant chunks were retrieved.".to_string(),
+            doc_ids: Vec::new(),
+        });
+    }
+
+    match mode {
+        SummaryMode::Abstractive => abstractive_summary(llm, chunks).await,
+        SummaryMode::Extractive {
+            embedder,
+            max_sentences,
+            splitter,
+        } => extractive_summary(chunks, query, embedder, max_sentences, splitter),
+    }
+}
+
+/// Which form `build_context` gave the retrieved chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextKind {
+    /// The chunks were condensed by `summarize_chunks`.
+    Summary,
+    /// The chunks were listed as is, one bullet each.
+    List,
+}
+
+/// When `build_context` summarizes the chunks instead of listing them.
+#[derive(Debug, Clone)]
+pub struct ContextPolicy {
+    /// Overlap above which two chunks count as redundant: a word-overlap ratio
+    /// for `are_chunks_overlapping`, a cosine similarity when chunk embeddings
+    /// are given.
+    pub overlap_threshold: f32,
+    /// Largest number of chunks listed as is; more are always summarized.
+    pub max_listed_chunks: usize,
+    /// How lexical overlap is scored; unused when chunk embeddings are given.
+    pub measure: SimilarityMeasure,
+}
+
+impl Default for ContextPolicy {
+    fn default() -> Self {
+        Self {
+            overlap_threshold: 0.8,
+            max_listed_chunks: 3,
+            measure: SimilarityMeasure::OverlapCoefficient,
+        }
+    }
+}
+
+/// Turn the retrieved chunks into the context for the final answer.
+///
+/// The chunks are summarized (the summary annotated with its source documents)
+/// if there are more than `policy.max_listed_chunks` of them or any two
+/// overlap; otherwise each is listed with its document id and category.
+/// Overlap is semantic when `chunk_embeddings` (aligned with `chunks`) are
+/// given and lexical otherwise. Returns the context and the branch taken.
+pub async fn build_context(
+    llm: &dyn LlmBackend,
+    chunks: &[Chunk],
+    query: &str,
+    mode: SummaryMode<'_>,
+    policy: &ContextPolicy,
+    chunk_embeddings: Option<&[Vec<f32>]>,
+) -> Result<(String, ContextKind), Box<dyn std::error::Error>> {
+    let overlapping = match chunk_embeddings {
+        Some(embeddings) => are_chunks_overlapping_semantic(embeddings, policy.overlap_threshold),
+        None => {
+            let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+            are_chunks_overlapping(&texts, policy.overlap_threshold, policy.measure)
+        }
+    };
+
+    if chunks.len() > policy.max_listed_chunks || overlapping {
+        let summary = summarize_chunks(llm, chunks, query, mode).await?;
+        Ok((summary.annotated(), ContextKind::Summary))
+    } else {
+        let list = chunks
+            .iter()
+            .map(|chunk| format!("- [doc {}, {}] {}", chunk.doc_id, chunk.category, chunk.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok((list, ContextKind::List))
+    }
+}
+
+/// Cosine similarity between two dense vectors (0.0 if either is all zeros).
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
     }
+}
 
-    let combined = chunks.join("\n");
-    let prompt = format!(
-        "You are an expert summarizer. Please generate a concise summary of the following text.\n\
-         Do not omit critical details that might answer the user's query.\n\
-         If you cannot produce a meaningful summary, just say 'Summary not possible'.\n\n\
-         Text:\n{}\n\nSummary:",
-        combined
-    );
+/// Select the sentences most similar to the query and return them verbatim.
+fn extractive_summary(
+    chunks: &[Chunk],
+    query: &str,
+    embedder: &SentenceEmbedder,
+    max_sentences: usize,
+    splitter: &SentenceSplitter,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    // Each sentence keeps a reference to the chunk it came from
+    let (sentences, origins): (Vec<&str>, Vec<&Chunk>) = chunks
+        .iter()
+        .flat_map(|chunk| {
+            splitter
+                .split(&chunk.text)
+                .into_iter()
+                .map(move |sentence| (sentence.trim(), chunk))
+        })
+        .filter(|(s, _)| !s.is_empty())
+        .unzip();
+
+    if sentences.len() <= max_sentences {
+        return Ok(Summary {
+            text: sentences.join(" "),
+            doc_ids: distinct_doc_ids(origins),
+        });
+    }
 
-    let summary = llm.get_llm_response(&prompt).await?.trim().to_string();
+    let query_embedding = embedder.embed_texts(&[query])?.remove(0);
+    let sentence_embeddings = embedder.embed_texts(&sentences)?;
+
+    // Rank sentences by similarity to the query
+    let mut ranked: Vec<(usize, f32)> = sentence_embeddings
+        .iter()
+        .enumerate()
+        .map(|(i, emb)| (i, cosine_similarity(&query_embedding, emb)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    // Keep the best ones, restored to their original order
+    let mut selected: Vec<usize> = ranked.into_iter().take(max_sentences).map(|(i, _)| i).collect();
+    selected.sort_unstable();
+
+    Ok(Summary {
+        text: selected
+            .iter()
+            .map(|&i| sentences[i])
+            .collect::<Vec<_>>()
+            .join(" "),
+        doc_ids: distinct_doc_ids(selected.iter().map(|&i| origins[i])),
+    })
+}
+
+/// Summarize the given chunks of text using the LLM.
+///
+/// If the summary is shorter than 20 characters or signals that a summary is not possible,
+/// return the full text of the chunks instead. Every chunk counts as a source.
+async fn abstractive_summary(
+    llm: &dyn LlmBackend,
+    chunks: &[Chunk],
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let combined = chunks
+        .iter()
+        .map(|chunk| chunk.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let doc_ids = distinct_doc_ids(chunks);
+    let prompt = render_builtin("summarize", &[("text", &combined)]);
+
+    let summary = llm
+        .get_llm_response_with_system("You are an expert summarizer.", &prompt)
+        .await?
+        .trim()
+        .to_string();
 
     // Only fall back if summary is too short or exactly "Summary not possible" (case-insensitive)
     if summary.len() < 20 || summary.to_lowercase() == "summary not possible" {
@@ -87,8 +334,105 @@ pub async fn summarize_chunks(
             },
             summary.len()
         );
-        Ok(combined)
+        Ok(Summary {
+            text: combined,
+            doc_ids,
+        })
     } else {
-        Ok(summary)
+        Ok(Summary {
+            text: summary,
+            doc_ids,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::MockLlm;
+
+    fn words(text: &str) -> HashSet<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn measures_order_the_same_pair_differently() {
+        // 3 shared words out of 5 and 4: union 6, larger set 5, mean size 4.5
+        let a = words("the cat sat on mat");
+        let b = words("the cat sat down");
+        let jaccard = SimilarityMeasure::Jaccard.score(&a, &b);
+        let overlap = SimilarityMeasure::OverlapCoefficient.score(&a, &b);
+        let dice = SimilarityMeasure::Dice.score(&a, &b);
+        assert_eq!(jaccard, 0.5);
+        assert_eq!(overlap, 0.6);
+        assert!((dice - 2.0 / 3.0).abs() < 1e-6);
+        assert!(jaccard < overlap && overlap < dice);
+    }
+
+    #[test]
+    fn one_threshold_gives_different_verdicts_per_measure() {
+        let chunks = vec!["the cat sat on mat".to_string(), "the cat sat down".to_string()];
+        assert!(!are_chunks_overlapping(&chunks, 0.55, SimilarityMeasure::Jaccard));
+        assert!(are_chunks_overlapping(&chunks, 0.55, SimilarityMeasure::OverlapCoefficient));
+        assert!(!are_chunks_overlapping(&chunks, 0.62, SimilarityMeasure::OverlapCoefficient));
+        assert!(are_chunks_overlapping(&chunks, 0.62, SimilarityMeasure::Dice));
+    }
+
+    #[test]
+    fn empty_sets_score_zero() {
+        let empty = HashSet::new();
+        for measure in [
+            SimilarityMeasure::OverlapCoefficient,
+            SimilarityMeasure::Jaccard,
+            SimilarityMeasure::Dice,
+        ] {
+            assert_eq!(measure.score(&empty, &empty), 0.0);
+        }
+    }
+
+    fn chunk(doc_id: usize, text: &str) -> Chunk {
+        Chunk {
+            doc_id,
+            chunk_id: 0,
+            category: "test".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn abstractive_summary_uses_the_model_reply() {
+        let llm = MockLlm::new(vec!["  Revenue grew 12% on strong cloud sales.  ".to_string()]);
+        let chunks = [
+            chunk(3, "Revenue grew 12%."),
+            chunk(1, "Cloud sales were strong."),
+            chunk(3, "More."),
+        ];
+        let summary = summarize_chunks(&llm, &chunks, "revenue", SummaryMode::Abstractive)
+            .await
+            .unwrap();
+        assert_eq!(summary.text, "Revenue grew 12% on strong cloud sales.");
+        assert_eq!(summary.doc_ids, [3, 1]);
+    }
+
+    #[tokio::test]
+    async fn short_or_refused_summaries_fall_back_to_the_chunks() {
+        let chunks = [chunk(0, "Revenue grew 12%."), chunk(1, "Cloud sales were strong.")];
+        for reply in ["Too short", "Summary not possible"] {
+            let llm = MockLlm::new(vec![reply.to_string()]);
+            let summary = summarize_chunks(&llm, &chunks, "revenue", SummaryMode::Abstractive)
+                .await
+                .unwrap();
+            assert_eq!(summary.text, "Revenue grew 12%.\nCloud sales were strong.");
+        }
+    }
+
+    #[tokio::test]
+    async fn no_chunks_skip_the_model() {
+        let llm = MockLlm::new(vec!["should not be used".to_string()]);
+        let summary = summarize_chunks(&llm, &[], "revenue", SummaryMode::Abstractive)
+            .await
+            .unwrap();
+        assert_eq!(summary.text, "No relevant chunks were retrieved.");
+        assert!(summary.doc_ids.is_empty());
     }
 }
@@ -1,31 +1,75 @@
 use crate::data::Chunk;
 use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
 use chromadb::collection::{ChromaCollection, CollectionEntries};
 use serde_json::json;
 use std::error::Error;
 
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// Chroma ids of `chunks`, in order: one per chunk, combining its `doc_id` and `chunk_id`.
+pub fn chunk_ids(chunks: &[Chunk]) -> Vec<String> {
+    chunks
+        .iter()
+        .map(|chunk| format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
+        .collect()
+}
+
 /// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
 pub async fn build_chroma_collection(
     chunks: &[Chunk],
     collection_name: &str,
     embedder: &SentenceEmbedder,
 ) -> Result<ChromaCollection, Box<dyn Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    let (collection, _) =
+        build_chroma_collection_returning_embeddings(chunks, collection_name, embedder).await?;
+    Ok(collection)
+}
+
+/// Like `build_chroma_collection`, but also returns the chunk embeddings,
+/// aligned with `chunks`, so callers that need the raw vectors don't embed twice.
+pub async fn build_chroma_collection_returning_embeddings(
+    chunks: &[Chunk],
+    collection_name: &str,
+    embedder: &SentenceEmbedder,
+) -> Result<(ChromaCollection, Vec<Vec<f32>>), Box<dyn Error>> {
+    let client = connect_chroma().await?;
     let collection = client.get_or_create_collection(collection_name, None).await?;
 
     // Skip empty collection
     if chunks.is_empty() {
-        return Ok(collection);
+        return Ok((collection, Vec::new()));
     }
 
     let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
     let documents: Vec<&str> = texts.iter().map(AsRef::as_ref).collect();
 
-    // Create unique IDs by combining doc_id and chunk_id
-    let ids_owned: Vec<String> = chunks.iter()
-        .map(|chunk| format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
-        .collect();
+    let ids_owned = chunk_ids(chunks);
     let ids: Vec<&str> = ids_owned.iter().map(AsRef::as_ref).collect();
 
     let metadatas = chunks.iter()
@@ -42,11 +86,11 @@ pub async fn build_chroma_collection(
 
     let entries = CollectionEntries {
         ids,
-        embeddings: Some(embeddings),
+        embeddings: Some(embeddings.clone()),
         metadatas: Some(metadatas),
         documents: Some(documents),
     };
 
     collection.upsert(entries, None).await?;
-    Ok(collection)
+    Ok((collection, embeddings))
 }
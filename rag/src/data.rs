@@ -0,0 +1,208 @@
+use crate::truncate::{CJK_TERMINATORS, SentenceSplitter};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+/// A knowledge-base document.
+///
+/// `metadata` holds any fields beyond `id`, `title` and `content` (category,
+/// tags, ticker, author, url, ...) so they are carried along with the document.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub metadata: HashMap<String, Value>,
+}
+
+pub type KnowledgeBase = HashMap<String, Document>;
+
+/// Words per chunk when a knowledge base is split for the vector store.
+const KB_CHUNK_SIZE: usize = 50;
+
+/// A piece of a knowledge-base document, as stored in a Chroma collection.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Id of the source document in the knowledge base.
+    pub doc_id: String,
+    pub chunk_id: usize,
+    pub category: String,
+    pub text: String,
+}
+
+/// Document as it appears in the JSON corpus; unknown fields land in `metadata`.
+#[derive(Debug, Deserialize)]
+struct RawDocument {
+    id: Option<Value>,
+    title: Option<String>,
+    content: String,
+    #[serde(flatten)]
+    metadata: HashMap<String, Value>,
+}
+
+impl Document {
+    /// Create a document with no extra metadata.
+    pub fn new(id: &str, title: &str, content: &str) -> Self {
+        Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Render the metadata as `key=value` pairs sorted by key.
+    pub fn metadata_summary(&self) -> String {
+        let mut pairs: Vec<String> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => format!("{}={}", key, s),
+                other => format!("{}={}", key, other),
+            })
+            .collect();
+        pairs.sort();
+        pairs.join(", ")
+    }
+}
+
+/// Key of each document, in file order: its explicit `id` as a string, or for
+/// a document without one `doc{n}`, numbered after the largest explicit
+/// `doc{n}` id so a fallback key never replaces an explicit one.
+fn assign_doc_ids(raw_documents: &[RawDocument]) -> Vec<String> {
+    let explicit: Vec<Option<String>> = raw_documents
+        .iter()
+        .map(|raw| match &raw.id {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => Some(other.to_string()),
+            None => None,
+        })
+        .collect();
+    let mut next_n = explicit
+        .iter()
+        .flatten()
+        .filter_map(|id| id.strip_prefix("doc")?.parse::<usize>().ok())
+        .max()
+        .map_or(1, |max| max + 1);
+    explicit
+        .into_iter()
+        .map(|id| {
+            id.unwrap_or_else(|| {
+                next_n += 1;
+                format!("doc{}", next_n - 1)
+            })
+        })
+        .collect()
+}
+
+/// Loads a knowledge base from a JSON array of documents.
+///
+/// The `id` field may be a string or a number; documents without one are keyed
+/// `doc{n}` (see `assign_doc_ids`). Every field other than `id`, `title`
+/// and `content` is kept in the document's `metadata`.
+pub fn load_knowledge_base(file_path: &str) -> Result<KnowledgeBase, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let raw_documents: Vec<RawDocument> = serde_json::from_reader(reader)?;
+
+    let doc_ids = assign_doc_ids(&raw_documents);
+    let mut kb = HashMap::new();
+    for (raw, id) in raw_documents.into_iter().zip(doc_ids) {
+        let doc = Document {
+            id: id.clone(),
+            title: raw.title.unwrap_or_default(),
+            content: raw.content,
+            metadata: raw.metadata,
+        };
+        kb.insert(id, doc);
+    }
+
+    Ok(kb)
+}
+
+/// Splits the given text into chunks of size 'chunk_size' words.
+///
+/// A word that holds several sentences, as unspaced Chinese or Japanese text
+/// does, is cut at the sentence ends `splitter` finds and each sentence counts
+/// as one word.
+pub fn chunk_text(text: &str, chunk_size: usize, splitter: &SentenceSplitter) -> Vec<String> {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .flat_map(|word| splitter.split(word))
+        .collect();
+    words
+        .chunks(chunk_size.max(1))
+        .map(|group| group.join(" "))
+        .collect()
+}
+
+/// Split every knowledge-base document into chunks for `build_chroma_collection`.
+///
+/// Each chunk of a titled document starts with "title: " so the title is
+/// embedded too; chunks of an untitled document are the content alone.
+/// The category comes from the document's `category` metadata, defaulting to
+/// "general". Documents are taken in id order so the output is deterministic.
+pub fn kb_to_chunks(kb: &KnowledgeBase) -> Vec<Chunk> {
+    let mut documents: Vec<&Document> = kb.values().collect();
+    documents.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+    let mut chunks = Vec::new();
+    for doc in documents {
+        let category = doc
+            .metadata
+            .get("category")
+            .and_then(Value::as_str)
+            .unwrap_or("general")
+            .to_string();
+        for (chunk_id, text) in chunk_text(&doc.content, KB_CHUNK_SIZE, &splitter)
+            .into_iter()
+            .enumerate()
+        {
+            chunks.push(Chunk {
+                doc_id: doc.id.clone(),
+                chunk_id,
+                category: category.clone(),
+                text: if doc.title.is_empty() {
+                    text
+                } else {
+                    format!("{}: {}", doc.title, text)
+                },
+            });
+        }
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_keys_follow_the_largest_explicit_doc_number() {
+        let raw: Vec<RawDocument> = serde_json::from_str(
+            r#"[{"id": "doc3", "content": "a"}, {"content": "b"}, {"id": 7, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&raw), ["doc3", "doc4", "7", "doc5"]);
+
+        let raw: Vec<RawDocument> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&raw), ["doc1", "doc2"]);
+    }
+    #[test]
+    fn only_titled_documents_prefix_their_chunks() {
+        let kb: KnowledgeBase = [
+            Document::new("doc1", "Report", "Sales grew."),
+            Document::new("doc2", "", "Costs fell."),
+        ]
+        .into_iter()
+        .map(|doc| (doc.id.clone(), doc))
+        .collect();
+        let texts: Vec<String> = kb_to_chunks(&kb).into_iter().map(|c| c.text).collect();
+        assert_eq!(texts, ["Report: Sales grew.", "Costs fell."]);
+    }
+}
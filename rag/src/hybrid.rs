@@ -0,0 +1,194 @@
+use crate::data::{Document, KnowledgeBase};
+use crate::embeddings::SentenceEmbedder;
+use crate::precision::Precision;
+use crate::vector_store::VectorStore;
+use bm25::{Embedder, EmbedderBuilder, Embedding, Language, TokenEmbedding};
+use std::error::Error;
+
+/// A BM25 “index” that precomputes sparse embeddings for every document.
+pub struct Bm25Index {
+    embedder: Embedder,
+    doc_embeddings: Vec<Embedding>,
+}
+
+impl Bm25Index {
+    /// Build the index by fitting BM25 to the full corpus of document texts.
+    pub fn new(texts: &[&str]) -> Self {
+        let corpus: Vec<String> = texts.iter().map(|t| t.to_lowercase()).collect();
+        let corpus_refs: Vec<&str> = corpus.iter().map(|s| s.as_str()).collect();
+
+        let embedder = EmbedderBuilder::with_fit_to_corpus(Language::English, &corpus_refs).build();
+        let doc_embeddings = corpus_refs
+            .iter()
+            .map(|&text| embedder.embed(text))
+            .collect();
+
+        Bm25Index {
+            embedder,
+            doc_embeddings,
+        }
+    }
+
+    /// Compute a BM25‐style score for the query against every document.
+    pub fn score(&self, query: &str) -> Vec<f32> {
+        let q_emb = self.embedder.embed(&query.to_lowercase());
+        self.doc_embeddings
+            .iter()
+            .map(|doc_emb| dot(&q_emb, doc_emb))
+            .collect()
+    }
+}
+
+/// Dot‐product of two sparse embeddings.
+fn dot(a: &Embedding, b: &Embedding) -> f32 {
+    let mut sum = 0.0;
+    for TokenEmbedding {
+        index: qi,
+        value: qv,
+    } in &a.0
+    {
+        for TokenEmbedding {
+            index: di,
+            value: dv,
+        } in &b.0
+        {
+            if qi == di {
+                sum += qv * dv;
+            }
+        }
+    }
+    sum
+}
+
+/// Cosine similarity between two dense vectors (0.0 if either is all zeros).
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Default weight of the title in the dense similarity (the content gets the rest).
+pub const DEFAULT_TITLE_WEIGHT: f32 = 0.3;
+
+/// BM25 + dense retriever over a `KnowledgeBase`.
+///
+/// The knowledge base is small, so document embeddings are kept in memory
+/// instead of a vector database. Titles and contents are embedded separately
+/// so a query matching a title gets a boost even if the content phrases it
+/// differently.
+pub struct HybridIndex<'a> {
+    documents: Vec<&'a Document>,
+    bm25: Bm25Index,
+    embeddings: VectorStore,
+    /// Embeddings of the non-empty titles only, in document order.
+    title_embeddings: VectorStore,
+}
+
+impl<'a> HybridIndex<'a> {
+    /// Index every document of the knowledge base, ordered by id, keeping the
+    /// document embeddings at `precision`; `Precision::F16` halves their
+    /// memory at a small cost in score accuracy.
+    pub fn new(
+        kb: &'a KnowledgeBase,
+        embedder: &SentenceEmbedder,
+        precision: Precision,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut documents: Vec<&Document> = kb.values().collect();
+        documents.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let texts: Vec<&str> = documents.iter().map(|d| d.content.as_str()).collect();
+        let titles: Vec<&str> = documents
+            .iter()
+            .map(|d| d.title.as_str())
+            .filter(|title| !title.is_empty())
+            .collect();
+        let bm25 = Bm25Index::new(&texts);
+        let embeddings = if texts.is_empty() {
+            VectorStore::new(precision)
+        } else {
+            VectorStore::from_vectors(embedder.embed_texts(&texts)?, precision)
+        };
+        let title_embeddings = if titles.is_empty() {
+            VectorStore::new(precision)
+        } else {
+            VectorStore::from_vectors(embedder.embed_texts(&titles)?, precision)
+        };
+
+        Ok(HybridIndex {
+            documents,
+            bm25,
+            embeddings,
+            title_embeddings,
+        })
+    }
+
+    /// Rank the documents for `query` by a weighted sum of BM25 and dense similarity.
+    ///
+    /// BM25 scores are min-max normalized to [0, 1] over the knowledge base and
+    /// combined as `alpha * bm25 + (1 - alpha) * dense`, where `dense` is
+    /// `title_weight * title_cosine + (1 - title_weight) * content_cosine`.
+    /// A `title_weight` of 0.0 ignores titles, and a document without a title
+    /// gets its content cosine as `dense`. Returns the top `top_k` documents
+    /// with their scores, highest first.
+    pub fn retrieve(
+        &self,
+        query: &str,
+        top_k: usize,
+        alpha: f32,        // weight on BM25 [0..1]
+        title_weight: f32, // weight on the title within the dense score [0..1]
+        embedder: &SentenceEmbedder,
+    ) -> Result<Vec<(&'a Document, f32)>, Box<dyn Error>> {
+        if self.documents.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 1) BM25 scores + normalization range
+        let b_scores = self.bm25.score(query);
+        let (b_min, b_max) = b_scores
+            .iter()
+            .cloned()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(mn, mx), v| {
+                (mn.min(v), mx.max(v))
+            });
+        let denom = (b_max - b_min).max(f32::EPSILON);
+
+        // 2) Dense similarity against the in-memory embeddings
+        let q_emb = embedder.embed_texts(&[query])?.remove(0);
+
+        // 3) Combine and keep the best top_k
+        let mut title_embeddings = self.title_embeddings.iter();
+        let mut merged: Vec<(&'a Document, f32)> = self
+            .documents
+            .iter()
+            .zip(self.embeddings.iter())
+            .zip(b_scores)
+            .map(|((&doc, emb), b_raw)| {
+                let b_norm = (b_raw - b_min) / denom;
+                let content_sim = cosine_similarity(&q_emb, &emb);
+                // Untitled documents have no title embedding to compare against
+                let title_emb = if doc.title.is_empty() {
+                    None
+                } else {
+                    title_embeddings.next()
+                };
+                let e_sim = match title_emb {
+                    Some(title_emb) => {
+                        title_weight * cosine_similarity(&q_emb, &title_emb)
+                            + (1.0 - title_weight) * content_sim
+                    }
+                    None => content_sim,
+                };
+                (doc, alpha * b_norm + (1.0 - alpha) * e_sim)
+            })
+            .collect();
+
+        merged.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        merged.truncate(top_k);
+        Ok(merged)
+    }
+}
@@ -5,12 +5,73 @@ use async_openai::types::{
     ChatCompletionRequestSystemMessageContent, ChatCompletionRequestUserMessage,
     ChatCompletionRequestUserMessageContent, CreateChatCompletionRequestArgs,
 };
+use async_trait::async_trait;
 use dotenv::dotenv;
+use std::collections::VecDeque;
 use std::env;
+use std::sync::Mutex;
+
+/// Reply used by the generation functions when there is no context to answer from.
+pub const DEFAULT_NO_CONTEXT_MESSAGE: &str =
+    "I'm sorry, but I couldn't find any relevant information.";
+
+/// A chat model that answers prompts.
+///
+/// `LlmClient` calls the OpenAI API; `MockLlm` answers offline, so generation
+/// logic can run without an API key or network access.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// Reply to give instead of calling the model when the context is empty.
+    fn no_context_message(&self) -> &str {
+        DEFAULT_NO_CONTEXT_MESSAGE
+    }
+
+    /// Like `get_llm_response`, but uses `system` as the system message for this call only.
+    async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Offline `LlmBackend` for tests and demos.
+///
+/// Returns the canned responses in order, one per call; once they run out
+/// (or if none were given) it echoes the prompt back. System messages are ignored.
+pub struct MockLlm {
+    responses: Mutex<VecDeque<String>>,
+}
+
+impl MockLlm {
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlm {
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let canned = self.responses.lock().unwrap().pop_front();
+        Ok(canned.unwrap_or_else(|| prompt.to_string()))
+    }
+
+    async fn get_llm_response_with_system(
+        &self,
+        _system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response(prompt).await
+    }
+}
 
 pub struct LlmClient {
     client: Client<OpenAIConfig>,
     system_prompt: String,
+    no_context_message: String,
 }
 
 impl LlmClient {
@@ -29,16 +90,36 @@ impl LlmClient {
             system_prompt:
                 "You are a helpful AI assistant. You always answer to the user's queries."
                     .to_string(),
+            no_context_message: DEFAULT_NO_CONTEXT_MESSAGE.to_string(),
         }
     }
 
-    pub async fn get_llm_response(
+    /// Use `message` instead of `DEFAULT_NO_CONTEXT_MESSAGE` when there is no context.
+    pub fn with_no_context_message(mut self, message: impl Into<String>) -> Self {
+        self.no_context_message = message.into();
+        self
+    }
+}
+
+#[async_trait]
+impl LlmBackend for LlmClient {
+    fn no_context_message(&self) -> &str {
+        &self.no_context_message
+    }
+
+    async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.get_llm_response_with_system(&self.system_prompt, prompt)
+            .await
+    }
+
+    async fn get_llm_response_with_system(
         &self,
+        system: &str,
         prompt: &str,
     ) -> Result<String, Box<dyn std::error::Error>> {
-        // Build messages using the default system prompt.
+        // Build messages using the given system prompt.
         let system_message = ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(self.system_prompt.clone()),
+            content: ChatCompletionRequestSystemMessageContent::Text(system.to_string()),
             name: None,
         };
 
@@ -1,14 +1,24 @@
+mod data;
+mod embeddings;
+mod error;
+mod hybrid;
 mod llm;
+mod precision;
+mod prompt;
+mod truncate;
+mod vector_db;
+mod vector_store;
 
+use data::{Document, KnowledgeBase, kb_to_chunks, load_knowledge_base};
+use embeddings::SentenceEmbedder;
+use hybrid::{DEFAULT_TITLE_WEIGHT, HybridIndex};
+use llm::{LlmBackend, LlmClient, MockLlm};
+use precision::Precision;
+use prompt::render_builtin;
 use std::collections::{HashMap, HashSet};
-
-#[derive(Debug, Clone)]
-struct Document {
-    title: String,
-    content: String,
-}
-
-type KnowledgeBase = HashMap<String, Document>;
+use std::env;
+use truncate::{CJK_TERMINATORS, SentenceSplitter, approx_token_count, truncate_to_budget};
+use vector_db::{build_chroma_collection, dense_retrieval};
 
 /// Creates a `KnowledgeBase` containing three sample documents related to Project Chimera.
 /// These documents are used for testing and demonstration purposes.
@@ -24,36 +34,36 @@ fn create_knowledge_base() -> KnowledgeBase {
 
     kb.insert(
         "doc1".to_string(),
-        Document {
-            title: "Project Chimera Overview".to_string(),
-            content: "Project Chimera is a research initiative focused on developing \
+        Document::new(
+            "doc1",
+            "Project Chimera Overview",
+            "Project Chimera is a research initiative focused on developing \
                       novel bio-integrated interfaces. It aims to merge biological \
-                      systems with advanced computing technologies."
-                .to_string(),
-        },
+                      systems with advanced computing technologies.",
+        ),
     );
 
     kb.insert(
         "doc2".to_string(),
-        Document {
-            title: "Chimera's Neural Interface".to_string(),
-            content: "The core component of Project Chimera is a neural interface \
+        Document::new(
+            "doc2",
+            "Chimera's Neural Interface",
+            "The core component of Project Chimera is a neural interface \
                       that allows for bidirectional communication between the brain \
                       and external devices. This interface uses biocompatible \
-                      nanomaterials."
-                .to_string(),
-        },
+                      nanomaterials.",
+        ),
     );
 
     kb.insert(
         "doc3".to_string(),
-        Document {
-            title: "Applications of Chimera".to_string(),
-            content: "Potential applications of Project Chimera include advanced \
+        Document::new(
+            "doc3",
+            "Applications of Chimera",
+            "Potential applications of Project Chimera include advanced \
                       prosthetics, treatment of neurological disorders, and enhanced \
-                      human-computer interaction. Ethical considerations are paramount."
-                .to_string(),
-        },
+                      human-computer interaction. Ethical considerations are paramount.",
+        ),
     );
 
     kb
@@ -62,37 +72,68 @@ fn create_knowledge_base() -> KnowledgeBase {
 /// naive_generation function
 /// Parameters:
 ///   query: &str - The user's question
-///   llm: &llm::LlmClient - The LLM client instance
+///   llm: &dyn LlmBackend - The LLM backend (OpenAI client or mock)
+///   dry_run: bool - Return the prompt instead of calling the LLM
 /// Returns: Result<String, Box<dyn std::error::Error>>
 /// Steps:
 ///   1. Format a simple prompt with the query using format!()
-///   2. Call llm.get_llm_response() with the prompt and return result
+///   2. In dry-run mode, return the prompt itself
+///   3. Otherwise call llm.get_llm_response() with the prompt and return result
 async fn naive_generation(
     query: &str,
-    llm: &llm::LlmClient,
+    llm: &dyn LlmBackend,
+    dry_run: bool,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let prompt = format!("Answer directly the following query: {}", query);
+    let prompt = render_builtin("naive", &[("query", query)]);
+    if dry_run {
+        return Ok(prompt);
+    }
     llm.get_llm_response(&prompt).await
 }
 
+/// Common English words that carry no meaning on their own; a query made only
+/// of these is treated as empty.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "is", "in", "of", "to", "a", "an", "that", "for", "on", "with", "as", "it", "by",
+    "this", "are", "was", "at", "from", "or", "be", "which", "not", "can", "also", "have", "has",
+    "had", "we", "they", "you", "he", "she", "his", "her", "its", "our", "us", "their", "them",
+    "i", "do", "does", "did", "just", "so", "if", "may", "will", "shall", "more", "most", "some",
+    "many", "any", "all", "what", "about", "would", "could", "should", "where", "when", "why",
+    "how",
+];
+
+/// True if `query` has no words but stopwords, e.g. "", "   " or "what is
+/// the". Embedding such a query would return arbitrary nearest neighbours.
+fn is_blank_query(query: &str) -> bool {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOPWORDS.contains(&word.to_lowercase().as_str()))
+}
+
 /// rag_retrieval function
 /// Parameters:
 ///   query: &str - The user's question
 ///   documents: &'a KnowledgeBase - The knowledge base
-/// Returns: Vec<&'a Document> - A vector of documents that overlap with the query
+/// Returns: Vec<(&'a Document, usize)> - Documents that overlap with the query, with their overlap counts
 /// Steps:
 ///   1. Convert query to lowercase and collect words into HashSet
 ///   2. Iterate through documents using iter() and filter_map()
 ///   3. For each doc, get word overlap count with query
-///   4. Return documents with overlap count > 0 using collect()
-fn rag_retrieval<'a>(query: &str, documents: &'a KnowledgeBase) -> Vec<&'a Document> {
+///   4. Keep documents with overlap count > 0, sorted by overlap in descending order
+///   A query of nothing but stopwords (see is_blank_query) retrieves nothing.
+fn rag_retrieval<'a>(query: &str, documents: &'a KnowledgeBase) -> Vec<(&'a Document, usize)> {
+    if is_blank_query(query) {
+        return Vec::new();
+    }
+
     let query_lower = query.to_lowercase();
     let query_words: HashSet<_> = query_lower
         .split_whitespace()
         .map(|s| s.to_string())
         .collect();
 
-    documents
+    let mut scored: Vec<(&Document, usize)> = documents
         .iter()
         .filter_map(|(_, doc)| {
             let content_lower = doc.content.to_lowercase();
@@ -101,41 +142,183 @@ fn rag_retrieval<'a>(query: &str, documents: &'a KnowledgeBase) -> Vec<&'a Docum
                 .map(|s| s.to_string())
                 .collect();
             let overlap = query_words.intersection(&content_words).count();
-            if overlap > 0 { Some(doc) } else { None }
+            if overlap > 0 {
+                Some((doc, overlap))
+            } else {
+                None
+            }
         })
-        .collect()
+        .collect();
+
+    // Most relevant first; break ties by title so the order is deterministic
+    scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.title.cmp(&b.0.title)));
+    scored
 }
 
 /// rag_generation function
 /// Parameters:
 ///   query: &str - The user's question
-///   documents: Vec<&Document> - The retrieved documents
-///   llm: &llm::LlmClient - The LLM client instance
-/// Returns: Result<String, Box<dyn std::error::Error>>
+///   documents: Vec<&Document> - The retrieved documents, most relevant first
+///   token_budget: usize - Approximate token budget for the context
+///   llm: &dyn LlmBackend - The LLM backend (OpenAI client or mock)
+///   dry_run: bool - Return the prompt instead of calling the LLM
+/// Returns: Result<(String, usize), Box<dyn std::error::Error>> - The answer and the number of documents included
 /// Steps:
-///   1. Match on documents to create appropriate prompt
-///   2. If documents is empty, create direct query prompt
-///   3. If documents is not empty, create prompt with context from documents
-///   4. Call llm.get_llm_response() with prompt and return result
+///   1. If documents is empty, return llm.no_context_message() without calling the LLM
+///   2. Otherwise add documents in relevance order until the token budget is reached
+///   3. Note how many documents were omitted, if any
+///   4. In dry-run mode, return the prompt itself as the answer
+///   5. Otherwise call llm.get_llm_response() with prompt and return result
 async fn rag_generation(
     query: &str,
     documents: Vec<&Document>,
-    llm: &llm::LlmClient,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let prompt = if documents.is_empty() {
-        format!("No relevant information found. Answer directly: {}", query)
+    token_budget: usize,
+    llm: &dyn LlmBackend,
+    dry_run: bool,
+) -> Result<(String, usize), Box<dyn std::error::Error>> {
+    if documents.is_empty() {
+        return Ok((llm.no_context_message().to_string(), 0));
+    }
+
+    let mut context_parts = Vec::new();
+    let mut used_tokens = 0;
+
+    for doc in &documents {
+        let entry = format!("{}: {}", doc.title, doc.content);
+        let entry_tokens = approx_token_count(&entry);
+
+        if used_tokens + entry_tokens <= token_budget {
+            context_parts.push(entry);
+            used_tokens += entry_tokens;
+        } else if context_parts.is_empty() {
+            // Even the most relevant document is too long: keep what fits of it,
+            // cutting at Chinese and Japanese sentence ends too
+            let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+            let (truncated, _) =
+                truncate_to_budget(&entry, token_budget, approx_token_count, &splitter);
+            context_parts.push(truncated);
+            break;
+        } else {
+            break;
+        }
+    }
+
+    let included = context_parts.len();
+    let omitted = documents.len() - included;
+    let mut context = context_parts.join("\n");
+    if omitted > 0 {
+        context.push_str(&format!(
+            "\n({} less relevant document(s) omitted to fit the context budget)",
+            omitted
+        ));
+    }
+
+    let prompt = render_builtin("answer", &[("context", &context), ("query", query)]);
+    if dry_run {
+        return Ok((prompt, included));
+    }
+    let answer = llm.get_llm_response(&prompt).await?;
+    Ok((answer, included))
+}
+
+/// Result of answering the same query without and with retrieval.
+#[derive(Debug, Clone)]
+struct CompareResult {
+    naive: String,
+    rag: String,
+    /// Ids of the retrieved documents, most relevant first
+    retrieved_doc_ids: Vec<String>,
+    /// How many of the retrieved documents fit in the context budget
+    docs_in_context: usize,
+}
+
+/// retrieve_documents function
+/// Parameters:
+///   query: &str - The user's question
+///   kb: &'a KnowledgeBase - The knowledge base
+/// Returns: Result<Vec<&'a Document>, Box<dyn std::error::Error>> - Retrieved documents, most relevant first
+/// Steps:
+///   1. Return nothing for a query of nothing but stopwords (see is_blank_query), whatever the retriever
+///   2. If RAG_RETRIEVER=hybrid, rank documents with BM25 + dense similarity (title and content) via HybridIndex,
+///      keeping its embeddings at half precision if RAG_PRECISION=f16
+///   3. If RAG_RETRIEVER=chroma, export the knowledge base to a Chroma collection with kb_to_chunks
+///      and rank documents by their closest chunks
+///   4. Otherwise rank them by word overlap with rag_retrieval (baseline)
+async fn retrieve_documents<'a>(
+    query: &str,
+    kb: &'a KnowledgeBase,
+) -> Result<Vec<&'a Document>, Box<dyn std::error::Error>> {
+    if is_blank_query(query) {
+        return Ok(Vec::new());
+    }
+
+    let retriever = env::var("RAG_RETRIEVER");
+    if retriever.as_deref() == Ok("hybrid") {
+        let embedder = SentenceEmbedder::new().await?;
+        // RAG_PRECISION=f16 keeps the in-memory embeddings at half precision
+        let precision = env::var("RAG_PRECISION")
+            .ok()
+            .and_then(|name| Precision::from_name(&name))
+            .unwrap_or_default();
+        let index = HybridIndex::new(kb, &embedder, precision)?;
+        let ranked = index.retrieve(query, 3, 0.5, DEFAULT_TITLE_WEIGHT, &embedder)?;
+        Ok(ranked.into_iter().map(|(doc, _)| doc).collect())
+    } else if retriever.as_deref() == Ok("chroma") {
+        let embedder = SentenceEmbedder::new().await?;
+        let collection =
+            build_chroma_collection(&kb_to_chunks(kb), "rag_kb_collection", &embedder).await?;
+        let doc_ids = dense_retrieval(&collection, query, 3, &embedder).await?;
+        Ok(doc_ids.iter().filter_map(|id| kb.get(id)).collect())
     } else {
-        let context = documents
-            .iter()
-            .map(|doc| format!("{}: {}", doc.title, doc.content))
-            .collect::<Vec<_>>()
-            .join("\n");
-        format!(
-            "Using the following information:\n'{}'\nAnswer: {}",
-            context, query
-        )
-    };
-    llm.get_llm_response(&prompt).await
+        let ranked = rag_retrieval(query, kb);
+        Ok(ranked.into_iter().map(|(doc, _)| doc).collect())
+    }
+}
+
+/// compare_approaches function
+/// Parameters:
+///   query: &str - The user's question
+///   kb: &KnowledgeBase - The knowledge base
+///   llm: &dyn LlmBackend - The LLM backend (OpenAI client or mock)
+///   dry_run: bool - Return the prompts instead of calling the LLM
+/// Returns: Result<CompareResult, Box<dyn std::error::Error>> - Both answers and the retrieved document ids
+/// Steps:
+///   1. Generate the naive answer with naive_generation
+///   2. Retrieve documents with retrieve_documents and record their ids
+///   3. Generate the RAG answer with rag_generation
+async fn compare_approaches(
+    query: &str,
+    kb: &KnowledgeBase,
+    llm: &dyn LlmBackend,
+    dry_run: bool,
+) -> Result<CompareResult, Box<dyn std::error::Error>> {
+    let naive = naive_generation(query, llm, dry_run).await?;
+
+    let retrieved_docs = retrieve_documents(query, kb).await?;
+    let retrieved_doc_ids = retrieved_docs.iter().map(|doc| doc.id.clone()).collect();
+
+    let (rag, docs_in_context) = rag_generation(query, retrieved_docs, 1000, llm, dry_run).await?;
+
+    Ok(CompareResult {
+        naive,
+        rag,
+        retrieved_doc_ids,
+        docs_in_context,
+    })
+}
+
+/// Whether `value` switches a flag on: "1", "true", "yes" or "on", in any case.
+fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Whether the environment variable `name` is set to a true value (see
+/// `is_truthy`); unset, empty, "0" and "false" all leave the flag off.
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| is_truthy(&value))
 }
 
 /// Main entry point for the RAG application.
@@ -145,25 +328,140 @@ async fn rag_generation(
 /// The two answers are then printed to the console for comparison.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let kb = create_knowledge_base();
-    let query = "What are the applications of Project Chimera?";
+    // --dry-run (or DRY_RUN=1) prints the prompts instead of calling the LLM
+    let dry_run = env::args().any(|arg| arg == "--dry-run") || env_flag("DRY_RUN");
 
-    // Create LlmClient instance
-    let llm_client = llm::LlmClient::new();
+    // An optional JSON corpus path replaces the built-in knowledge base
+    let kb = match env::args().skip(1).find(|arg| arg != "--dry-run") {
+        Some(path) => load_knowledge_base(&path)?,
+        None => create_knowledge_base(),
+    };
+    let query = "What are the applications of Project Chimera?";
 
-    // Call naive_generation and print result
-    println!(
-        "Naive approach: {}",
-        naive_generation(query, &llm_client).await?
-    );
+    // LLM_BACKEND=mock echoes prompts instead of calling OpenAI (no API key needed).
+    // A dry run never calls the LLM, so it does not need a key either.
+    let llm: Box<dyn LlmBackend> = if dry_run || env::var("LLM_BACKEND").as_deref() == Ok("mock") {
+        Box::new(MockLlm::new(Vec::new()))
+    } else {
+        // NO_CONTEXT_MESSAGE overrides the reply given when nothing relevant is retrieved
+        let mut client = LlmClient::new();
+        if let Ok(message) = env::var("NO_CONTEXT_MESSAGE") {
+            client = client.with_no_context_message(message);
+        }
+        Box::new(client)
+    };
 
-    // Call rag_retrieval to get relevant document
-    let retrieved_docs = rag_retrieval(query, &kb);
-    // Call rag_generation with document and print result
+    // Run both approaches and print the comparison
+    let result = compare_approaches(query, &kb, llm.as_ref(), dry_run).await?;
+    if dry_run {
+        println!("[dry run] Prompts are shown in place of answers; no LLM was called.");
+    }
+    println!("Naive approach: {}", result.naive);
+    for id in &result.retrieved_doc_ids {
+        let metadata = kb
+            .get(id)
+            .map(|doc| doc.metadata_summary())
+            .unwrap_or_default();
+        println!("Retrieved {} [{}]", id, metadata);
+    }
     println!(
-        "RAG approach: {}",
-        rag_generation(query, retrieved_docs, &llm_client).await?
+        "RAG approach ({} document(s) in context): {}",
+        result.docs_in_context, result.rag
     );
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kb() -> KnowledgeBase {
+        let doc = Document {
+            id: "1".to_string(),
+            title: "Quarterly report".to_string(),
+            content: "The quarterly report is out and it has what we expected".to_string(),
+            metadata: HashMap::new(),
+        };
+        HashMap::from([(doc.id.clone(), doc)])
+    }
+
+    #[test]
+    fn empty_whitespace_and_stopword_queries_retrieve_nothing() {
+        let kb = kb();
+        for query in ["", "   ", "what is the?"] {
+            assert!(rag_retrieval(query, &kb).is_empty(), "{query:?}");
+        }
+        assert!(!rag_retrieval("quarterly report", &kb).is_empty());
+    }
+
+    #[tokio::test]
+    async fn naive_generation_returns_the_model_reply() {
+        let llm = MockLlm::new(vec!["Paris".to_string()]);
+        let answer = naive_generation("capital of France?", &llm, false)
+            .await
+            .unwrap();
+        assert_eq!(answer, "Paris");
+    }
+
+    #[tokio::test]
+    async fn rag_generation_without_documents_skips_the_model() {
+        let llm = MockLlm::new(vec!["should not be used".to_string()]);
+        let (answer, included) = rag_generation("anything", Vec::new(), 1000, &llm, false)
+            .await
+            .unwrap();
+        assert_eq!(answer, llm.no_context_message());
+        assert_eq!(included, 0);
+    }
+
+    #[tokio::test]
+    async fn rag_generation_puts_retrieved_documents_in_the_prompt() {
+        let kb = kb();
+        let docs: Vec<&Document> = kb.values().collect();
+        // No canned replies: the mock echoes the prompt back
+        let llm = MockLlm::new(Vec::new());
+        let (prompt, included) = rag_generation("what is out?", docs, 1000, &llm, false)
+            .await
+            .unwrap();
+        assert_eq!(included, 1);
+        assert!(prompt.contains("Quarterly report: The quarterly report is out"));
+        assert!(prompt.ends_with("Answer: what is out?"));
+    }
+
+    #[tokio::test]
+    async fn rag_generation_notes_documents_over_the_budget() {
+        let kb = create_knowledge_base();
+        let mut docs: Vec<&Document> = kb.values().collect();
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+        let llm = MockLlm::new(Vec::new());
+        let first_tokens = approx_token_count(&format!("{}: {}", docs[0].title, docs[0].content));
+        let (prompt, included) = rag_generation("chimera", docs, first_tokens, &llm, false)
+            .await
+            .unwrap();
+        assert_eq!(included, 1);
+        assert!(prompt.contains("(2 less relevant document(s) omitted"));
+    }
+
+    #[tokio::test]
+    async fn compare_approaches_answers_both_ways() {
+        let kb = kb();
+        let llm = MockLlm::new(vec!["naive".to_string(), "grounded".to_string()]);
+        let result = compare_approaches("quarterly report", &kb, &llm, false)
+            .await
+            .unwrap();
+        assert_eq!(result.naive, "naive");
+        assert_eq!(result.rag, "grounded");
+        assert_eq!(result.retrieved_doc_ids, ["1"]);
+        assert_eq!(result.docs_in_context, 1);
+    }
+
+    #[test]
+    fn only_true_values_turn_a_flag_on() {
+        for value in ["1", "true", "TRUE", " yes ", "On"] {
+            assert!(is_truthy(value), "{value:?}");
+        }
+        for value in ["", "0", "false", "no", "off", "maybe"] {
+            assert!(!is_truthy(value), "{value:?}");
+        }
+    }
+}
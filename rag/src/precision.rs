@@ -0,0 +1,52 @@
+use half::f16;
+use std::borrow::Cow;
+
+/// Floating-point precision at which embeddings are kept in memory.
+///
+/// `F16` halves the memory of `F32`: 3 KB instead of 6 KB per 1536-dim
+/// vector. Vectors are converted back to f32 before any similarity is
+/// computed, so only storage is affected. Rounding to f16 keeps about three
+/// significant digits, which moves cosine similarities by roughly 1e-3;
+/// results whose scores are that close may swap ranks, but top-k recall
+/// is otherwise unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    #[default]
+    F32,
+    F16,
+}
+
+impl Precision {
+    /// Parse "f32" or "f16", ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "f32" => Some(Precision::F32),
+            "f16" => Some(Precision::F16),
+            _ => None,
+        }
+    }
+}
+
+/// An embedding stored at a given `Precision`.
+#[derive(Debug, Clone)]
+pub enum StoredVector {
+    F32(Vec<f32>),
+    F16(Vec<f16>),
+}
+
+impl StoredVector {
+    pub fn new(vector: Vec<f32>, precision: Precision) -> Self {
+        match precision {
+            Precision::F32 => StoredVector::F32(vector),
+            Precision::F16 => StoredVector::F16(vector.into_iter().map(f16::from_f32).collect()),
+        }
+    }
+
+    /// The vector as f32, borrowed when it is already stored that way.
+    pub fn to_f32(&self) -> Cow<'_, [f32]> {
+        match self {
+            StoredVector::F32(vector) => Cow::Borrowed(vector),
+            StoredVector::F16(vector) => Cow::Owned(vector.iter().map(|x| x.to_f32()).collect()),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+/// Approximate the token count of `text` (1 token ≈ 0.75 words).
+pub fn approx_token_count(text: &str) -> usize {
+    let word_count = text.split_whitespace().count();
+    (word_count as f32 / 0.75).ceil() as usize
+}
+
+/// Truncate `text` so it fits within `max_tokens`, cutting at a sentence boundary
+/// as found by `splitter`.
+///
+/// Tokens are counted with `count_tokens` (e.g. `approx_token_count`). Returns the
+/// kept text and whether anything was dropped. Sentences are kept in order up to
+/// the last complete one that fits; if not even the first sentence fits, its
+/// leading words are kept instead so the result is never empty for non-empty input.
+/// `count_tokens` must not give a longer prefix of a sentence fewer tokens.
+pub fn truncate_to_budget<F>(
+    text: &str,
+    max_tokens: usize,
+    count_tokens: F,
+    splitter: &SentenceSplitter,
+) -> (String, bool)
+where
+    F: Fn(&str) -> usize,
+{
+    if count_tokens(text) <= max_tokens {
+        return (text.to_string(), false);
+    }
+
+    let sentences: Vec<&str> = splitter
+        .split(text)
+        .into_iter()
+        .filter(|s| !s.trim().is_empty())
+        .collect();
+
+    let mut truncated = String::new();
+    let mut current_tokens = 0;
+
+    // Add sentences until reaching the token limit
+    for sentence in &sentences {
+        let sentence_tokens = count_tokens(sentence);
+        if current_tokens + sentence_tokens > max_tokens {
+            break;
+        }
+        truncated.push_str(sentence);
+        current_tokens += sentence_tokens;
+    }
+
+    // The first sentence alone is over budget: keep as many of its words as fit,
+    // binary-searching the prefix length so a long unpunctuated sentence costs
+    // O(log n) token counts rather than one per word
+    if truncated.trim().is_empty() {
+        let words: Vec<&str> = sentences
+            .first()
+            .copied()
+            .unwrap_or(text)
+            .split_whitespace()
+            .collect();
+        let (mut fits, mut too_long) = (0, words.len() + 1);
+        while too_long - fits > 1 {
+            let mid = fits + (too_long - fits) / 2;
+            if count_tokens(&words[..mid].join(" ")) <= max_tokens {
+                fits = mid;
+            } else {
+                too_long = mid;
+            }
+        }
+        truncated = words[..fits].join(" ");
+    }
+
+    (truncated, true)
+}
+
+/// Abbreviations (lowercase, with their trailing period) that do not end a sentence.
+pub const DEFAULT_ABBREVIATIONS: &[&str] = &[
+    "mr.", "mrs.", "ms.", "dr.", "prof.", "sr.", "jr.", "st.", "inc.", "ltd.", "co.", "corp.",
+    "vs.", "etc.", "e.g.", "i.e.", "u.s.", "u.k.", "no.", "fig.", "approx.",
+];
+
+/// Sentence terminators every splitter knows.
+pub const DEFAULT_TERMINATORS: &[char] = &['.', '!', '?'];
+
+/// Full-width terminators of Chinese and Japanese text, for
+/// `SentenceSplitter::with_terminators`.
+pub const CJK_TERMINATORS: &[char] = &['。', '！', '？', '｡', '．'];
+
+/// Closing quotes and brackets kept with the sentence they end.
+const CLOSERS: &[char] = &['"', '\'', ')', ']', '”', '’', '」', '』', '）', '】'];
+
+/// Splits text into sentences on '.', '!' and '?', plus any terminators added
+/// with `with_terminators`.
+///
+/// An ASCII terminator only ends a sentence when it is followed by whitespace
+/// or the end of the text (closing quotes and brackets are kept with the
+/// sentence), so decimals such as "3.5" and dotted forms such as "U.S." are
+/// never split inside. Other terminators, such as the full-width ones in
+/// `CJK_TERMINATORS`, end a sentence wherever they occur, since those scripts
+/// do not put spaces between sentences, except between two digits ("３．５").
+/// A period ending a known abbreviation ("Inc.", "e.g.") does not end the
+/// sentence either; add domain-specific ones with `SentenceSplitter::new`.
+pub struct SentenceSplitter {
+    abbreviations: HashSet<String>,
+    terminators: Vec<char>,
+}
+
+impl SentenceSplitter {
+    /// A splitter that knows `DEFAULT_ABBREVIATIONS` plus `extra_abbreviations`,
+    /// which are matched ignoring case and with or without their trailing
+    /// period ("Corp" and "corp." are the same).
+    pub fn new(extra_abbreviations: &[&str]) -> Self {
+        let abbreviations = DEFAULT_ABBREVIATIONS
+            .iter()
+            .chain(extra_abbreviations)
+            .map(|abbr| format!("{}.", abbr.trim().trim_end_matches('.').to_lowercase()))
+            .collect();
+        Self {
+            abbreviations,
+            terminators: DEFAULT_TERMINATORS.to_vec(),
+        }
+    }
+
+    /// Also end sentences at `terminators`, e.g. `CJK_TERMINATORS`.
+    pub fn with_terminators(mut self, terminators: &[char]) -> Self {
+        for &terminator in terminators {
+            if !self.terminators.contains(&terminator) {
+                self.terminators.push(terminator);
+            }
+        }
+        self
+    }
+
+    /// Split `text` into sentences, each keeping its terminator and leading
+    /// whitespace, so concatenating them gives back `text`.
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            if !self.terminators.contains(&c) {
+                continue;
+            }
+
+            // Keep closing quotes/brackets and repeated terminators ("?!", "...")
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, next)) = chars.peek() {
+                if self.terminators.contains(&next) || CLOSERS.contains(&next) {
+                    end = j + next.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let at_boundary = if c.is_ascii() {
+                chars.peek().is_none_or(|&(_, next)| next.is_whitespace())
+            } else {
+                // A full-width period between digits is a decimal point ("３．５")
+                let after_digit = text[..i].chars().next_back().is_some_and(char::is_numeric);
+                !(after_digit && chars.peek().is_some_and(|&(_, next)| next.is_numeric()))
+            };
+            if at_boundary && !(c == '.' && self.ends_with_abbreviation(&text[start..end])) {
+                sentences.push(&text[start..end]);
+                start = end;
+            }
+        }
+
+        if start < text.len() {
+            sentences.push(&text[start..]);
+        }
+        sentences
+    }
+
+    /// Whether the last word of `sentence` is a known abbreviation.
+    fn ends_with_abbreviation(&self, sentence: &str) -> bool {
+        sentence
+            .split_whitespace()
+            .next_back()
+            .map(|word| {
+                word.trim_start_matches(['(', '"', '\'', '“', '‘'])
+                    .to_lowercase()
+            })
+            .is_some_and(|word| self.abbreviations.contains(&word))
+    }
+}
+
+impl Default for SentenceSplitter {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviations_and_decimals_do_not_end_sentences() {
+        let splitter = SentenceSplitter::default();
+        for (text, first) in [
+            (
+                "Acme Inc. announced results. Shares rose.",
+                "Acme Inc. announced results.",
+            ),
+            (
+                "Cache it, e.g. the policy above. It helps.",
+                "Cache it, e.g. the policy above.",
+            ),
+            (
+                "Dr. Lee met Mr. Park in the U.S. today. Then left.",
+                "Dr. Lee met Mr. Park in the U.S. today.",
+            ),
+            (
+                "Revenue grew 3.5 percent. Costs fell.",
+                "Revenue grew 3.5 percent.",
+            ),
+        ] {
+            assert_eq!(splitter.split(text)[0], first);
+            assert_eq!(splitter.split(text).len(), 2, "{text}");
+        }
+    }
+
+    #[test]
+    fn extra_abbreviations_need_no_trailing_period() {
+        let text = "The Dept. of Energy agreed. Fine.";
+        assert_eq!(SentenceSplitter::default().split(text).len(), 3);
+        for extra in ["Dept", "dept."] {
+            assert_eq!(SentenceSplitter::new(&[extra]).split(text).len(), 2);
+        }
+    }
+
+    const JAPANESE: &str = "今日は晴れです。明日は雨が降るでしょう！傘を持っていきますか？";
+
+    #[test]
+    fn japanese_paragraph_splits_into_sentences() {
+        let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+        assert_eq!(
+            splitter.split(JAPANESE),
+            [
+                "今日は晴れです。",
+                "明日は雨が降るでしょう！",
+                "傘を持っていきますか？"
+            ]
+        );
+    }
+
+    #[test]
+    fn default_splitter_ignores_full_width_terminators() {
+        assert_eq!(SentenceSplitter::default().split(JAPANESE), [JAPANESE]);
+    }
+
+    #[test]
+    fn full_width_period_between_digits_is_a_decimal_point() {
+        let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+        assert_eq!(
+            splitter.split("金利は３．５％です．来年は下がる。"),
+            ["金利は３．５％です．", "来年は下がる。"]
+        );
+    }
+    #[test]
+    fn unpunctuated_text_keeps_the_longest_fitting_prefix() {
+        let text = ["word"; 1000].join(" ");
+        let calls = std::cell::Cell::new(0);
+        let count_words = |text: &str| {
+            calls.set(calls.get() + 1);
+            text.split_whitespace().count()
+        };
+        let splitter = SentenceSplitter::default();
+
+        let (kept, truncated) = truncate_to_budget(&text, 10, count_words, &splitter);
+        assert!(truncated);
+        assert_eq!(kept, ["word"; 10].join(" "));
+        assert!(calls.get() < 30, "{} token counts", calls.get());
+    }
+}
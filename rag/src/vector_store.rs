@@ -0,0 +1,72 @@
+use crate::precision::{Precision, StoredVector};
+use std::borrow::Cow;
+
+/// Embeddings kept in memory at one `Precision`, for retrievers that do not
+/// use a vector database.
+///
+/// Each vector is converted as it is added, so an f16 store never holds a
+/// second, f32 copy of its contents. Vectors are read back as f32 for
+/// similarity; see `Precision` for the accuracy cost.
+pub struct VectorStore {
+    precision: Precision,
+    vectors: Vec<StoredVector>,
+}
+
+impl VectorStore {
+    pub fn new(precision: Precision) -> Self {
+        Self {
+            precision,
+            vectors: Vec::new(),
+        }
+    }
+
+    /// A store holding `vectors`, in order.
+    pub fn from_vectors(vectors: Vec<Vec<f32>>, precision: Precision) -> Self {
+        let mut store = Self::new(precision);
+        for vector in vectors {
+            store.push(vector);
+        }
+        store
+    }
+
+    pub fn push(&mut self, vector: Vec<f32>) {
+        self.vectors.push(StoredVector::new(vector, self.precision));
+    }
+
+    /// Every vector as f32, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, [f32]>> {
+        self.vectors.iter().map(StoredVector::to_f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vectors() -> Vec<Vec<f32>> {
+        vec![
+            vec![0.1, 0.2, 0.3],
+            vec![-1.5, 0.0, 2.25],
+            vec![0.333, 0.667, 1.0],
+        ]
+    }
+
+    #[test]
+    fn f32_store_returns_vectors_unchanged() {
+        let store = VectorStore::from_vectors(vectors(), Precision::F32);
+        let stored: Vec<Vec<f32>> = store.iter().map(Cow::into_owned).collect();
+        assert_eq!(stored, vectors());
+    }
+
+    #[test]
+    fn f16_store_keeps_order_and_about_three_digits() {
+        let store = VectorStore::from_vectors(vectors(), Precision::F16);
+        let stored: Vec<Vec<f32>> = store.iter().map(Cow::into_owned).collect();
+        assert_eq!(stored.len(), 3);
+        for (stored, original) in stored.iter().zip(vectors()) {
+            for (x, y) in stored.iter().zip(original) {
+                assert!((x - y).abs() <= 1e-3 * y.abs().max(1.0), "{x} vs {y}");
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::embeddings::Embedder;
+use crate::error::RagError;
+use crate::pipeline::RetrievalSettings;
+use crate::precision::{Precision, StoredVector};
+use crate::vector_db::{
+    QueryInput, RetrievedChunk, collection_version, is_blank_query, retrieve_top_chunks,
+};
+use chromadb::collection::ChromaCollection;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Largest cosine distance between two query embeddings for them to share a
+/// cache entry. Small enough that only rephrasings such as a changed
+/// punctuation mark or letter case hit.
+const CACHE_EPSILON: f32 = 0.01;
+
+/// Owned copy of the settings a cached result was retrieved with.
+#[derive(PartialEq)]
+struct SettingsKey {
+    top_k: usize,
+    category_filter: Option<String>,
+    exclude_categories: Option<Vec<String>>,
+    distance_threshold: Option<f32>,
+    min_chunk_words: Option<usize>,
+    pinned_ids: Vec<String>,
+}
+
+impl SettingsKey {
+    fn new(settings: &RetrievalSettings<'_>) -> Self {
+        Self {
+            top_k: settings.top_k,
+            category_filter: settings.category_filter.map(str::to_string),
+            exclude_categories: settings.exclude_categories.clone(),
+            distance_threshold: settings.distance_threshold,
+            min_chunk_words: settings.min_chunk_words,
+            pinned_ids: settings.pinned_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+}
+
+struct CacheEntry {
+    embedding: StoredVector,
+    settings: SettingsKey,
+    chunks: Vec<RetrievedChunk>,
+}
+
+/// Least-recently-used cache of retrieval results, most recent first.
+struct QueryCache {
+    capacity: usize,
+    precision: Precision,
+    /// `collection_version` of the cached collection when the entries were
+    /// stored; any later change to the collection empties the cache.
+    version_seen: u64,
+    entries: VecDeque<CacheEntry>,
+}
+
+impl QueryCache {
+    fn get(
+        &mut self,
+        version: u64,
+        embedding: &[f32],
+        settings: &SettingsKey,
+    ) -> Option<Vec<RetrievedChunk>> {
+        self.invalidate_if_stale(version);
+        let pos = self.entries.iter().position(|entry| {
+            entry.settings == *settings
+                && cosine_distance(&entry.embedding.to_f32(), embedding) <= CACHE_EPSILON
+        })?;
+        let entry = self.entries.remove(pos)?;
+        let chunks = entry.chunks.clone();
+        self.entries.push_front(entry);
+        Some(chunks)
+    }
+
+    fn insert(
+        &mut self,
+        version: u64,
+        embedding: Vec<f32>,
+        settings: SettingsKey,
+        chunks: Vec<RetrievedChunk>,
+    ) {
+        self.invalidate_if_stale(version);
+        self.entries.push_front(CacheEntry {
+            embedding: StoredVector::new(embedding, self.precision),
+            settings,
+            chunks,
+        });
+        self.entries.truncate(self.capacity);
+    }
+
+    fn invalidate_if_stale(&mut self, version: u64) {
+        if version != self.version_seen {
+            self.entries.clear();
+            self.version_seen = version;
+        }
+    }
+}
+
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return f32::INFINITY;
+    }
+    1.0 - dot / (norm_a * norm_b)
+}
+
+/// Retrieves chunks from a collection, optionally caching results so that
+/// repeated or near-identical queries skip the Chroma round trip.
+pub struct Retriever<'a> {
+    collection: &'a ChromaCollection,
+    cache: Option<Mutex<QueryCache>>,
+}
+
+impl<'a> Retriever<'a> {
+    /// A retriever without a cache.
+    pub fn new(collection: &'a ChromaCollection) -> Self {
+        Self {
+            collection,
+            cache: None,
+        }
+    }
+
+    /// Cache up to `capacity` results, evicting the least recently used.
+    ///
+    /// A query hits when its embedding is within a small cosine distance of a
+    /// cached query's and the retrieval settings are identical. The cache is
+    /// emptied whenever this process upserts into or deletes from the
+    /// retriever's collection; changes to other collections leave it alone.
+    pub fn with_query_cache(mut self, capacity: usize) -> Self {
+        self.cache = (capacity > 0).then(|| {
+            Mutex::new(QueryCache {
+                capacity,
+                precision: Precision::F32,
+                version_seen: collection_version(self.collection.name()),
+                entries: VecDeque::new(),
+            })
+        });
+        self
+    }
+
+    /// Keep cached query embeddings at `precision`. Has no effect without
+    /// `with_query_cache`; entries already cached keep their precision.
+    pub fn with_cache_precision(self, precision: Precision) -> Self {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().precision = precision;
+        }
+        self
+    }
+
+    pub fn collection(&self) -> &'a ChromaCollection {
+        self.collection
+    }
+
+    /// Drop every cached result.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().entries.clear();
+        }
+    }
+
+    /// Retrieve chunks for `query` as `retrieve_top_chunks` does, answering from
+    /// the cache when possible. A blank text query fails with
+    /// `RagError::EmptyQuery` before anything is embedded or looked up.
+    pub async fn retrieve<'q>(
+        &self,
+        query: impl Into<QueryInput<'q>>,
+        embedder: &dyn Embedder,
+        settings: &RetrievalSettings<'_>,
+    ) -> Result<Vec<RetrievedChunk>, Box<dyn std::error::Error>> {
+        let query = query.into();
+        if let QueryInput::Text(text) = query
+            && is_blank_query(text)
+        {
+            return Err(RagError::EmptyQuery.into());
+        }
+        let query_embedding = query.to_embedding(embedder)?;
+        let key = SettingsKey::new(settings);
+        // Read once: results fetched before a concurrent change must be cached
+        // under the version they were fetched at
+        let version = collection_version(self.collection.name());
+
+        if let Some(cache) = &self.cache
+            && let Some(chunks) = cache.lock().unwrap().get(version, &query_embedding, &key)
+        {
+            debug!("Query cache hit ({} chunks)", chunks.len());
+            return Ok(chunks);
+        }
+
+        let chunks = retrieve_top_chunks(
+            self.collection,
+            query_embedding.as_slice(),
+            settings.top_k,
+            embedder,
+            settings.category_filter,
+            settings.exclude_categories.clone(),
+            settings.distance_threshold,
+            settings.min_chunk_words,
+            settings.pinned_ids,
+        )
+        .await?;
+
+        if let Some(cache) = &self.cache {
+            cache
+                .lock()
+                .unwrap()
+                .insert(version, query_embedding, key, chunks.clone());
+        }
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> SettingsKey {
+        SettingsKey {
+            top_k: 3,
+            category_filter: None,
+            exclude_categories: None,
+            distance_threshold: None,
+            min_chunk_words: None,
+            pinned_ids: Vec::new(),
+        }
+    }
+
+    fn cached(id: &str) -> Vec<RetrievedChunk> {
+        vec![RetrievedChunk {
+            id: id.to_string(),
+            chunk: String::new(),
+            doc_id: 0,
+            chunk_id: 0,
+            distance: Some(0.1),
+            pinned: false,
+        }]
+    }
+
+    #[test]
+    fn a_new_collection_version_empties_the_cache() {
+        let mut cache = QueryCache {
+            capacity: 2,
+            precision: Precision::F32,
+            version_seen: 0,
+            entries: VecDeque::new(),
+        };
+        cache.insert(0, vec![1.0, 0.0], settings(), cached("doc_0_chunk_0"));
+
+        let hit = cache.get(0, &[1.0, 0.001], &settings()).unwrap();
+        assert_eq!(hit[0].id, "doc_0_chunk_0");
+        assert!(cache.get(1, &[1.0, 0.0], &settings()).is_none());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::embeddings::Embedder;
+use crate::llm::cosine_similarity;
+use crate::sentences::SentenceSplitter;
+use crate::vector_db::RetrievedChunk;
+use std::error::Error;
+
+/// Keep only the sentences of `chunk` most similar to `query`.
+///
+/// The chunk is split into sentences with `splitter`, each is scored by
+/// embedding similarity to the query, and the best `keep_ratio` fraction
+/// (rounded up, and never less than one sentence) is kept in its original
+/// order. A chunk of one sentence is returned unchanged without embedding.
+pub fn compress_chunk(
+    chunk: &str,
+    query: &str,
+    embedder: &dyn Embedder,
+    keep_ratio: f32,
+    splitter: &SentenceSplitter,
+) -> Result<String, Box<dyn Error>> {
+    let sentences: Vec<&str> = splitter
+        .split(chunk)
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if sentences.len() <= 1 {
+        return Ok(chunk.to_string());
+    }
+
+    let keep = ((sentences.len() as f32 * keep_ratio.clamp(0.0, 1.0)).ceil() as usize)
+        .clamp(1, sentences.len());
+    if keep == sentences.len() {
+        return Ok(sentences.join(" "));
+    }
+
+    // Query first, then the sentences, in a single embedding call
+    let mut texts = Vec::with_capacity(sentences.len() + 1);
+    texts.push(query);
+    texts.extend(&sentences);
+    let mut embeddings = embedder.embed_texts(&texts)?;
+    let query_embedding = embeddings.remove(0);
+
+    let mut ranked: Vec<(usize, f32)> = embeddings
+        .iter()
+        .enumerate()
+        .map(|(i, emb)| (i, cosine_similarity(&query_embedding, emb)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut kept: Vec<usize> = ranked.into_iter().take(keep).map(|(i, _)| i).collect();
+    kept.sort_unstable();
+
+    Ok(kept
+        .into_iter()
+        .map(|i| sentences[i])
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Apply `compress_chunk` to every retrieved chunk, keeping everything else
+/// about them (ids, distances, order) as is.
+pub fn compress_chunks(
+    chunks: Vec<RetrievedChunk>,
+    query: &str,
+    embedder: &dyn Embedder,
+    keep_ratio: f32,
+    splitter: &SentenceSplitter,
+) -> Result<Vec<RetrievedChunk>, Box<dyn Error>> {
+    chunks
+        .into_iter()
+        .map(|mut chunk| {
+            chunk.chunk = compress_chunk(&chunk.chunk, query, embedder, keep_ratio, splitter)?;
+            Ok(chunk)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+    use crate::sentences::CJK_TERMINATORS;
+
+    #[test]
+    fn japanese_chunk_is_compressed_by_sentence() {
+        let chunk = "売上は増えた。利益は減った。配当は据え置き。";
+        let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+        let compressed =
+            compress_chunk(chunk, "利益", &HashEmbedder::new(64), 0.5, &splitter).unwrap();
+        // Two of the three sentences are kept, in their original order
+        let kept: Vec<&str> = compressed.split(' ').collect();
+        assert_eq!(kept.len(), 2);
+        assert!(kept.iter().all(|sentence| chunk.contains(sentence)));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::llm::{LlmClient, clean_answer};
+use crate::prompt::render_builtin;
+use tracing::debug;
+
+/// Critique rounds run when no other number is given.
+pub const DEFAULT_CRITIQUE_ROUNDS: usize = 1;
+
+/// Reply the critic gives when a draft needs no changes.
+const NO_ISSUES: &str = "NO ISSUES";
+
+const ANSWER_SYSTEM_PROMPT: &str = "You answer questions using only the provided context.";
+
+const CRITIC_SYSTEM_PROMPT: &str =
+    "You are a careful reviewer checking an answer against its source context.";
+
+const REVISER_SYSTEM_PROMPT: &str =
+    "You revise answers so that every claim is supported by the provided context.";
+
+/// The critic's notes on each draft of a `generate_self_critique` answer.
+#[derive(Debug, Clone, Default)]
+pub struct Critique {
+    /// One critique per round, in order. The last one is "NO ISSUES" if the
+    /// loop stopped because the critic found nothing to fix.
+    pub notes: Vec<String>,
+    /// How many times the answer was rewritten.
+    pub revisions: usize,
+}
+
+/// Answer `query` from `context`, then have the LLM critique and revise its
+/// own answer up to `rounds` times.
+///
+/// Each round asks the model to list claims the context does not support and
+/// information the context holds that the answer leaves out; if it finds
+/// neither, the loop stops early and the draft is kept. Otherwise the answer
+/// is rewritten to address the critique. A round costs two LLM calls on top
+/// of the first answer, one with no issues costs one; `rounds` of 0 is plain
+/// single-shot generation. Returns the final answer and the critique notes.
+pub async fn generate_self_critique(
+    query: &str,
+    context: &str,
+    llm: &LlmClient,
+    rounds: usize,
+) -> Result<(String, Critique), Box<dyn std::error::Error>> {
+    let prompt = render_builtin("draft", &[("context", context), ("query", query)]);
+    let mut answer = clean_answer(
+        &llm.get_llm_response_with_system(ANSWER_SYSTEM_PROMPT, &prompt)
+            .await?,
+    );
+    let mut critique = Critique::default();
+
+    for round in 0..rounds {
+        let critic_prompt = render_builtin(
+            "critique",
+            &[
+                ("no_issues", NO_ISSUES),
+                ("context", context),
+                ("query", query),
+                ("answer", &answer),
+            ],
+        );
+        let notes = llm
+            .get_llm_response_with_system(CRITIC_SYSTEM_PROMPT, &critic_prompt)
+            .await?
+            .trim()
+            .to_string();
+        let clean = notes
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .eq_ignore_ascii_case(NO_ISSUES);
+        debug!(round, clean, "self-critique");
+        if clean {
+            critique.notes.push(NO_ISSUES.to_string());
+            break;
+        }
+
+        let revise_prompt = render_builtin(
+            "revise",
+            &[
+                ("context", context),
+                ("query", query),
+                ("answer", &answer),
+                ("review", &notes),
+            ],
+        );
+        answer = clean_answer(
+            &llm.get_llm_response_with_system(REVISER_SYSTEM_PROMPT, &revise_prompt)
+                .await?,
+        );
+        critique.notes.push(notes);
+        critique.revisions += 1;
+    }
+
+    Ok((answer, critique))
+}
@@ -11,11 +11,34 @@ pub struct Chunk {
     pub text: String,
 }
 
+/// One entry of the JSON corpus, as deserialized from the file.
 #[derive(Debug, Deserialize)]
-struct Document {
-    id: usize,
-    content: String,
-    category: Option<String>,
+pub struct Document {
+    /// Explicit document id; without one, loaders number it after the largest
+    /// explicit id (see `assign_doc_ids`).
+    pub id: Option<usize>,
+    pub content: String,
+    pub category: Option<String>,
+}
+
+/// The id of each document, in file order: its explicit `id`, or for a
+/// document without one the next number after the largest explicit id, so a
+/// fallback id never collides with an explicit one.
+fn assign_doc_ids(documents: &[Document]) -> Vec<usize> {
+    let mut next_id = documents
+        .iter()
+        .filter_map(|doc| doc.id)
+        .max()
+        .map_or(0, |max| max + 1);
+    documents
+        .iter()
+        .map(|doc| {
+            doc.id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            })
+        })
+        .collect()
 }
 
 /// Loads the dataset from the given JSON file and returns full documents as single chunks.
@@ -24,13 +47,15 @@ pub fn load_documents(file_path: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
     let reader = BufReader::new(file);
     let documents: Vec<Document> = serde_json::from_reader(reader)?;
 
+    let doc_ids = assign_doc_ids(&documents);
     // Use map to convert each Document into a Chunk.
     let docs = documents
         .into_iter()
-        .map(|doc| {
+        .zip(doc_ids)
+        .map(|(doc, doc_id)| {
             let category = doc.category.unwrap_or_else(|| "general".to_string());
             Chunk {
-                doc_id: doc.id,
+                doc_id,
                 chunk_id: 0,
                 category,
                 text: doc.content,
@@ -40,3 +65,21 @@ pub fn load_documents(file_path: &str) -> Result<Vec<Chunk>, Box<dyn Error>> {
 
     Ok(docs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_ids_follow_the_largest_explicit_id() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": 5, "content": "a"}, {"content": "b"}, {"id": 2, "content": "c"}, {"content": "d"}]"#,
+        )
+        .unwrap();
+        assert_eq!(assign_doc_ids(&documents), [5, 6, 2, 7]);
+
+        let documents: Vec<Document> =
+            serde_json::from_str(r#"[{"content": "a"}, {"content": "b"}]"#).unwrap();
+        assert_eq!(assign_doc_ids(&documents), [0, 1]);
+    }
+}
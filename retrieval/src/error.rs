@@ -0,0 +1,92 @@
+use std::fmt;
+
+/// A component checked by `warmup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Embedder,
+    VectorDb,
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Component::Embedder => write!(f, "embedder"),
+            Component::VectorDb => write!(f, "ChromaDB collection"),
+        }
+    }
+}
+
+/// Errors the pipeline reports with a typed cause instead of a raw client error.
+pub enum RagError {
+    /// The Chroma server could not be reached at `url`.
+    VectorDbUnavailable { url: String, reason: String },
+    /// The query has no words but stopwords, so there is nothing to search for.
+    EmptyQuery,
+    /// `warmup` could not use `component`.
+    WarmupFailed { component: Component, reason: String },
+    /// The judge's reply to `grade_answer` held no score within the scale.
+    UnparsableGrade { reply: String },
+    /// Input `index` has more words than the embedder accepts.
+    InputTooLong { index: usize, words: usize, max_words: usize },
+    /// The new embedder's vectors do not fit the collection's dimension.
+    DimensionMismatch { collection: usize, embedder: usize },
+    /// The collection was built from normalized vectors and the embedder's are
+    /// not, or the other way round.
+    NormalizationMismatch { collection_normalized: bool, embedder_normalized: bool },
+    /// A prompt template uses `{placeholder}` but no value was given for it.
+    MissingPromptValue { placeholder: String },
+}
+
+impl fmt::Display for RagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RagError::VectorDbUnavailable { url, reason } => write!(
+                f,
+                "could not reach ChromaDB at {} ({}).\n\
+                 Start a local server with `docker run -p 8000:8000 chromadb/chroma` and try again.",
+                url, reason
+            ),
+            RagError::EmptyQuery => write!(f, "the query is empty; enter a question to search for"),
+            RagError::WarmupFailed { component, reason } => {
+                write!(f, "warmup failed: the {} is not usable ({})", component, reason)
+            }
+            RagError::UnparsableGrade { reply } => {
+                write!(f, "the judge's reply has no score on the grading scale: {:?}", reply)
+            }
+            RagError::InputTooLong { index, words, max_words } => write!(
+                f,
+                "input {} has {} words, more than the embedder's limit of {}; chunk it first",
+                index, words, max_words
+            ),
+            RagError::DimensionMismatch { collection, embedder } => write!(
+                f,
+                "the collection holds {}-dimensional vectors but the embedder produces {}; \
+                 Chroma cannot change a collection's dimension, so delete the collection and \
+                 rebuild it from the source documents instead",
+                collection, embedder
+            ),
+            RagError::NormalizationMismatch { collection_normalized, embedder_normalized } => {
+                let describe = |normalized: &bool| if *normalized { "normalized" } else { "unnormalized" };
+                write!(
+                    f,
+                    "the collection was built from {} vectors but the embedder produces {} ones; \
+                     query it with the embedder it was built with or rebuild it",
+                    describe(collection_normalized),
+                    describe(embedder_normalized)
+                )
+            }
+            RagError::MissingPromptValue { placeholder } => {
+                write!(f, "prompt template placeholder {{{}}} has no value", placeholder)
+            }
+        }
+    }
+}
+
+// `main` prints returned errors with `{:?}`, so show the actionable message there too.
+impl fmt::Debug for RagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RagError {}
@@ -0,0 +1,168 @@
+use chromadb::collection::{ChromaCollection, GetOptions, GetResult};
+use crate::embeddings::Embedder;
+use crate::vector_db::{retrieve_top_chunks, RetrievedChunk};
+use tracing::warn;
+
+/// Weights for Rocchio relevance feedback.
+///
+/// `alpha` keeps the original query, `beta` pulls it toward the relevant
+/// chunks and `gamma` pushes it away from the non-relevant ones.
+pub struct RocchioParams {
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+}
+
+impl Default for RocchioParams {
+    fn default() -> Self {
+        // Classic values from the SMART system.
+        Self { alpha: 1.0, beta: 0.75, gamma: 0.15 }
+    }
+}
+
+/// Compute the component-wise mean of a set of vectors, or `None` if the set is empty.
+fn centroid(embeddings: &[Vec<f32>], dim: usize) -> Option<Vec<f32>> {
+    if embeddings.is_empty() {
+        return None;
+    }
+
+    let mut sum = vec![0.0; dim];
+    for emb in embeddings {
+        for (acc, value) in sum.iter_mut().zip(emb.iter()) {
+            *acc += value;
+        }
+    }
+
+    let n = embeddings.len() as f32;
+    Some(sum.into_iter().map(|v| v / n).collect())
+}
+
+/// Apply Rocchio relevance feedback to a query embedding.
+///
+/// Returns `alpha * q + beta * centroid(relevant) - gamma * centroid(nonrelevant)`.
+/// An empty relevant or non-relevant set simply drops its term.
+pub fn rocchio_refine(
+    query_emb: &[f32],
+    relevant_embs: &[Vec<f32>],
+    nonrelevant_embs: &[Vec<f32>],
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+) -> Vec<f32> {
+    let dim = query_emb.len();
+    let mut refined: Vec<f32> = query_emb.iter().map(|v| alpha * v).collect();
+
+    if let Some(rel) = centroid(relevant_embs, dim) {
+        for (q, r) in refined.iter_mut().zip(rel) {
+            *q += beta * r;
+        }
+    }
+
+    if let Some(nonrel) = centroid(nonrelevant_embs, dim) {
+        for (q, n) in refined.iter_mut().zip(nonrel) {
+            *q -= gamma * n;
+        }
+    }
+
+    refined
+}
+
+/// The embedding stored for `id` in `response`, if Chroma returned one.
+fn stored_embedding<'a>(response: &'a GetResult, id: &str) -> Option<&'a Vec<f32>> {
+    // Chroma returns the entries in its own order, so look the id up
+    let pos = response.ids.iter().position(|found| found == id)?;
+    response.embeddings.as_ref()?.get(pos)?.as_ref()
+}
+
+/// The embeddings the collection stored for `chunks`, in order, so feedback
+/// does not embed the chunks again. A chunk the collection has no vector for
+/// is embedded from its text, with a warning.
+async fn stored_embeddings(
+    collection: &ChromaCollection,
+    chunks: &[RetrievedChunk],
+    embedder: &dyn Embedder,
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    if chunks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let options = GetOptions {
+        ids: chunks.iter().map(|chunk| chunk.id.clone()).collect(),
+        where_metadata: None,
+        limit: None,
+        offset: None,
+        where_document: None,
+        include: Some(vec!["embeddings".to_string()]),
+    };
+    let response = collection.get(options).await?;
+
+    let mut embeddings = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        match stored_embedding(&response, &chunk.id) {
+            Some(embedding) => embeddings.push(embedding.clone()),
+            None => {
+                warn!("No stored embedding for {:?}; embedding its text instead", chunk.id);
+                embeddings.push(embedder.embed_texts(&[chunk.chunk.as_str()])?.remove(0));
+            }
+        }
+    }
+    Ok(embeddings)
+}
+
+/// Re-query the collection after marking some of the first-pass results as relevant.
+///
+/// `relevant` holds the indices into `initial` judged useful; every other
+/// chunk of `initial` is treated as non-relevant. The chunks' embeddings are
+/// read back from the collection rather than computed again.
+pub async fn retrieve_with_feedback(
+    collection: &ChromaCollection,
+    query: &str,
+    initial: &[RetrievedChunk],
+    relevant: &[usize],
+    embedder: &dyn Embedder,
+    params: &RocchioParams,
+    top_k: usize,
+) -> Result<Vec<RetrievedChunk>, Box<dyn std::error::Error>> {
+    let query_emb = embedder.embed_texts(&[query])?.remove(0);
+
+    // Split the first-pass chunks' stored embeddings into relevant and non-relevant
+    let embeddings = stored_embeddings(collection, initial, embedder).await?;
+    let mut relevant_embs = Vec::new();
+    let mut nonrelevant_embs = Vec::new();
+    for (i, embedding) in embeddings.into_iter().enumerate() {
+        if relevant.contains(&i) {
+            relevant_embs.push(embedding);
+        } else {
+            nonrelevant_embs.push(embedding);
+        }
+    }
+
+    let refined = rocchio_refine(
+        &query_emb,
+        &relevant_embs,
+        &nonrelevant_embs,
+        params.alpha,
+        params.beta,
+        params.gamma,
+    );
+
+    retrieve_top_chunks(collection, refined.as_slice(), top_k, embedder, None, None, None, None, &[]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_embeddings_are_looked_up_by_id() {
+        let response = GetResult {
+            ids: vec!["doc_1_chunk_0".to_string(), "doc_0_chunk_0".to_string()],
+            documents: None,
+            metadatas: None,
+            embeddings: Some(vec![Some(vec![1.0, 0.0]), None]),
+        };
+        assert_eq!(stored_embedding(&response, "doc_1_chunk_0"), Some(&vec![1.0, 0.0]));
+        assert_eq!(stored_embedding(&response, "doc_0_chunk_0"), None);
+        assert_eq!(stored_embedding(&response, "doc_2_chunk_0"), None);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::vector_db::{RetrievedChunk, SimilarityBands, format_distance};
+use serde_json::json;
+
+/// Renders retrieved chunks and the answer generated from them.
+pub trait ResultFormatter {
+    /// Render `results`, most relevant first, followed by `answer` if there is one.
+    fn format(&self, results: &[RetrievedChunk], answer: Option<&str>) -> String;
+}
+
+/// Look up a formatter by name: "plain", "markdown" (or "md") or "json".
+///
+/// `bands` labels distances in the plain output.
+pub fn formatter_by_name(name: &str, bands: SimilarityBands) -> Option<Box<dyn ResultFormatter>> {
+    match name.to_ascii_lowercase().as_str() {
+        "plain" => Some(Box::new(PlainFormatter::new(bands))),
+        "markdown" | "md" => Some(Box::new(MarkdownFormatter)),
+        "json" => Some(Box::new(JsonFormatter)),
+        _ => None,
+    }
+}
+
+/// Terminal output: one ruled block per chunk with its distance and
+/// similarity band, then the answer between rules.
+pub struct PlainFormatter {
+    bands: SimilarityBands,
+}
+
+impl PlainFormatter {
+    pub fn new(bands: SimilarityBands) -> Self {
+        Self { bands }
+    }
+}
+
+impl ResultFormatter for PlainFormatter {
+    fn format(&self, results: &[RetrievedChunk], answer: Option<&str>) -> String {
+        let mut out = String::new();
+        for (i, chunk) in results.iter().enumerate() {
+            out.push_str(&format!("\n{}\n", "-".repeat(40)));
+            out.push_str(&format!(
+                "Document {} | ID: {} | Chroma ID: {} | Distance: {}\n",
+                i + 1,
+                chunk.doc_id,
+                chunk.id,
+                format_distance(chunk.distance)
+            ));
+            out.push_str(&format!(
+                "Similarity: {}\n",
+                chunk.distance.map_or("unknown", |d| self.bands.band_for(d))
+            ));
+            out.push_str(&format!("{}\n{}\n", "-".repeat(40), chunk.chunk));
+        }
+        if let Some(answer) = answer {
+            let rule = "=".repeat(60);
+            out.push_str(&format!("\n{rule}\nANSWER\n{rule}\n{answer}\n{rule}\n"));
+        }
+        out
+    }
+}
+
+/// A Markdown report: the answer, then the sources as a numbered list with
+/// their distances and text quoted.
+pub struct MarkdownFormatter;
+
+impl ResultFormatter for MarkdownFormatter {
+    fn format(&self, results: &[RetrievedChunk], answer: Option<&str>) -> String {
+        let mut out = String::new();
+        if let Some(answer) = answer {
+            out.push_str(&format!("## Answer\n\n{}\n\n", answer.trim()));
+        }
+        out.push_str("## Sources\n\n");
+        if results.is_empty() {
+            out.push_str("No sources retrieved.\n");
+        }
+        for (i, chunk) in results.iter().enumerate() {
+            out.push_str(&format!(
+                "{}. `{}` (document {}, chunk {}), distance {}\n",
+                i + 1,
+                chunk.id,
+                chunk.doc_id,
+                chunk.chunk_id,
+                format_distance(chunk.distance)
+            ));
+            for line in chunk.chunk.lines() {
+                out.push_str(&format!("   > {}\n", line));
+            }
+        }
+        out
+    }
+}
+
+/// A JSON object `{"answer": ..., "sources": [...]}` for other programs; the
+/// answer is `null` when there is none, as is an unknown distance.
+pub struct JsonFormatter;
+
+impl ResultFormatter for JsonFormatter {
+    fn format(&self, results: &[RetrievedChunk], answer: Option<&str>) -> String {
+        let sources: Vec<_> = results
+            .iter()
+            .map(|chunk| {
+                json!({
+                    "id": chunk.id,
+                    "doc_id": chunk.doc_id,
+                    "chunk_id": chunk.chunk_id,
+                    "distance": chunk.distance,
+                    "text": chunk.chunk,
+                })
+            })
+            .collect();
+        let output = json!({ "answer": answer, "sources": sources });
+        serde_json::to_string_pretty(&output).unwrap_or_default()
+    }
+}
@@ -0,0 +1,233 @@
+use crate::error::RagError;
+use crate::llm::LlmClient;
+use crate::prompt::PromptTemplate;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// System message for every judge call.
+const JUDGE_SYSTEM_PROMPT: &str =
+    "You are a strict grader comparing an answer to a reference answer.";
+
+/// Prompt and scale used by `grade_answer`.
+#[derive(Debug, Clone)]
+pub struct JudgeConfig {
+    /// Prompt with `{question}`, `{reference}`, `{candidate}`, `{min}` and
+    /// `{max}` placeholders.
+    pub prompt: PromptTemplate,
+    /// Lowest possible score.
+    pub min_score: u32,
+    /// Highest possible score.
+    pub max_score: u32,
+}
+
+impl Default for JudgeConfig {
+    /// The built-in prompt on a 0 to 5 scale.
+    fn default() -> Self {
+        JudgeConfig {
+            prompt: PromptTemplate::builtin("judge").expect("built-in template exists"),
+            min_score: 0,
+            max_score: 5,
+        }
+    }
+}
+
+/// The judge's verdict on one candidate answer.
+#[derive(Debug, Clone)]
+pub struct Grade {
+    pub score: u32,
+    /// The judge's reason for the score; the whole reply if it gave no
+    /// `Justification:` line.
+    pub justification: String,
+}
+
+/// Ask the LLM to grade `candidate` against `reference` for `question`.
+///
+/// The score is read from the last line starting with `Score: N` if there is
+/// one, otherwise from the first `N/max` or `N out of max`, otherwise from the
+/// first whole number within the scale, so commentary around it does not matter. A reply with no
+/// number on the scale fails with `RagError::UnparsableGrade`.
+pub async fn grade_answer(
+    question: &str,
+    reference: &str,
+    candidate: &str,
+    llm: &LlmClient,
+    judge: &JudgeConfig,
+) -> Result<Grade, Box<dyn std::error::Error>> {
+    let min = judge.min_score.to_string();
+    let max = judge.max_score.to_string();
+    let prompt = judge.prompt.render(&HashMap::from([
+        ("question", question),
+        ("reference", reference),
+        ("candidate", candidate),
+        ("min", min.as_str()),
+        ("max", max.as_str()),
+    ]))?;
+
+    let reply = llm
+        .get_llm_response_with_system(JUDGE_SYSTEM_PROMPT, &prompt)
+        .await?;
+    let score = parse_score(&reply, judge.min_score, judge.max_score).ok_or_else(|| {
+        RagError::UnparsableGrade {
+            reply: reply.clone(),
+        }
+    })?;
+
+    Ok(Grade {
+        score,
+        justification: parse_justification(&reply),
+    })
+}
+
+/// One labeled question: what was asked and what a correct answer says.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabeledQuestion {
+    pub question: String,
+    pub reference: String,
+}
+
+/// Grades of a whole test set.
+#[derive(Debug, Clone)]
+pub struct GradeReport {
+    /// One grade per question, in test-set order.
+    pub grades: Vec<Grade>,
+    pub min_score: u32,
+    pub max_score: u32,
+}
+
+impl GradeReport {
+    /// Mean score on the judge's scale; 0.0 for an empty test set.
+    pub fn mean(&self) -> f32 {
+        if self.grades.is_empty() {
+            return 0.0;
+        }
+        self.grades.iter().map(|g| g.score as f32).sum::<f32>() / self.grades.len() as f32
+    }
+
+    /// Mean score rescaled to [0, 1], for comparing runs with different scales.
+    pub fn normalized_mean(&self) -> f32 {
+        let range = self.max_score.saturating_sub(self.min_score).max(1) as f32;
+        ((self.mean() - self.min_score as f32) / range).clamp(0.0, 1.0)
+    }
+}
+
+/// Grade one candidate answer per labeled question, in order.
+///
+/// `candidates[i]` answers `test_set[i]`; extra entries on either side are ignored.
+pub async fn grade_test_set(
+    test_set: &[LabeledQuestion],
+    candidates: &[String],
+    llm: &LlmClient,
+    judge: &JudgeConfig,
+) -> Result<GradeReport, Box<dyn std::error::Error>> {
+    let mut grades = Vec::with_capacity(test_set.len());
+    for (labeled, candidate) in test_set.iter().zip(candidates) {
+        grades.push(
+            grade_answer(&labeled.question, &labeled.reference, candidate, llm, judge).await?,
+        );
+    }
+    Ok(GradeReport {
+        grades,
+        min_score: judge.min_score,
+        max_score: judge.max_score,
+    })
+}
+
+/// Find the score in the judge's reply; see `grade_answer` for the order tried.
+fn parse_score(reply: &str, min: u32, max: u32) -> Option<u32> {
+    let on_scale = |n: u32| (min..=max).contains(&n).then_some(n);
+    let lower = reply.to_ascii_lowercase();
+
+    // A "Score: 4" or "**Score**: 4/5" line; the last one wins, since a judge
+    // that changes its mind restates the score further down
+    let labeled = lower.lines().rev().find_map(|line| {
+        let rest = line
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '#' | '-' | '>'))
+            .strip_prefix("score")?
+            .trim_start_matches(['*', ' '])
+            .strip_prefix(':')?;
+        leading_number(rest.trim_start_matches(|c: char| c.is_whitespace() || c == '*'))
+            .and_then(on_scale)
+    });
+    if labeled.is_some() {
+        return labeled;
+    }
+
+    // "4/5", "4 / 5", "4 out of 5"
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '/')
+        .filter(|w| !w.is_empty())
+        .collect();
+    let max_text = max.to_string();
+    for (i, word) in words.iter().enumerate() {
+        if let Some((n, of)) = word.split_once('/')
+            && of == max_text
+            && let Some(n) = n.parse().ok().and_then(on_scale)
+        {
+            return Some(n);
+        }
+        if words.get(i + 1..i + 4) == Some(&["out", "of", max_text.as_str()][..])
+            && let Some(n) = word.parse().ok().and_then(on_scale)
+        {
+            return Some(n);
+        }
+    }
+
+    // Any number on the scale
+    words
+        .iter()
+        .find_map(|word| word.parse().ok().and_then(on_scale))
+}
+
+/// The digits at the start of `text`, as a number.
+fn leading_number(text: &str) -> Option<u32> {
+    let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// The text after "Justification:", or the whole reply without it.
+fn parse_justification(reply: &str) -> String {
+    let lower = reply.to_ascii_lowercase();
+    match lower.find("justification") {
+        Some(pos) => reply[pos + "justification".len()..]
+            .trim_start_matches(|c: char| c == ':' || c == '*' || c.is_whitespace())
+            .trim()
+            .to_string(),
+        None => reply.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_label_at_line_start() {
+        assert_eq!(parse_score("Score: 4\nJustification: close", 0, 5), Some(4));
+        assert_eq!(
+            parse_score("**Score**: 3/5\nJustification: partial", 0, 5),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn score_mentioned_in_commentary_is_not_the_label() {
+        let reply =
+            "The reference has a score of 2 points to cover.\nScore: 5\nJustification: all covered";
+        assert_eq!(parse_score(reply, 0, 5), Some(5));
+    }
+
+    #[test]
+    fn last_score_label_wins() {
+        let reply = "Score: 2\nOn reflection the candidate covers the second fact too.\nScore: 4";
+        assert_eq!(parse_score(reply, 0, 5), Some(4));
+    }
+
+    #[test]
+    fn falls_back_to_fractions_and_bare_numbers() {
+        assert_eq!(parse_score("I would give it 3 out of 5.", 0, 5), Some(3));
+        assert_eq!(parse_score("Verdict: 2/5, missing details", 0, 5), Some(2));
+        assert_eq!(parse_score("Mostly right, 4", 0, 5), Some(4));
+        assert_eq!(parse_score("Score: 9", 0, 5), None);
+        assert_eq!(parse_score("No grade given", 0, 5), None);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::data::Chunk;
+use crate::vector_db::{DistanceMetric, RetrievedChunk, chunk_ids, distance_to_similarity};
+use bm25::{Embedder, EmbedderBuilder, Embedding, Language, TokenEmbedding};
+use std::collections::HashMap;
+
+/// BM25 weight used when none is given: an even mix of both retrievers.
+pub const DEFAULT_HYBRID_ALPHA: f32 = 0.5;
+
+/// BM25 scores over the chunks a collection was built from, for fusing with
+/// dense results.
+pub struct Bm25Index {
+    embedder: Embedder,
+    doc_embeddings: Vec<Embedding>,
+    /// The indexed chunks as retrieval results, under their Chroma ids and
+    /// without a distance.
+    entries: Vec<RetrievedChunk>,
+}
+
+impl Bm25Index {
+    /// Fit BM25 to the texts of `chunks`, given in the order they were passed
+    /// to `build_chroma_collection` so the ids match the collection's.
+    pub fn new(chunks: &[Chunk]) -> Self {
+        let corpus: Vec<String> = chunks.iter().map(|c| c.text.to_lowercase()).collect();
+        let corpus_refs: Vec<&str> = corpus.iter().map(String::as_str).collect();
+        let embedder = EmbedderBuilder::with_fit_to_corpus(Language::English, &corpus_refs).build();
+        let doc_embeddings = corpus_refs
+            .iter()
+            .map(|&text| embedder.embed(text))
+            .collect();
+        let entries = chunks
+            .iter()
+            .zip(chunk_ids(chunks))
+            .map(|(chunk, id)| RetrievedChunk {
+                id,
+                chunk: chunk.text.clone(),
+                doc_id: chunk.doc_id,
+                chunk_id: chunk.chunk_id,
+                distance: None,
+                pinned: false,
+            })
+            .collect();
+        Self {
+            embedder,
+            doc_embeddings,
+            entries,
+        }
+    }
+
+    /// BM25 score of every indexed chunk for `query`, in index order.
+    pub fn score(&self, query: &str) -> Vec<f32> {
+        let query_embedding = self.embedder.embed(&query.to_lowercase());
+        self.doc_embeddings
+            .iter()
+            .map(|doc_embedding| dot(&query_embedding, doc_embedding))
+            .collect()
+    }
+}
+
+fn dot(a: &Embedding, b: &Embedding) -> f32 {
+    let mut sum = 0.0;
+    for TokenEmbedding {
+        index: qi,
+        value: qv,
+    } in &a.0
+    {
+        for TokenEmbedding {
+            index: di,
+            value: dv,
+        } in &b.0
+        {
+            if qi == di {
+                sum += qv * dv;
+            }
+        }
+    }
+    sum
+}
+
+/// Merge `dense` results with the best BM25 matches and keep the `top_k`
+/// highest fused scores.
+///
+/// Each chunk scores `alpha * bm25 + (1 - alpha) * similarity`, with BM25
+/// scores divided by the best one so both terms lie in [0, 1] and distances
+/// turned into similarities for `metric`. Chunks BM25 finds that the dense
+/// results lack are added with an unknown distance, and a dense result BM25
+/// does not know scores on similarity alone.
+///
+/// With `require_distance` (when the dense results were filtered by a
+/// distance threshold), BM25-only chunks are not added, since they could not
+/// be checked against it; the `top_k` are then chosen from the dense results.
+pub fn fuse_with_bm25(
+    query: &str,
+    dense: Vec<RetrievedChunk>,
+    bm25: &Bm25Index,
+    alpha: f32,
+    metric: DistanceMetric,
+    top_k: usize,
+    require_distance: bool,
+) -> Vec<RetrievedChunk> {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let scores = bm25.score(query);
+    let max_score = scores.iter().copied().fold(0.0, f32::max).max(f32::EPSILON);
+    let bm25_by_id: HashMap<&str, f32> = bm25
+        .entries
+        .iter()
+        .zip(&scores)
+        .map(|(entry, &score)| (entry.id.as_str(), score / max_score))
+        .collect();
+
+    let mut candidates = dense;
+    if !require_distance {
+        let mut lexical: Vec<(usize, f32)> = scores
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, score)| score > 0.0)
+            .collect();
+        lexical.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (idx, _) in lexical.into_iter().take(top_k) {
+            let entry = &bm25.entries[idx];
+            if !candidates.iter().any(|chunk| chunk.id == entry.id) {
+                candidates.push(entry.clone());
+            }
+        }
+    }
+
+    let mut fused: Vec<(f32, RetrievedChunk)> = candidates
+        .into_iter()
+        .map(|chunk| {
+            let lexical = bm25_by_id.get(chunk.id.as_str()).copied().unwrap_or(0.0);
+            let similarity = chunk
+                .distance
+                .map_or(0.0, |d| distance_to_similarity(d, metric));
+            (alpha * lexical + (1.0 - alpha) * similarity, chunk)
+        })
+        .collect();
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0));
+    fused
+        .into_iter()
+        .take(top_k)
+        .map(|(_, chunk)| chunk)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(doc_id: usize, text: &str) -> Chunk {
+        Chunk {
+            doc_id,
+            chunk_id: 0,
+            category: "test".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn index() -> Bm25Index {
+        Bm25Index::new(&[
+            chunk(0, "the quarterly revenue grew strongly"),
+            chunk(1, "revenue revenue revenue forecast"),
+            chunk(2, "an unrelated note about weather"),
+        ])
+    }
+
+    fn dense(id: &str, distance: f32) -> RetrievedChunk {
+        RetrievedChunk {
+            id: id.to_string(),
+            chunk: String::new(),
+            doc_id: 0,
+            chunk_id: 0,
+            distance: Some(distance),
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn bm25_only_chunks_are_added_without_a_distance() {
+        let fused = fuse_with_bm25(
+            "revenue",
+            vec![dense("doc_2_chunk_0", 0.1)],
+            &index(),
+            0.5,
+            DistanceMetric::Cosine,
+            3,
+            false,
+        );
+        assert!(
+            fused
+                .iter()
+                .any(|c| c.id == "doc_1_chunk_0" && c.distance.is_none())
+        );
+    }
+
+    #[test]
+    fn required_distance_keeps_top_k_dense_results() {
+        let fused = fuse_with_bm25(
+            "revenue",
+            vec![dense("doc_2_chunk_0", 0.1), dense("doc_0_chunk_0", 0.4)],
+            &index(),
+            1.0,
+            DistanceMetric::Cosine,
+            2,
+            true,
+        );
+        // BM25-only doc 1 would outrank both, but cannot be checked against a threshold
+        let ids: Vec<&str> = fused.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["doc_0_chunk_0", "doc_2_chunk_0"]);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::data::{Chunk, Document};
+use crate::embeddings::Embedder;
+use crate::vector_db::upsert_chunks;
+use chromadb::collection::ChromaCollection;
+use serde::Deserialize;
+use std::error::Error;
+use std::io::{self, BufRead};
+use tracing::info;
+
+/// Chunks embedded and upserted together; bounds the memory held at once.
+const INGEST_BATCH_SIZE: usize = 64;
+
+/// Running totals reported by `ingest_stream`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IngestProgress {
+    pub documents: usize,
+    pub chunks: usize,
+}
+
+/// Load a corpus from `reader` into `collection` without reading it all into memory.
+///
+/// The input is either a JSON array of documents, as in `corpus.json`, or
+/// JSON Lines with one document per line; the first non-blank byte decides.
+/// Documents are parsed one at a time and split into windows of `chunk_size`
+/// words, each starting `chunk_size - overlap` words after the previous one.
+/// Chunks are embedded and upserted in batches of `INGEST_BATCH_SIZE`, and
+/// progress is logged after every batch. Returns the final totals.
+///
+/// Documents are numbered from `first_doc_id` in stream order; their own `id`
+/// fields are ignored, since the stream cannot tell which ids the collection
+/// already holds. Pass one more than the largest `doc_id` in the collection,
+/// so the ingested chunks get Chroma ids and `doc_id`s of their own instead of
+/// overwriting (or being coalesced with) the chunks already there.
+pub async fn ingest_stream<R: BufRead>(
+    mut reader: R,
+    chunk_size: usize,
+    overlap: usize,
+    first_doc_id: usize,
+    embedder: &dyn Embedder,
+    collection: &ChromaCollection,
+) -> Result<IngestProgress, Box<dyn Error>> {
+    let is_array = skip_whitespace(&mut reader)? == Some(b'[');
+    if is_array {
+        reader.consume(1);
+    }
+
+    let mut progress = IngestProgress::default();
+    let mut batch: Vec<Chunk> = Vec::with_capacity(INGEST_BATCH_SIZE);
+
+    while let Some(doc) = next_document(&mut reader, is_array, progress.documents)? {
+        let doc_id = first_doc_id + progress.documents;
+        batch.extend(document_chunks(doc, doc_id, chunk_size, overlap));
+        progress.documents += 1;
+
+        if batch.len() >= INGEST_BATCH_SIZE {
+            progress.chunks += flush(&mut batch, embedder, collection).await?;
+            info!(
+                documents = progress.documents,
+                chunks = progress.chunks,
+                "ingesting"
+            );
+        }
+    }
+
+    progress.chunks += flush(&mut batch, embedder, collection).await?;
+    info!(
+        documents = progress.documents,
+        chunks = progress.chunks,
+        "ingestion finished"
+    );
+    Ok(progress)
+}
+
+/// Split `doc` into chunks of document `doc_id`.
+fn document_chunks(doc: Document, doc_id: usize, chunk_size: usize, overlap: usize) -> Vec<Chunk> {
+    let category = doc.category.unwrap_or_else(|| "general".to_string());
+    chunk_words(&doc.content, chunk_size, overlap)
+        .into_iter()
+        .enumerate()
+        .map(|(chunk_id, text)| Chunk {
+            doc_id,
+            chunk_id,
+            category: category.clone(),
+            text,
+        })
+        .collect()
+}
+
+/// Embed and upsert the batched chunks, leaving the batch empty.
+async fn flush(
+    batch: &mut Vec<Chunk>,
+    embedder: &dyn Embedder,
+    collection: &ChromaCollection,
+) -> Result<usize, Box<dyn Error>> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    upsert_chunks(collection, batch, embedder).await?;
+    let count = batch.len();
+    batch.clear();
+    Ok(count)
+}
+
+/// Parse the next document, or `None` at the end of the input.
+///
+/// Inside an array, the separating comma and the closing bracket are consumed
+/// here; each element is deserialized on its own, so only one document is in
+/// memory at a time.
+fn next_document<R: BufRead>(
+    reader: &mut R,
+    is_array: bool,
+    position: usize,
+) -> Result<Option<Document>, Box<dyn Error>> {
+    let mut next = skip_whitespace(reader)?;
+    if is_array {
+        if position > 0 && next == Some(b',') {
+            reader.consume(1);
+            next = skip_whitespace(reader)?;
+        }
+        if next == Some(b']') {
+            reader.consume(1);
+            return Ok(None);
+        }
+    }
+    match next {
+        None if is_array => Err(invalid_data("unterminated JSON array").into()),
+        None => Ok(None),
+        Some(_) => {
+            // An object ends at its closing brace, so the parser reads no further
+            let mut de = serde_json::Deserializer::from_reader(&mut *reader);
+            let doc = Document::deserialize(&mut de)
+                .map_err(|e| invalid_data(&format!("document {}: {}", position, e)))?;
+            Ok(Some(doc))
+        }
+    }
+}
+
+/// Skip whitespace and return the next byte without consuming it.
+fn skip_whitespace<R: BufRead>(reader: &mut R) -> io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(pos) => {
+                let byte = buf[pos];
+                reader.consume(pos);
+                return Ok(Some(byte));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Split `text` into windows of `chunk_size` words that share `overlap` words
+/// with the previous window. An overlap of `chunk_size` or more is reduced so
+/// each window still advances by one word.
+fn chunk_words(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let chunk_size = chunk_size.max(1);
+    let step = chunk_size - overlap.min(chunk_size - 1);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        let end = (start + chunk_size).min(words.len());
+        chunks.push(words[start..end].join(" "));
+        if end == words.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_db::chunk_ids;
+
+    fn document(id: Option<usize>, content: &str) -> Document {
+        Document {
+            id,
+            content: content.to_string(),
+            category: None,
+        }
+    }
+
+    #[test]
+    fn ingested_ids_do_not_collide_with_the_existing_corpus() {
+        let corpus = vec![
+            Chunk {
+                doc_id: 0,
+                chunk_id: 0,
+                category: "general".to_string(),
+                text: "existing".to_string(),
+            },
+            Chunk {
+                doc_id: 1,
+                chunk_id: 0,
+                category: "general".to_string(),
+                text: "existing".to_string(),
+            },
+        ];
+        // Stream order numbering from one past the corpus, as `ingest_stream` does;
+        // the second document's explicit id 0 would collide with the corpus
+        let first_doc_id = 2;
+        let mut ingested = document_chunks(document(None, "a b c"), first_doc_id, 2, 0);
+        ingested.extend(document_chunks(
+            document(Some(0), "d e"),
+            first_doc_id + 1,
+            2,
+            0,
+        ));
+
+        let existing = chunk_ids(&corpus);
+        let new_ids = chunk_ids(&ingested);
+        assert_eq!(new_ids, ["doc_2_chunk_0", "doc_2_chunk_1", "doc_3_chunk_0"]);
+        assert!(new_ids.iter().all(|id| !existing.contains(id)));
+    }
+}
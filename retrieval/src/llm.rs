@@ -1,5 +1,6 @@
 use async_openai::{Client};
 use async_openai::config::OpenAIConfig;
+use async_openai::error::OpenAIError;
 use async_openai::types::{
     CreateChatCompletionRequestArgs, ChatCompletionRequestMessage,
     ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
@@ -7,11 +8,26 @@ use async_openai::types::{
 };
 use dotenv::dotenv;
 use std::env;
-use crate::vector_db::RetrievedChunk;
+use tracing::warn;
+use crate::embeddings::Embedder;
+use crate::prompt::render_builtin;
+use crate::ratelimit::RateLimiter;
+use crate::vector_db::{distance_to_similarity, DistanceMetric, RetrievedChunk};
+
+/// Reply given when there is no context to answer from.
+pub const DEFAULT_NO_CONTEXT_MESSAGE: &str = "I'm sorry, but I couldn't find any relevant information.";
+
+/// Sampling temperature for `get_llm_response_n`; at 0 every choice would be the same.
+const CANDIDATE_TEMPERATURE: f32 = 0.7;
+
+/// Closing sentence of `build_prompt` (without its colon), which models sometimes echo back.
+const ANSWER_LEAD_IN: &str = "Based on the context provided above, here is my answer";
 
 pub struct LlmClient {
     client: Client<OpenAIConfig>,
     system_prompt: String,
+    rate_limiter: Option<RateLimiter>,
+    no_context_message: String,
 }
 
 impl LlmClient {
@@ -28,60 +44,80 @@ impl LlmClient {
         Self {
             client: Client::with_config(config),
             system_prompt: "You are a helpful AI assistant. You always answer to the user's queries.".to_string(),
+            rate_limiter: None,
+            no_context_message: DEFAULT_NO_CONTEXT_MESSAGE.to_string(),
         }
     }
 
-    pub fn build_prompt(&self, query: &str, retrieved_chunks: &[RetrievedChunk]) -> String {
-        // Initialize a string with the question and a directive to use the provided context
-        let mut prompt = format!(
-            "You are a helpful assistant. Answer the following question based on the provided context. \
-             If the answer cannot be found in the context, say so clearly. \
-             Use only the information from the context to formulate your response.\n\n"
-        );
+    /// A client without an API key, for dry runs that build prompts but never call the API.
+    pub fn offline() -> Self {
+        Self {
+            client: Client::with_config(OpenAIConfig::new().with_api_key("")),
+            system_prompt: "You are a helpful AI assistant. You always answer to the user's queries.".to_string(),
+            rate_limiter: None,
+            no_context_message: DEFAULT_NO_CONTEXT_MESSAGE.to_string(),
+        }
+    }
 
-        // Add context header
-        prompt.push_str("===== CONTEXT =====\n\n");
+    /// Wait for `rate_limiter` before every API call.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
 
-        // Iterate over each retrieved chunk and append it to the prompt
-        for (idx, chunk) in retrieved_chunks.iter().enumerate() {
-            prompt.push_str(&format!("--- Document {} (Relevance Score: {:.4}) ---\n", idx + 1, 1.0 - chunk.distance));
-            prompt.push_str(&chunk.chunk);
-            prompt.push_str("\n\n");
+    /// Use `message` instead of `DEFAULT_NO_CONTEXT_MESSAGE` when there is no context.
+    pub fn with_no_context_message(mut self, message: impl Into<String>) -> Self {
+        self.no_context_message = message.into();
+        self
+    }
+
+    /// Reply to give instead of calling the model when nothing relevant was
+    /// retrieved or the context cannot answer the question.
+    pub fn no_context_message(&self) -> &str {
+        &self.no_context_message
+    }
+
+    async fn throttle(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
         }
+    }
 
-        // Add the question section
-        prompt.push_str("===== QUESTION =====\n\n");
-        prompt.push_str(query);
-        prompt.push_str("\n\n");
+    /// Build the answer prompt; each chunk's relevance score is its distance
+    /// converted to a similarity under `metric`, or "unknown" without a distance.
+    pub fn build_prompt(&self, query: &str, retrieved_chunks: &[RetrievedChunk], metric: DistanceMetric) -> String {
+        // Number each retrieved chunk and give its relevance score
+        let mut context = String::new();
+        for (idx, chunk) in retrieved_chunks.iter().enumerate() {
+            let relevance = chunk.distance.map_or_else(|| "unknown".to_string(), |d| format!("{:.4}", distance_to_similarity(d, metric)));
+            context.push_str(&format!(
+                "--- Document {} (Relevance Score: {}) ---\n",
+                idx + 1,
+                relevance
+            ));
+            context.push_str(&chunk.chunk);
+            context.push_str("\n\n");
+        }
 
-        // Append a final directive to indicate where the answer should begin
-        prompt.push_str("===== ANSWER =====\n\n");
-        prompt.push_str("Based on the context provided above, here is my answer:\n\n");
-        
-        // Return the constructed prompt
-        prompt
+        render_builtin(
+            "answer",
+            &[("context", &context), ("query", query), ("lead_in", ANSWER_LEAD_IN)],
+        )
     }
 
     pub async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // Build messages using the default system prompt.
-        let system_message = ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(self.system_prompt.clone()),
-            name: None,
-        };
-
-        let user_message = ChatCompletionRequestUserMessage {
-            content: ChatCompletionRequestUserMessageContent::Text(prompt.to_string()),
-            name: None,
-        };
-
-        let messages = vec![
-            ChatCompletionRequestMessage::System(system_message),
-            ChatCompletionRequestMessage::User(user_message),
-        ];
+        self.get_llm_response_with_system(&self.system_prompt, prompt).await
+    }
 
+    /// Like `get_llm_response`, but uses `system` as the system message for this call only.
+    pub async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
         let request = CreateChatCompletionRequestArgs::default()
             .model("gpt-4o-mini")
-            .messages(messages)
+            .messages(chat_messages(system, prompt))
             .temperature(0.0)
             .max_tokens(500_u32)
             .top_p(1.0)
@@ -89,6 +125,7 @@ impl LlmClient {
             .presence_penalty(0.0)
             .build()?;
 
+        self.throttle().await;
         let response = self.client.chat().create(request).await?;
         let answer = response
             .choices
@@ -97,4 +134,264 @@ impl LlmClient {
             .unwrap_or_else(|| "No response".to_string());
         Ok(answer)
     }
+
+    /// Request `n` independent answers to `prompt` in one call and return every choice.
+    ///
+    /// Choices are sampled at a temperature of 0.7 so that they can differ;
+    /// choices without text are skipped.
+    pub async fn get_llm_response_n(&self, prompt: &str, n: u8) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let request = CreateChatCompletionRequestArgs::default()
+            .model("gpt-4o-mini")
+            .messages(chat_messages(&self.system_prompt, prompt))
+            .temperature(CANDIDATE_TEMPERATURE)
+            .max_tokens(500_u32)
+            .n(n)
+            .build()?;
+
+        self.throttle().await;
+        let response = self.client.chat().create(request).await?;
+        Ok(response
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.message.content)
+            .collect())
+    }
+}
+
+/// Whether `error`, returned by an LLM call, means the client is set up wrong:
+/// a missing or invalid API key, an unknown model, no remaining quota, or a
+/// request the client could not build. Retrying cannot fix these. Network
+/// failures, timeouts, rate limits and server errors are not configuration errors.
+pub fn is_configuration_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    match error.downcast_ref::<OpenAIError>() {
+        Some(OpenAIError::ApiError(api)) => {
+            matches!(
+                api.code.as_deref(),
+                Some("invalid_api_key" | "model_not_found" | "insufficient_quota")
+            ) || api.message.contains("API key")
+        }
+        Some(OpenAIError::InvalidArgument(_)) => true,
+        _ => false,
+    }
+}
+
+/// The system and user messages of a chat request.
+fn chat_messages(system: &str, prompt: &str) -> Vec<ChatCompletionRequestMessage> {
+    let system_message = ChatCompletionRequestSystemMessage {
+        content: ChatCompletionRequestSystemMessageContent::Text(system.to_string()),
+        name: None,
+    };
+
+    let user_message = ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(prompt.to_string()),
+        name: None,
+    };
+
+    vec![
+        ChatCompletionRequestMessage::System(system_message),
+        ChatCompletionRequestMessage::User(user_message),
+    ]
+}
+
+/// Pick the answer the candidates agree on most.
+///
+/// Each answer is embedded and scored by its mean cosine similarity to all the
+/// other answers; the highest-scoring one (the medoid) is returned, ties going
+/// to the earliest. With fewer than three answers there is no majority, so the
+/// first one is returned. Returns an empty string for no answers.
+pub fn self_consistency(answers: &[String], embedder: &dyn Embedder) -> Result<String, Box<dyn std::error::Error>> {
+    if answers.len() < 3 {
+        return Ok(answers.first().cloned().unwrap_or_default());
+    }
+
+    let texts: Vec<&str> = answers.iter().map(String::as_str).collect();
+    let embeddings = embedder.embed_texts(&texts)?;
+
+    let mut best = (0, f32::NEG_INFINITY);
+    for (i, a) in embeddings.iter().enumerate() {
+        let agreement: f32 = embeddings
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, b)| cosine_similarity(a, b))
+            .sum::<f32>()
+            / (embeddings.len() - 1) as f32;
+        if agreement > best.1 {
+            best = (i, agreement);
+        }
+    }
+
+    Ok(answers[best.0].clone())
+}
+
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Ask the model whether `query` can be answered solely from `context`.
+///
+/// This is a cheap yes/no call made before a full generation so that obviously
+/// unanswerable questions can be refused without spending tokens. Replies are
+/// parsed leniently ("Yes.", "**No**", "Answer: no, the context ..."). If the
+/// call fails or the reply contains neither word, the question is treated as
+/// answerable so that generation still runs.
+pub async fn is_answerable(query: &str, context: &str, llm: &LlmClient) -> bool {
+    if context.trim().is_empty() {
+        return false;
+    }
+
+    let prompt = render_builtin("answerable", &[("context", context), ("query", query)]);
+
+    match llm
+        .get_llm_response_with_system("You judge whether a context contains the answer to a question.", &prompt)
+        .await
+    {
+        Ok(reply) => parse_yes_no(&reply).unwrap_or(true),
+        Err(e) => {
+            warn!("Answerability check failed ({}); generating anyway", e);
+            true
+        }
+    }
+}
+
+/// Return the first standalone "yes" or "no" in `reply`, ignoring case and punctuation.
+fn parse_yes_no(reply: &str) -> Option<bool> {
+    reply
+        .split(|c: char| !c.is_alphanumeric())
+        .find_map(|word| match word.to_lowercase().as_str() {
+            "yes" => Some(true),
+            "no" => Some(false),
+            _ => None,
+        })
+}
+
+/// Strip prompt scaffolding that the model echoed into its reply.
+///
+/// Removes, as often as they repeat at the start, an "ANSWER" header line
+/// (`===== ANSWER =====`, `## Answer`, `**Answer:**`), an inline `Answer:`
+/// label and the lead-in sentence `build_prompt` ends with, then any quotes
+/// wrapping the remaining text. Returns just the answer body.
+pub fn clean_answer(raw: &str) -> String {
+    let is_decoration = |c: char| c == '=' || c == '#' || c == '*' || c == ':' || c.is_whitespace();
+    let mut rest = raw.trim();
+
+    loop {
+        // A whole line that is only a header or the echoed lead-in
+        let (first_line, remainder) = rest.split_once('\n').unwrap_or((rest, ""));
+        let bare = first_line.trim_matches(is_decoration);
+        if bare.eq_ignore_ascii_case("answer") || bare.eq_ignore_ascii_case(ANSWER_LEAD_IN) {
+            rest = remainder.trim();
+            continue;
+        }
+
+        // The same text used as an inline label in front of the answer
+        let unmarked = rest.trim_start_matches(['#', '*', ' ']);
+        let stripped = ["answer:", ANSWER_LEAD_IN]
+            .iter()
+            .find_map(|prefix| strip_prefix_ignore_case(unmarked, prefix));
+        match stripped {
+            Some(after) => rest = after.trim_start_matches([':', '*']).trim(),
+            None => break,
+        }
+    }
+
+    for (open, close) in [('"', '"'), ('\'', '\''), ('“', '”')] {
+        if let Some(inner) = rest.strip_prefix(open).and_then(|r| r.strip_suffix(close)) {
+            rest = inner.trim();
+            break;
+        }
+    }
+
+    rest.to_string()
+}
+
+/// `text.strip_prefix(prefix)`, comparing ASCII case-insensitively.
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &text[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+
+    #[test]
+    fn clean_answer_strips_echoed_headers_and_lead_in() {
+        let cases = [
+            (
+                "===== ANSWER =====\n\nBased on the context provided above, here is my answer:\n\nRevenue rose 12%.",
+                "Revenue rose 12%.",
+            ),
+            ("## Answer\nThe launch is in May.", "The launch is in May."),
+            ("**Answer:** The launch is in May.", "The launch is in May."),
+            (
+                "based on the context provided above, here is my answer: \"Margins fell.\"",
+                "Margins fell.",
+            ),
+            ("Answer: “Two products shipped.”", "Two products shipped."),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(clean_answer(raw), expected, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn clean_answer_keeps_a_plain_answer_untouched() {
+        let answer = "The answer depends on \"net\" revenue: it rose 12%.";
+        assert_eq!(clean_answer(answer), answer);
+        assert_eq!(clean_answer("  Answers vary by region.  "), "Answers vary by region.");
+    }
+
+    #[test]
+    fn self_consistency_picks_the_majority_answer() {
+        let answers = [
+            "The capital is Lyon.",
+            "Paris is the capital of France.",
+            "The capital of France is Paris.",
+            "France's capital is Paris.",
+        ]
+        .map(String::from);
+        let embedder = HashEmbedder::new(256);
+        let picked = self_consistency(&answers, &embedder).unwrap();
+        assert!(picked.contains("Paris"), "{picked}");
+        // Too few answers to vote: the first one wins
+        assert_eq!(self_consistency(&answers[..2], &embedder).unwrap(), answers[0]);
+    }
+
+    fn api_error(code: Option<&str>, message: &str) -> Box<dyn std::error::Error> {
+        Box::new(OpenAIError::ApiError(async_openai::error::ApiError {
+            message: message.to_string(),
+            r#type: None,
+            param: None,
+            code: code.map(str::to_string),
+        }))
+    }
+
+    #[test]
+    fn only_setup_problems_are_configuration_errors() {
+        for error in [
+            api_error(Some("invalid_api_key"), "Incorrect API key provided"),
+            api_error(None, "You didn't provide an API key."),
+            api_error(Some("model_not_found"), "The model does not exist"),
+            Box::new(OpenAIError::InvalidArgument("bad request".to_string())),
+        ] {
+            assert!(is_configuration_error(error.as_ref()), "{error}");
+        }
+        for error in [
+            api_error(Some("rate_limit_exceeded"), "Rate limit reached"),
+            api_error(None, "The server had an error while processing your request"),
+            Box::new(OpenAIError::StreamError("connection reset".to_string())),
+            "not an LLM error".into(),
+        ] {
+            assert!(!is_configuration_error(error.as_ref()), "{error}");
+        }
+    }
 }
@@ -1,17 +1,89 @@
+mod cache;
+mod compress;
+mod critique;
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 mod llm;
+mod feedback;
+mod format;
+mod grading;
+mod hybrid;
+mod ingest;
+mod rerank;
+mod pipeline;
+mod ratelimit;
+mod precision;
+mod prompt;
+mod sentences;
 
+use cache::Retriever;
+use critique::{generate_self_critique, DEFAULT_CRITIQUE_ROUNDS};
 use data::load_documents;
-use vector_db::{build_chroma_collection, retrieve_top_chunks};
-use embeddings::SentenceEmbedder;
-use llm::LlmClient;
+use vector_db::{build_chroma_collection, format_distance, CollectionMode, reembed_collection, DistanceMetric, retrieve_top_chunks_batch, retrieve_top_chunks_verbose, search_per_category, IndexParams, SimilarityBands};
+use embeddings::{Embedder, HashEmbedder, LongInputPolicy, SentenceEmbedder, DEFAULT_MAX_WORDS};
+use format::formatter_by_name;
+use feedback::{retrieve_with_feedback, RocchioParams};
+use grading::{grade_test_set, JudgeConfig, LabeledQuestion};
+use hybrid::{Bm25Index, DEFAULT_HYBRID_ALPHA};
+use ingest::ingest_stream;
+use llm::{clean_answer, is_configuration_error, self_consistency, LlmClient};
+use ratelimit::RateLimiter;
+use precision::Precision;
+use pipeline::{answer, answer_with_sources, warmup, AnswerOutcome, RagPipeline, RetrievalSettings};
+use rerank::{LlmReranker, NoopReranker, Reranker};
 use std::env;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Set when stdout is reserved for the JSON document; `status!` then writes to stderr.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for progress and diagnostics, sent to stderr under `--format=json`
+/// so another program reading stdout gets nothing but the JSON.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// Whether `value` switches a flag on: "1", "true", "yes" or "on", in any case.
+fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Whether the environment variable `name` is set to a true value (see
+/// `is_truthy`); unset, empty, "0" and "false" all leave the flag off.
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| is_truthy(&value))
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // RUST_LOG=debug shows retrieval internals; by default only warnings are logged
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")))
+        .with_writer(std::io::stderr)
+        .init();
+
+    // --format=markdown|json (or OUTPUT_FORMAT) renders the results as a report
+    // or for another program; with json, stdout carries only the JSON
+    let format_name = env::args()
+        .find_map(|arg| arg.strip_prefix("--format=").map(str::to_string))
+        .or_else(|| env::var("OUTPUT_FORMAT").ok())
+        .unwrap_or_else(|| "plain".to_string());
+    JSON_OUTPUT.store(format_name.eq_ignore_ascii_case("json"), Ordering::Relaxed);
+
     // Determine the path to corpus.json
     let current_dir = env::current_dir()?;
     let dataset_file = current_dir.join("data").join("corpus.json");
@@ -20,128 +92,344 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let docs = load_documents(dataset_file.to_str().unwrap())?;
 
     // Create the embedder instance.
-    let embedder = SentenceEmbedder::new().await?;
+    // EMBEDDER=hash uses an offline hashed bag-of-words embedder instead of the model.
+    // Its vectors live in their own collection so they never mix with model vectors.
+    let use_hash_embedder = env::var("EMBEDDER").as_deref() == Ok("hash");
+    // Whole documents can exceed the model's input limit: LONG_INPUT=mean averages
+    // the embeddings of every window, LONG_INPUT=error refuses such inputs
+    let long_input = match env::var("LONG_INPUT").as_deref() {
+        Ok("mean") => LongInputPolicy::MeanPool,
+        Ok("error") => LongInputPolicy::Error,
+        _ => LongInputPolicy::Truncate,
+    };
+    // REQUESTS_PER_MINUTE=<n> throttles the LLM client; the embedder runs locally
+    let rate_limiter = env::var("REQUESTS_PER_MINUTE").ok().and_then(|v| v.parse().ok()).map(RateLimiter::new);
+    let embedder: Box<dyn Embedder> = if use_hash_embedder {
+        Box::new(HashEmbedder::new(384))
+    } else {
+        Box::new(SentenceEmbedder::new().await?.with_long_input_policy(long_input, DEFAULT_MAX_WORDS))
+    };
+    let embedder = embedder.as_ref();
+    let collection_name = if use_hash_embedder { "full_document_collection_hash" } else { "full_document_collection" };
 
-    // Build (or retrieve) the ChromaDB collection using full documents.
-    let collection = build_chroma_collection(&docs, "full_document_collection", &embedder).await?;
+    // Build the ChromaDB collection from scratch, so chunks from earlier runs with other
+    // settings never mix in. COLLECTION_MODE=sync removes only the stale entries and
+    // COLLECTION_MODE=reuse upserts into whatever is there (e.g. with --reembed)
+    let collection_mode = env::var("COLLECTION_MODE").ok().and_then(|name| CollectionMode::from_name(&name)).unwrap_or_default();
+    let collection = build_chroma_collection(&docs, collection_name, embedder, &IndexParams::default(), collection_mode).await?;
+    // --warmup (or WARMUP=1) checks the embedder and Chroma before the first query
+    if env::args().any(|arg| arg == "--warmup") || env_flag("WARMUP") {
+        warmup(embedder, &collection).await?;
+    }
+    // INGEST_FILE=big_corpus.jsonl streams a further corpus (JSON array or JSON Lines)
+    // into the collection in 50-word chunks overlapping by 10, without loading it whole
+    if let Ok(path) = env::var("INGEST_FILE") {
+        let reader = std::io::BufReader::new(std::fs::File::open(&path)?);
+        // Number the new documents after the corpus so they never overwrite its chunks
+        let first_doc_id = docs.iter().map(|doc| doc.doc_id + 1).max().unwrap_or(0);
+        let progress = ingest_stream(reader, 50, 10, first_doc_id, embedder, &collection).await?;
+        status!("Ingested {} documents ({} chunks) from {}", progress.documents, progress.chunks, path);
+    }
+    // --reembed (or REEMBED=1) rewrites every stored vector with the current embedder
+    if env::args().any(|arg| arg == "--reembed") || env_flag("REEMBED") {
+        let count = reembed_collection(&collection, embedder).await?;
+        status!("Re-embedded {} entries with {}", count, embedder.model_name());
+    }
     let doc_count = collection.count().await?;
-    println!("ChromaDB collection created with {} documents.", doc_count);
+    info!("ChromaDB collection created with {} documents.", doc_count);
 
     // Define a user query and category for filtering
     let user_query = "What are the recent developments in artificial intelligence?";
     let category_filter = None;  // Options: "Technology", "Science", "Health", etc., or None
+    let exclude_categories: Option<Vec<String>> = None;  // e.g. Some(vec!["Entertainment".to_string()])
     let distance_threshold = Some(1.0);  // Only include chunks with distance <= 1.0 (good similarity)
                                          // Typical ranges: 0.0-0.5 (very similar), 0.5-1.0 (similar), 1.0-1.5 (somewhat similar), >1.5 (dissimilar)
 
+    let min_chunk_words = Some(5);  // Drop fragments too short to carry usable information
+    let min_top_distance = Some(0.8);  // Refuse unless the best chunk is at least this close
+
     // Retrieve the top documents relevant to the query with both filters
     let top_k = 3;
 
-    println!("\n{}", "=".repeat(60));
-    println!("RAG SYSTEM WITH DUAL FILTERING");
-    println!("{}", "=".repeat(60));
-    println!("Query: {}", user_query);
-    println!("Category Filter: {:?}", category_filter.unwrap_or("None"));
-    println!("Distance Threshold: {:?} (lower = more similar)", distance_threshold.unwrap_or(2.0));
-    println!("Max Results: {}", top_k);
-    println!("{}", "=".repeat(60));
-
-    let retrieved_chunks = retrieve_top_chunks(
-        &collection, 
-        user_query, 
-        top_k, 
-        &embedder, 
+    status!("\n{}", "=".repeat(60));
+    status!("RAG SYSTEM WITH DUAL FILTERING");
+    status!("{}", "=".repeat(60));
+    status!("Query: {}", user_query);
+    status!("Category Filter: {:?}", category_filter.unwrap_or("None"));
+    status!("Excluded Categories: {:?}", exclude_categories.as_deref().unwrap_or_default());
+    status!("Distance Threshold: {:?} (lower = more similar)", distance_threshold.unwrap_or(2.0));
+    if let Some(gate) = min_top_distance {
+        status!("Best-Match Gate: {} (refuse if the closest chunk is farther)", gate);
+    }
+    status!("Max Results: {}", top_k);
+    status!("{}", "=".repeat(60));
+
+    // --dry-run (or DRY_RUN=1) prints the prompt instead of calling the LLM, so no API key is needed
+    let dry_run = env::args().any(|arg| arg == "--dry-run") || env_flag("DRY_RUN");
+    let mut llm_client = if dry_run { LlmClient::offline() } else { LlmClient::new() };
+    if let Some(rate_limiter) = rate_limiter {
+        llm_client = llm_client.with_rate_limiter(rate_limiter);
+    }
+    // NO_CONTEXT_MESSAGE overrides the reply given when nothing relevant is retrieved
+    if let Ok(message) = env::var("NO_CONTEXT_MESSAGE") {
+        llm_client = llm_client.with_no_context_message(message);
+    }
+
+    // RERANKER=llm lets the LLM reorder the results; by default the retrieval order is kept.
+    // Dry runs never rerank with the LLM. RERANK_CONCURRENCY caps the scoring calls in flight.
+    let reranker: Box<dyn Reranker + '_> = if !dry_run && env::var("RERANKER").as_deref() == Ok("llm") {
+        let mut reranker = LlmReranker::new(&llm_client);
+        if let Some(concurrency) = env::var("RERANK_CONCURRENCY").ok().and_then(|v| v.parse().ok()) {
+            reranker = reranker.with_concurrency(concurrency);
+        }
+        Box::new(reranker)
+    } else {
+        Box::new(NoopReranker)
+    };
+
+    // PINNED_IDS=doc_0_chunk_0,doc_3_chunk_0 always puts those chunks in the context
+    let pinned_ids_var = env::var("PINNED_IDS").unwrap_or_default();
+    let pinned_ids: Vec<&str> = pinned_ids_var.split(',').map(str::trim).filter(|id| !id.is_empty()).collect();
+
+    // Retrieve, coalesce adjacent chunks, rerank and generate, timing each stage
+    let settings = RetrievalSettings {
+        top_k,
         category_filter,
-        distance_threshold
-    ).await?;
+        exclude_categories: exclude_categories.clone(),
+        distance_threshold,
+        min_chunk_words,
+        min_top_distance,
+        // COMPRESS_KEEP_RATIO=0.5 keeps the better half of each chunk's sentences in the prompt
+        compress_keep_ratio: env::var("COMPRESS_KEEP_RATIO").ok().and_then(|v| v.parse().ok()),
+        pinned_ids: &pinned_ids,
+    };
+    // Cache retrieval results so repeated questions skip the Chroma query;
+    // CACHE_PRECISION=f16 halves the memory of the cached query embeddings
+    let cache_precision = env::var("CACHE_PRECISION").ok().and_then(|name| Precision::from_name(&name)).unwrap_or_default();
+    let retriever = Retriever::new(&collection).with_query_cache(32).with_cache_precision(cache_precision);
+    let result = match answer(user_query, &retriever, embedder, reranker.as_ref(), &llm_client, &settings, dry_run).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Error answering query: {}", e);
+            // Other LLM failures are reported through the outcome; these need the setup fixed
+            if is_configuration_error(e.as_ref()) {
+                error!("Check OPENAI_API_KEY (and OPENAI_BASE_URL, if set) in your environment or .env file");
+            }
+            return Err(e);
+        }
+    };
+    let retrieved_chunks = result.chunks;
+
+    let bands = SimilarityBands::for_metric(DistanceMetric::of_collection(&collection));
+    let formatter = formatter_by_name(&format_name, bands).ok_or_else(|| format!("Unknown output format {:?}; use plain, markdown or json", format_name))?;
 
     // Check if we found any results
     if retrieved_chunks.is_empty() {
-        println!("\n⚠️  No relevant documents found!");
-        println!("\nThe search returned no results that meet your criteria:");
+        // A program reading the JSON still gets a document, with no sources
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            println!("{}", formatter.format(&retrieved_chunks, result.text.as_deref()));
+        }
+        status!("\n⚠️  No relevant documents found!");
+        status!("\nThe search returned no results that meet your criteria:");
         if let Some(category) = category_filter {
-            println!("  • Category: {}", category);
+            status!("  • Category: {}", category);
+        }
+        if let Some(excluded) = &exclude_categories {
+            status!("  • Excluding categories: {}", excluded.join(", "));
         }
         if let Some(threshold) = distance_threshold {
-            println!("  • Similarity threshold: distance ≤ {:.2}", threshold);
+            status!("  • Similarity threshold: distance ≤ {:.2}", threshold);
+        }
+        if let Some(min_words) = min_chunk_words {
+            status!("  • Minimum length: {} words", min_words);
         }
         
-        println!("\nSuggestions:");
-        println!("  1. Try relaxing the distance threshold (increase the value)");
-        println!("  2. Remove or change the category filter");
-        println!("  3. Rephrase your query");
-        
-        // Try without filters to show what's available
-        println!("\n{}", "-".repeat(60));
-        println!("Attempting search without filters for comparison...");
-        let unfiltered_chunks = retrieve_top_chunks(
+        // Repeat the search, reporting what each filter removed
+        warn!("No results with filters; explaining what the filters removed");
+        let (_, filtered_out) = retrieve_top_chunks_verbose(
             &collection,
             user_query,
             top_k,
-            &embedder,
-            None,  // No category filter
-            None   // No distance threshold
+            embedder,
+            category_filter,
+            exclude_categories.as_deref().unwrap_or_default(),
+            distance_threshold,
+            min_chunk_words,
         ).await?;
-        
-        if !unfiltered_chunks.is_empty() {
-            println!("\nFound {} documents without filters:", unfiltered_chunks.len());
-            for (i, chunk) in unfiltered_chunks.iter().enumerate() {
-                println!("\n  {}. Distance: {:.4}, Doc ID: {}", i + 1, chunk.distance, chunk.doc_id);
-                println!("     Preview: {}...", &chunk.chunk[..chunk.chunk.len().min(150)]);
-            }
-            println!("\nThese results show what's available without filtering constraints.");
+
+        if filtered_out.is_empty() {
+            status!("\nNo documents found even without filters. The query might be too specific.");
         } else {
-            println!("\nNo documents found even without filters. The query might be too specific.");
+            status!("\nNearest chunks removed by the filters:");
+            for (i, filtered) in filtered_out.iter().enumerate() {
+                status!("  {}. {} (distance {}): {}", i + 1, filtered.id, format_distance(filtered.distance), filtered.reason);
+            }
         }
     } else {
-        println!("\n✓ Retrieved {} documents meeting all criteria:", retrieved_chunks.len());
-        
-        // Display retrieved chunks with details
-        for (i, chunk) in retrieved_chunks.iter().enumerate() {
-            println!("\n{}", "-".repeat(40));
-            println!("Document {} | ID: {} | Distance: {:.4}", i + 1, chunk.doc_id, chunk.distance);
-            println!("Similarity: {}", match chunk.distance {
-                d if d <= 0.5 => "Very High ★★★★★",
-                d if d <= 0.8 => "High ★★★★",
-                d if d <= 1.0 => "Good ★★★",
-                d if d <= 1.2 => "Moderate ★★",
-                _ => "Low ★"
-            });
-            println!("{}", "-".repeat(40));
-            println!("{}", chunk.chunk);
-        }
-
-        // Build the LLM prompt using the retrieved contexts
-        println!("\n{}", "=".repeat(60));
-        println!("GENERATING LLM RESPONSE");
-        println!("{}", "=".repeat(60));
-        
-        let llm_client = LlmClient::new();
-        let final_prompt = llm_client.build_prompt(user_query, &retrieved_chunks);
-        
-        // Show prompt preview (first part)
-        println!("\nPrompt Preview:");
-        let prompt_preview = if final_prompt.len() > 500 {
-            format!("{}...", &final_prompt[..500])
-        } else {
-            final_prompt.clone()
-        };
-        println!("{}", prompt_preview);
-
-        // Query the LLM
-        match llm_client.get_llm_response(&final_prompt).await {
-            Ok(answer) => {
-                println!("\n{}", "=".repeat(60));
-                println!("LLM RESPONSE");
-                println!("{}", "=".repeat(60));
-                println!("{}", answer);
-                println!("{}", "=".repeat(60));
+        status!("\n✓ Retrieved {} documents meeting all criteria:", retrieved_chunks.len());
+
+        // Display the retrieved chunks and the answer in the chosen format
+        println!("{}", formatter.format(&retrieved_chunks, result.text.as_deref()));
+        match result.outcome {
+            AnswerOutcome::WeakMatch => status!("(refused: best match too weak, LLM not called)"),
+            AnswerOutcome::Unanswerable => status!("(refused: context does not answer the question)"),
+            AnswerOutcome::GenerationFailed => status!("(no answer: the LLM call failed, see the warning above)"),
+            _ if dry_run => status!("(dry run: the answer is the prompt, LLM not called)"),
+            _ => {}
+        }
+
+        // SELF_CONSISTENCY=<n> samples n answers and keeps the one they agree on most
+        if let Some(n) = env::var("SELF_CONSISTENCY").ok().and_then(|v| v.parse::<u8>().ok()).filter(|_| !dry_run && result.outcome == AnswerOutcome::Answered) {
+            let prompt = llm_client.build_prompt(user_query, &retrieved_chunks, DistanceMetric::of_collection(&collection));
+            let candidates: Vec<String> = llm_client
+                .get_llm_response_n(&prompt, n)
+                .await?
+                .iter()
+                .map(|answer| clean_answer(answer))
+                .collect();
+            status!("\nSelf-consistency over {} answers:\n{}", candidates.len(), self_consistency(&candidates, embedder)?);
+        }
+
+        // SELF_CRITIQUE=1 (or a number of rounds) has the LLM critique and revise its answer
+        if let Some(rounds) = env::var("SELF_CRITIQUE").ok().map(|v| v.parse().unwrap_or(DEFAULT_CRITIQUE_ROUNDS)).filter(|_| !dry_run && result.outcome == AnswerOutcome::Answered) {
+            let context = retrieved_chunks.iter().map(|chunk| chunk.chunk.as_str()).collect::<Vec<_>>().join("\n\n");
+            let (revised, critique) = generate_self_critique(user_query, &context, &llm_client, rounds).await?;
+            status!("\nSelf-critique ({} revisions):", critique.revisions);
+            for (i, notes) in critique.notes.iter().enumerate() {
+                status!("  Round {}: {}", i + 1, notes);
+            }
+            status!("Revised answer:\n{}", revised);
+        }
+
+        // ANSWER_WITH_SOURCES=1 answers again with numbered citations to the chunks used,
+        // through the same reranker; HYBRID=1 (or a BM25 weight) mixes in BM25 scores
+        if !dry_run && env_flag("ANSWER_WITH_SOURCES") {
+            let bm25 = Bm25Index::new(&docs);
+            let mut pipeline = RagPipeline::new(embedder, &collection, &llm_client).with_top_k(top_k).with_rerank(reranker.as_ref());
+            if let Ok(alpha) = env::var("HYBRID") {
+                pipeline = pipeline.with_hybrid(&bm25, alpha.parse().unwrap_or(DEFAULT_HYBRID_ALPHA));
+            }
+            if let Some(threshold) = distance_threshold {
+                pipeline = pipeline.with_distance_threshold(threshold);
             }
-            Err(e) => {
-                eprintln!("\n❌ Error getting LLM response: {}", e);
-                eprintln!("   Make sure OPENAI_API_KEY is set in your environment or .env file");
+            if let Some(gate) = min_top_distance {
+                pipeline = pipeline.with_min_top_distance(gate);
             }
+            let cited = pipeline.answer(user_query).await?;
+            status!("\nAnswer with sources:\n{}", cited.answer);
+            // A refusal cites nothing, so the sources are only listed for a real answer
+            if cited.outcome == AnswerOutcome::Answered {
+                for (i, source) in cited.sources.iter().enumerate() {
+                    status!("  [{}] Doc ID: {}, chunk {} ({}), Distance: {}", i + 1, source.doc_id, source.chunk_id, source.id, format_distance(source.distance));
+                    status!("      {}...", source.text.chars().take(80).collect::<String>());
+                }
+            }
+        }
+
+        // EVAL_FILE=labeled.json ([{"question": ..., "reference": ...}]) answers each
+        // question and has the LLM grade the answers against the references
+        if !dry_run && let Ok(path) = env::var("EVAL_FILE") {
+            let test_set: Vec<LabeledQuestion> = serde_json::from_reader(std::fs::File::open(&path)?)?;
+            let mut candidates = Vec::with_capacity(test_set.len());
+            for labeled in &test_set {
+                candidates.push(answer_with_sources(&labeled.question, &collection, embedder, &llm_client, top_k).await?.answer);
+            }
+            let judge = JudgeConfig::default();
+            let report = grade_test_set(&test_set, &candidates, &llm_client, &judge).await?;
+            status!("\nAnswer grades ({}):", path);
+            for (labeled, grade) in test_set.iter().zip(&report.grades) {
+                status!("  {}/{} {} - {}", grade.score, judge.max_score, labeled.question, grade.justification);
+            }
+            status!("  mean {:.2} ({:.0}%)", report.mean(), report.normalized_mean() * 100.0);
+        }
+
+        // Second pass: treat the top result as relevant and the rest as non-relevant
+        status!("\n{}", "=".repeat(60));
+        status!("RELEVANCE FEEDBACK (second pass)");
+        status!("{}", "=".repeat(60));
+        let refined_chunks = retrieve_with_feedback(
+            &collection,
+            user_query,
+            &retrieved_chunks,
+            &[0],
+            embedder,
+            &RocchioParams::default(),
+            top_k,
+        ).await?;
+        for (i, chunk) in refined_chunks.iter().enumerate() {
+            status!("  {}. Distance: {}, Doc ID: {}", i + 1, format_distance(chunk.distance), chunk.doc_id);
+        }
+    }
+
+    // Batch retrieval: several queries answered with one embedding call and one Chroma query
+    status!("\n{}", "=".repeat(60));
+    status!("BATCH RETRIEVAL");
+    status!("{}", "=".repeat(60));
+    let batch_queries = [
+        user_query,
+        "How do vaccines train the immune system?",
+        "What causes climate change?",
+    ];
+    let batch_results = retrieve_top_chunks_batch(
+        &collection,
+        &batch_queries,
+        top_k,
+        embedder,
+        category_filter,
+        exclude_categories,
+        distance_threshold,
+        min_chunk_words,
+    ).await?;
+    for (query, chunks) in batch_queries.iter().zip(&batch_results) {
+        status!("\nQuery: {}", query);
+        if chunks.is_empty() {
+            status!("  (no results)");
+        }
+        for (i, chunk) in chunks.iter().enumerate() {
+            status!("  {}. Distance: {}, Doc ID: {}", i + 1, format_distance(chunk.distance), chunk.doc_id);
+        }
+    }
+
+    // Best match in each category, searched concurrently
+    status!("\n{}", "=".repeat(60));
+    status!("TOP RESULT PER CATEGORY");
+    status!("{}", "=".repeat(60));
+    let categories = ["Technology", "Science", "Health", "Business"];
+    let per_category = search_per_category(&collection, user_query, &categories, 1, embedder).await?;
+    for category in categories {
+        match per_category.get(category).and_then(|chunks| chunks.first()) {
+            Some(chunk) => status!("  {:<12} Distance: {}, Doc ID: {}", category, format_distance(chunk.distance), chunk.doc_id),
+            None => status!("  {:<12} (no documents)", category),
         }
     }
 
+    status!("\nTimings: {}", result.timings);
+
+    // Asking the same question again is answered from the query cache
+    status!("\n{}", "=".repeat(60));
+    status!("QUERY CACHE");
+    status!("{}", "=".repeat(60));
+    for label in ["cached", "after clear_cache"] {
+        let start = std::time::Instant::now();
+        let chunks = retriever.retrieve(user_query, embedder, &settings).await?;
+        status!("  {:<18} {} chunks in {:?}", label, chunks.len(), start.elapsed());
+        retriever.clear_cache();
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_true_values_turn_a_flag_on() {
+        for value in ["1", "true", "TRUE", " yes ", "On"] {
+            assert!(is_truthy(value), "{value:?}");
+        }
+        for value in ["", "0", "false", "no", "off", "maybe"] {
+            assert!(!is_truthy(value), "{value:?}");
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use crate::cache::Retriever;
+use crate::compress::compress_chunks;
+use crate::embeddings::Embedder;
+use crate::error::{Component, RagError};
+use crate::hybrid::{Bm25Index, fuse_with_bm25};
+use crate::llm::{LlmClient, clean_answer, is_answerable, is_configuration_error};
+use crate::rerank::Reranker;
+use crate::sentences::{CJK_TERMINATORS, SentenceSplitter};
+use crate::vector_db::{
+    DistanceMetric, RetrievedChunk, coalesce_adjacent, is_blank_query, retrieve_top_chunks,
+};
+use chromadb::collection::ChromaCollection;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Filters and limits applied when retrieving context in `answer`.
+pub struct RetrievalSettings<'a> {
+    pub top_k: usize,
+    pub category_filter: Option<&'a str>,
+    pub exclude_categories: Option<Vec<String>>,
+    pub distance_threshold: Option<f32>,
+    pub min_chunk_words: Option<usize>,
+    /// Stricter gate on the best chunk alone: if even the closest retrieved
+    /// chunk is farther than this, `answer` refuses without calling the LLM.
+    /// Chunks without a known distance cannot satisfy it.
+    pub min_top_distance: Option<f32>,
+    /// With a ratio, `answer` shrinks each chunk to that fraction of its
+    /// sentences, the ones closest to the query, before building the prompt.
+    pub compress_keep_ratio: Option<f32>,
+    /// Chroma ids always placed first in the context, whatever their distance.
+    pub pinned_ids: &'a [&'a str],
+}
+
+/// Wall-clock time spent in each stage of `answer`.
+///
+/// `llm` covers context compression and the answerability check as well as the
+/// generation itself.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub embed: Duration,
+    pub query: Duration,
+    pub rerank: Duration,
+    pub llm: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.embed + self.query + self.rerank + self.llm
+    }
+}
+
+/// Milliseconds below one second, seconds with one decimal above ("8ms", "2.1s").
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f32())
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "embed: {}, query: {}, rerank: {}, llm: {} (total {})",
+            format_duration(self.embed),
+            format_duration(self.query),
+            format_duration(self.rerank),
+            format_duration(self.llm),
+            format_duration(self.total())
+        )
+    }
+}
+
+/// How `answer` concluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerOutcome {
+    /// The LLM answered from the retrieved context (or, in a dry run, the prompt was built).
+    Answered,
+    /// The answerability check found no answer in the retrieved context.
+    Unanswerable,
+    /// Chunks were retrieved, but the best one is farther than `min_top_distance`.
+    WeakMatch,
+    /// No chunks passed the retrieval filters.
+    NoMatch,
+    /// The LLM call failed for a reason other than configuration (see
+    /// `is_configuration_error`), such as a timeout or a server error.
+    GenerationFailed,
+}
+
+/// Refusal returned when the best chunk is too weak a match to answer from.
+const WEAK_MATCH_REFUSAL: &str =
+    "I'm sorry, but the closest documents I found are not similar enough to this question to answer it reliably.";
+
+/// Reply when the LLM call failed for a reason other than configuration.
+const GENERATION_FAILED_MESSAGE: &str =
+    "I'm sorry, but the answer could not be generated; please try again.";
+
+/// Whether the closest searched chunk is farther than `max_distance`.
+///
+/// Reranking may reorder the chunks, so every one is considered. Pinned chunks
+/// were chosen rather than found, so they are exempt; a context of pinned
+/// chunks only is never a weak match. A searched chunk without a known
+/// distance cannot satisfy the gate.
+fn is_weak_match(chunks: &[RetrievedChunk], max_distance: f32) -> bool {
+    let mut searched = chunks.iter().filter(|chunk| !chunk.pinned).peekable();
+    if searched.peek().is_none() {
+        return false;
+    }
+    let best_distance = searched
+        .filter_map(|chunk| chunk.distance)
+        .fold(f32::INFINITY, f32::min);
+    best_distance > max_distance
+}
+
+/// The outcome of `answer`: the generated answer, the context it was based on
+/// and how long each stage took.
+pub struct Answer {
+    pub outcome: AnswerOutcome,
+    /// `None` when no chunks passed the retrieval filters, so no LLM call was
+    /// made, or when the LLM call failed.
+    pub text: Option<String>,
+    pub chunks: Vec<RetrievedChunk>,
+    pub timings: Timings,
+}
+
+/// Retrieve context for `query`, rerank it and generate an answer, timing each stage.
+///
+/// Chunks come from `retriever`, so a cached result is reused when it has a
+/// query cache. Adjacent chunks are coalesced before reranking. If the answerability
+/// pre-check says the context cannot answer the query, or the best chunk is
+/// farther than `settings.min_top_distance`, a refusal is returned instead of
+/// a generated answer; `Answer::outcome` tells these cases apart.
+///
+/// With `dry_run` the LLM is never called: the answerability check is skipped
+/// and the assembled prompt is returned as the answer text. A blank query fails
+/// with `RagError::EmptyQuery` before any API call.
+pub async fn answer(
+    query: &str,
+    retriever: &Retriever<'_>,
+    embedder: &dyn Embedder,
+    reranker: &dyn Reranker,
+    llm: &LlmClient,
+    settings: &RetrievalSettings<'_>,
+    dry_run: bool,
+) -> Result<Answer, Box<dyn std::error::Error>> {
+    if is_blank_query(query) {
+        return Err(RagError::EmptyQuery.into());
+    }
+
+    let mut timings = Timings::default();
+
+    let start = Instant::now();
+    let query_embedding = embedder.embed_texts(&[query])?.remove(0);
+    timings.embed = start.elapsed();
+
+    let start = Instant::now();
+    let chunks = retriever
+        .retrieve(query_embedding.as_slice(), embedder, settings)
+        .await?;
+    let chunks = coalesce_adjacent(chunks);
+    timings.query = start.elapsed();
+
+    // Pinned chunks keep their place at the front; only the searched ones are reranked
+    let start = Instant::now();
+    let (mut chunks, searched): (Vec<_>, Vec<_>) = chunks.into_iter().partition(|chunk| chunk.pinned);
+    chunks.extend(reranker.rerank(query, searched).await);
+    timings.rerank = start.elapsed();
+
+    let start = Instant::now();
+    let (outcome, text, chunks) = gated_answer(
+        query,
+        chunks,
+        embedder,
+        llm,
+        DistanceMetric::of_collection(retriever.collection()),
+        settings.min_top_distance,
+        settings.compress_keep_ratio,
+        dry_run,
+    )
+    .await?;
+    timings.llm = start.elapsed();
+
+    Ok(Answer {
+        outcome,
+        text,
+        chunks,
+        timings,
+    })
+}
+
+/// The gates between retrieval and generation, shared by `answer` and
+/// `RagPipeline::answer`.
+///
+/// Refuses on an empty context or one weaker than `min_top_distance`,
+/// compresses the chunks when `compress_keep_ratio` is set, then asks the LLM
+/// only if the answerability check passes. Returns the outcome, the answer
+/// text (`None` for `NoMatch` and `GenerationFailed`) and the chunks the
+/// answer was based on. Only configuration errors from the LLM are returned as
+/// errors; other failures are logged and reported as `GenerationFailed`.
+#[allow(clippy::too_many_arguments)]
+async fn gated_answer(
+    query: &str,
+    chunks: Vec<RetrievedChunk>,
+    embedder: &dyn Embedder,
+    llm: &LlmClient,
+    metric: DistanceMetric,
+    min_top_distance: Option<f32>,
+    compress_keep_ratio: Option<f32>,
+    dry_run: bool,
+) -> Result<(AnswerOutcome, Option<String>, Vec<RetrievedChunk>), Box<dyn std::error::Error>> {
+    if chunks.is_empty() {
+        return Ok((AnswerOutcome::NoMatch, None, chunks));
+    }
+
+    if let Some(max_distance) = min_top_distance
+        && is_weak_match(&chunks, max_distance)
+    {
+        debug!(max_distance, "best chunk too weak to answer from");
+        return Ok((
+            AnswerOutcome::WeakMatch,
+            Some(WEAK_MATCH_REFUSAL.to_string()),
+            chunks,
+        ));
+    }
+
+    let chunks = match compress_keep_ratio {
+        Some(keep_ratio) => {
+            let splitter = SentenceSplitter::default().with_terminators(CJK_TERMINATORS);
+            compress_chunks(chunks, query, embedder, keep_ratio, &splitter)?
+        }
+        None => chunks,
+    };
+    let context = chunks
+        .iter()
+        .map(|chunk| chunk.chunk.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let answerable = dry_run || is_answerable(query, &context, llm).await;
+    let text = if answerable {
+        let prompt = llm.build_prompt(query, &chunks, metric);
+        debug!(
+            "Prompt preview:\n{}",
+            prompt.chars().take(500).collect::<String>()
+        );
+        if dry_run {
+            prompt
+        } else {
+            match llm.get_llm_response(&prompt).await {
+                Ok(raw) => clean_answer(&raw),
+                Err(e) if is_configuration_error(e.as_ref()) => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "LLM call failed ({}); returning the retrieved context without an answer",
+                        e
+                    );
+                    return Ok((AnswerOutcome::GenerationFailed, None, chunks));
+                }
+            }
+        }
+    } else {
+        llm.no_context_message().to_string()
+    };
+
+    let outcome = if answerable {
+        AnswerOutcome::Answered
+    } else {
+        AnswerOutcome::Unanswerable
+    };
+    Ok((outcome, Some(text), chunks))
+}
+
+/// A retrieved chunk an answer was based on, for footnote-style citations.
+#[derive(Debug, Clone)]
+pub struct SourceRef {
+    /// Chroma id of the chunk.
+    pub id: String,
+    pub doc_id: usize,
+    pub chunk_id: usize,
+    pub distance: Option<f32>,
+    pub text: String,
+}
+
+impl From<RetrievedChunk> for SourceRef {
+    fn from(chunk: RetrievedChunk) -> Self {
+        Self {
+            id: chunk.id,
+            doc_id: chunk.doc_id,
+            chunk_id: chunk.chunk_id,
+            distance: chunk.distance,
+            text: chunk.chunk,
+        }
+    }
+}
+
+/// An answer together with the chunks it was generated from.
+#[derive(Debug, Clone)]
+pub struct AnswerWithSources {
+    pub outcome: AnswerOutcome,
+    pub answer: String,
+    /// In the order they appear in the prompt, so `sources[i]` is "Document i + 1".
+    pub sources: Vec<SourceRef>,
+}
+
+/// Results `RagPipeline` retrieves when `with_top_k` is not called.
+pub const DEFAULT_PIPELINE_TOP_K: usize = 3;
+
+/// Retrieval and generation in one object: holds the embedder, collection and
+/// LLM client, plus the optional stages set with its `with_*` methods, and
+/// answers a query with `answer`.
+///
+/// By default it retrieves the `DEFAULT_PIPELINE_TOP_K` closest chunks with
+/// `retrieve_top_chunks`, without filters, reranking or hybrid scoring.
+pub struct RagPipeline<'a> {
+    embedder: &'a dyn Embedder,
+    collection: &'a ChromaCollection,
+    llm: &'a LlmClient,
+    /// BM25 index and weight for hybrid retrieval.
+    hybrid: Option<(&'a Bm25Index, f32)>,
+    reranker: Option<&'a dyn Reranker>,
+    top_k: usize,
+    distance_threshold: Option<f32>,
+    min_top_distance: Option<f32>,
+}
+
+impl<'a> RagPipeline<'a> {
+    pub fn new(
+        embedder: &'a dyn Embedder,
+        collection: &'a ChromaCollection,
+        llm: &'a LlmClient,
+    ) -> Self {
+        Self {
+            embedder,
+            collection,
+            llm,
+            hybrid: None,
+            reranker: None,
+            top_k: DEFAULT_PIPELINE_TOP_K,
+            distance_threshold: None,
+            min_top_distance: None,
+        }
+    }
+
+    /// Fuse the dense results with BM25 scores from `bm25`, weighted by
+    /// `alpha` (see `fuse_with_bm25`). Three times `top_k` dense candidates
+    /// are fetched so the fusion has more than the final results to choose from.
+    pub fn with_hybrid(mut self, bm25: &'a Bm25Index, alpha: f32) -> Self {
+        self.hybrid = Some((bm25, alpha));
+        self
+    }
+
+    /// Reorder the retrieved chunks with `reranker` before generating.
+    pub fn with_rerank(mut self, reranker: &'a dyn Reranker) -> Self {
+        self.reranker = Some(reranker);
+        self
+    }
+
+    /// Answer from at most `top_k` chunks.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Drop chunks farther than `threshold` from the query. Chunks found only
+    /// by BM25 have no distance, so they never pass it.
+    pub fn with_distance_threshold(mut self, threshold: f32) -> Self {
+        self.distance_threshold = Some(threshold);
+        self
+    }
+
+    /// Refuse without calling the LLM unless the best searched chunk is within
+    /// `max_distance` (see `RetrievalSettings::min_top_distance`).
+    pub fn with_min_top_distance(mut self, max_distance: f32) -> Self {
+        self.min_top_distance = Some(max_distance);
+        self
+    }
+
+    /// Retrieve chunks for `query`, rerank them if a reranker is set and
+    /// answer from them with numbered sources.
+    ///
+    /// The context goes through the same gates as in `answer`: if nothing is
+    /// retrieved, the best chunk is too weak or the answerability check fails,
+    /// the answer is a refusal and `outcome` says which. A blank query fails
+    /// with `RagError::EmptyQuery`.
+    pub async fn answer(
+        &self,
+        query: &str,
+    ) -> Result<AnswerWithSources, Box<dyn std::error::Error>> {
+        let dense_k = if self.hybrid.is_some() {
+            self.top_k * 3
+        } else {
+            self.top_k
+        };
+        let mut chunks = retrieve_top_chunks(
+            self.collection,
+            query,
+            dense_k,
+            self.embedder,
+            None,
+            None,
+            self.distance_threshold,
+            None,
+            &[],
+        )
+        .await?;
+
+        let metric = DistanceMetric::of_collection(self.collection);
+        if let Some((bm25, alpha)) = self.hybrid {
+            let require_distance = self.distance_threshold.is_some();
+            chunks = fuse_with_bm25(
+                query,
+                chunks,
+                bm25,
+                alpha,
+                metric,
+                self.top_k,
+                require_distance,
+            );
+        }
+        if let Some(reranker) = self.reranker {
+            chunks = reranker.rerank(query, chunks).await;
+        }
+
+        let (outcome, text, chunks) = gated_answer(
+            query,
+            chunks,
+            self.embedder,
+            self.llm,
+            metric,
+            self.min_top_distance,
+            None,
+            false,
+        )
+        .await?;
+        let answer = text.unwrap_or_else(|| match outcome {
+            AnswerOutcome::GenerationFailed => GENERATION_FAILED_MESSAGE.to_string(),
+            _ => self.llm.no_context_message().to_string(),
+        });
+
+        Ok(AnswerWithSources {
+            outcome,
+            answer,
+            sources: chunks.into_iter().map(SourceRef::from).collect(),
+        })
+    }
+}
+
+/// Retrieve the `top_k` closest chunks and answer `query` from them.
+///
+/// This is `RagPipeline::answer` with only `top_k` set: no filters,
+/// reranking or caching (see `answer` for those).
+pub async fn answer_with_sources(
+    query: &str,
+    collection: &ChromaCollection,
+    embedder: &dyn Embedder,
+    llm: &LlmClient,
+    top_k: usize,
+) -> Result<AnswerWithSources, Box<dyn std::error::Error>> {
+    RagPipeline::new(embedder, collection, llm)
+        .with_top_k(top_k)
+        .answer(query)
+        .await
+}
+
+/// Prime the embedder and the Chroma connection before the first real query.
+///
+/// Embeds a short dummy string and counts the collection, so a missing model,
+/// bad credentials or an unreachable server are reported at startup. Fails
+/// with `RagError::WarmupFailed` naming the component that did not respond.
+pub async fn warmup(embedder: &dyn Embedder, collection: &ChromaCollection) -> Result<(), RagError> {
+    embedder
+        .embed_texts(&["warmup"])
+        .map_err(|e| RagError::WarmupFailed {
+            component: Component::Embedder,
+            reason: e.to_string(),
+        })?;
+    let count = collection
+        .count()
+        .await
+        .map_err(|e| RagError::WarmupFailed {
+            component: Component::VectorDb,
+            reason: e.to_string(),
+        })?;
+    debug!(count, "warmup complete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, distance: Option<f32>, pinned: bool) -> RetrievedChunk {
+        RetrievedChunk {
+            id: id.to_string(),
+            chunk: id.to_string(),
+            doc_id: 0,
+            chunk_id: 0,
+            distance,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn pinned_only_context_is_not_a_weak_match() {
+        let chunks = vec![chunk("a", None, true), chunk("b", None, true)];
+        assert!(!is_weak_match(&chunks, 0.5));
+    }
+
+    #[test]
+    fn pinned_chunks_do_not_count_towards_the_best_distance() {
+        let chunks = vec![chunk("pinned", None, true), chunk("far", Some(0.9), false)];
+        assert!(is_weak_match(&chunks, 0.5));
+
+        let chunks = vec![chunk("pinned", None, true), chunk("near", Some(0.2), false)];
+        assert!(!is_weak_match(&chunks, 0.5));
+    }
+
+    #[test]
+    fn searched_chunks_of_unknown_distance_are_a_weak_match() {
+        let chunks = vec![chunk("unknown", None, false)];
+        assert!(is_weak_match(&chunks, 0.5));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::error::RagError;
+use std::collections::HashMap;
+
+/// Answer from the numbered context documents; the reply is expected to
+/// start after `{lead_in}`, which `clean_answer` strips if the model echoes it.
+const ANSWER: &str = "You are a helpful assistant. Answer the following question based on the provided context. \
+    If the answer cannot be found in the context, say so clearly. \
+    Use only the information from the context to formulate your response.\n\n\
+    ===== CONTEXT =====\n\n\
+    {context}\
+    ===== QUESTION =====\n\n\
+    {query}\n\n\
+    ===== ANSWER =====\n\n\
+    {lead_in}:\n\n";
+
+/// Yes/no check of whether the context can answer the question.
+const ANSWERABLE: &str = "Can this question be answered solely from this context? \
+    Reply with a single word: Yes or No.\n\n\
+    Context:\n{context}\n\n\
+    Question: {query}";
+
+/// Relevance rating of one passage, for the LLM reranker.
+const RATE: &str = "Rate how relevant the passage is to the query on a scale from 0 (irrelevant) \
+    to 10 (fully answers it). Reply with the number only.\n\n\
+    Query: {query}\n\nPassage: {passage}\n\nRating:";
+
+/// First answer of the self-critique loop.
+const DRAFT: &str = "Answer the question using only the context below. If the context does not \
+    contain the answer, say so.\n\n\
+    Context:\n{context}\n\n\
+    Question: {query}";
+
+/// Review of an answer against the context; `{no_issues}` is the reply that
+/// ends the loop.
+const CRITIQUE: &str = "Review the answer to the question against the context.\n\
+    1. List every claim in the answer that the context does not support.\n\
+    2. List information in the context needed to answer the question that the answer leaves out.\n\
+    If there is nothing in either list, reply with exactly: {no_issues}\n\n\
+    Context:\n{context}\n\n\
+    Question: {query}\n\n\
+    Answer:\n{answer}";
+
+/// Rewrite of an answer to address a review.
+const REVISE: &str = "Rewrite the answer to fix the problems the review found: drop or correct the \
+    unsupported claims and add the missing information, using only the context. \
+    Reply with the revised answer only.\n\n\
+    Context:\n{context}\n\n\
+    Question: {query}\n\n\
+    Answer:\n{answer}\n\n\
+    Review:\n{review}";
+
+/// Grade of a candidate answer against a reference; `{min}` and `{max}` are
+/// the ends of the scale.
+const JUDGE: &str = "Grade the candidate answer against the reference answer for correctness \
+    and completeness, on a scale from {min} (wrong or missing) to {max} (correct and complete). \
+    Judge only the facts, not the wording.\n\n\
+    Question: {question}\n\
+    Reference answer: {reference}\n\
+    Candidate answer: {candidate}\n\n\
+    Reply in exactly this format:\n\
+    Score: <number>\n\
+    Justification: <one or two sentences>";
+
+/// A prompt with named `{placeholder}`s, filled in by `render`.
+///
+/// A placeholder is a brace-enclosed name made of letters, digits and
+/// underscores; any other brace is kept as is.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    template: String,
+}
+
+impl PromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// Look up a built-in template: "answer", "answerable", "rate", "draft",
+    /// "critique", "revise" or "judge".
+    pub fn builtin(name: &str) -> Option<Self> {
+        let template = match name {
+            "answer" => ANSWER,
+            "answerable" => ANSWERABLE,
+            "rate" => RATE,
+            "draft" => DRAFT,
+            "critique" => CRITIQUE,
+            "revise" => REVISE,
+            "judge" => JUDGE,
+            _ => return None,
+        };
+        Some(Self::new(template))
+    }
+
+    /// Replace every placeholder with its value from `vars`.
+    ///
+    /// Fails with `RagError::MissingPromptValue` if a placeholder has no value,
+    /// rather than sending the literal `{name}` to the model. Extra entries in
+    /// `vars` are ignored.
+    pub fn render(&self, vars: &HashMap<&str, &str>) -> Result<String, RagError> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(open) = rest.find('{') {
+            rendered.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+
+            let name_len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            if name_len > 0 && after[name_len..].starts_with('}') {
+                let name = &after[..name_len];
+                let value = vars.get(name).ok_or_else(|| RagError::MissingPromptValue {
+                    placeholder: name.to_string(),
+                })?;
+                rendered.push_str(value);
+                rest = &after[name_len + 1..];
+            } else {
+                // Not a placeholder: keep the brace literally
+                rendered.push('{');
+                rest = after;
+            }
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
+
+/// Render the built-in template `name` with `vars`.
+///
+/// Panics if there is no such template or `vars` lacks one of its
+/// placeholders; either is a mistake in this crate, not in its input.
+pub fn render_builtin(name: &str, vars: &[(&str, &str)]) -> String {
+    PromptTemplate::builtin(name)
+        .unwrap_or_else(|| panic!("no built-in prompt template {:?}", name))
+        .render(&vars.iter().copied().collect())
+        .unwrap_or_else(|e| panic!("built-in prompt template {:?}: {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled_and_other_braces_kept() {
+        let template = PromptTemplate::new("{query} in {context} {not a placeholder} {}");
+        let vars = HashMap::from([("query", "q"), ("context", "c {query}")]);
+        assert_eq!(
+            template.render(&vars).unwrap(),
+            "q in c {query} {not a placeholder} {}"
+        );
+    }
+
+    #[test]
+    fn a_missing_value_is_an_error() {
+        let template = PromptTemplate::new("Answer: {query}");
+        assert!(matches!(
+            template.render(&HashMap::new()),
+            Err(RagError::MissingPromptValue { placeholder }) if placeholder == "query"
+        ));
+    }
+
+    #[test]
+    fn answer_template_keeps_the_section_layout() {
+        let prompt = render_builtin(
+            "answer",
+            &[
+                ("context", "--- Document 1 ---\nText\n\n"),
+                ("query", "Why?"),
+                ("lead_in", "Here"),
+            ],
+        );
+        assert!(prompt.ends_with(
+            "===== CONTEXT =====\n\n--- Document 1 ---\nText\n\n\
+             ===== QUESTION =====\n\nWhy?\n\n===== ANSWER =====\n\nHere:\n\n"
+        ));
+    }
+}
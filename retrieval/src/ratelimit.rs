@@ -0,0 +1,49 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Spaces out API requests so that, together, they stay under a
+/// requests-per-minute budget.
+///
+/// Clones share one schedule, so a limiter handed to several API clients
+/// throttles them together rather than each client on its own. Only remote
+/// calls need it; the local embedder is not throttled.
+/// Requests are admitted one every `60 / requests_per_minute` seconds, in the
+/// order they ask; a burst waits instead of failing.
+#[derive(Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// Earliest time the next request may start.
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    /// A limiter admitting `requests_per_minute` requests per minute (at least one).
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / requests_per_minute.max(1),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Wait, without blocking the runtime, until this request may start.
+    pub async fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Book the next free slot and return how long to wait for it.
+    fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut next_slot = self.next_slot.lock().unwrap();
+        let slot = (*next_slot).max(now);
+        *next_slot = slot + self.interval;
+        let wait = slot - now;
+        if !wait.is_zero() {
+            debug!(wait_ms = wait.as_millis() as u64, "rate limited");
+        }
+        wait
+    }
+}
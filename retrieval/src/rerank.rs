@@ -0,0 +1,127 @@
+use crate::llm::LlmClient;
+use crate::prompt::render_builtin;
+use crate::vector_db::RetrievedChunk;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use tracing::warn;
+
+/// Rating given to a chunk whose LLM score could not be obtained: the middle
+/// of the 0-10 scale, so a failed call neither promotes nor buries the chunk.
+const NEUTRAL_SCORE: f32 = 5.0;
+
+/// Scoring calls in flight at once unless `with_concurrency` says otherwise.
+const DEFAULT_RERANK_CONCURRENCY: usize = 4;
+
+/// Reorders retrieved chunks by relevance to the query.
+///
+/// Implement this to plug in a different reranking strategy (e.g. a hosted
+/// rerank API); the pipeline only ever sees a `&dyn Reranker`.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    async fn rerank(&self, query: &str, chunks: Vec<RetrievedChunk>) -> Vec<RetrievedChunk>;
+}
+
+/// Keeps the retrieval order unchanged.
+pub struct NoopReranker;
+
+#[async_trait]
+impl Reranker for NoopReranker {
+    async fn rerank(&self, _query: &str, chunks: Vec<RetrievedChunk>) -> Vec<RetrievedChunk> {
+        chunks
+    }
+}
+
+/// Asks the LLM to rate each chunk's relevance from 0 to 10 and sorts by that rating.
+///
+/// The chunks are scored concurrently. A chunk whose rating cannot be
+/// obtained gets a neutral score of 5 instead of failing the whole rerank.
+/// Ties keep their retrieval order.
+pub struct LlmReranker<'a> {
+    llm: &'a LlmClient,
+    concurrency: usize,
+}
+
+impl<'a> LlmReranker<'a> {
+    pub fn new(llm: &'a LlmClient) -> Self {
+        Self {
+            llm,
+            concurrency: DEFAULT_RERANK_CONCURRENCY,
+        }
+    }
+
+    /// Allow at most `concurrency` scoring calls in flight at once (at least 1).
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Rate one chunk, returning `None` if the call fails or the reply is not a number.
+    async fn score(&self, query: &str, chunk: &str) -> Option<f32> {
+        let prompt = render_builtin("rate", &[("query", query), ("passage", chunk)]);
+
+        match self.llm.get_llm_response(&prompt).await {
+            Ok(reply) => {
+                let rating = parse_rating(&reply);
+                if rating.is_none() {
+                    warn!("LLM rerank reply has no rating: {:?}", reply);
+                }
+                rating
+            }
+            Err(e) => {
+                warn!("LLM rerank scoring failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The first number in `reply`, ignoring punctuation around it ("7", "Rating: 8.5", "**9**").
+fn parse_rating(reply: &str) -> Option<f32> {
+    reply.split_whitespace().find_map(|token| {
+        token
+            .trim_matches(|c: char| !c.is_ascii_digit() && c != '.')
+            .parse::<f32>()
+            .ok()
+    })
+}
+
+#[async_trait]
+impl Reranker for LlmReranker<'_> {
+    async fn rerank(&self, query: &str, chunks: Vec<RetrievedChunk>) -> Vec<RetrievedChunk> {
+        // Replies arrive in any order, so each score carries its chunk's retrieval position
+        let calls: Vec<_> = chunks
+            .iter()
+            .enumerate()
+            .map(|(position, chunk)| async move {
+                let score = self.score(query, &chunk.chunk).await.unwrap_or(NEUTRAL_SCORE);
+                (position, score)
+            })
+            .collect();
+        let mut scores: Vec<(usize, f32)> = stream::iter(calls)
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        // Highest rating first; the retrieval position breaks ties
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut slots: Vec<Option<RetrievedChunk>> = chunks.into_iter().map(Some).collect();
+        scores
+            .into_iter()
+            .filter_map(|(position, _)| slots[position].take())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_are_read_from_decorated_replies() {
+        assert_eq!(parse_rating("7"), Some(7.0));
+        assert_eq!(parse_rating("Rating: 8.5"), Some(8.5));
+        assert_eq!(parse_rating("**9**"), Some(9.0));
+        assert_eq!(parse_rating("I cannot rate this passage."), None);
+    }
+}
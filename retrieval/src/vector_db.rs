@@ -1,38 +1,773 @@
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
-use chromadb::collection::{ChromaCollection, CollectionEntries, QueryOptions};
-use serde_json::json;
+use chromadb::collection::{
+    ChromaCollection, CollectionEntries, GetOptions, GetResult, QueryOptions, QueryResult,
+};
+use futures::future::join_all;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+use tracing::{info, warn};
 use crate::data::Chunk;
-use crate::embeddings::SentenceEmbedder;
+use crate::embeddings::Embedder;
 
+/// Changes this process has made to each collection, by name, so caches can
+/// tell that a collection's contents changed.
+static COLLECTION_VERSIONS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// How many times this process has changed the contents of the collection
+/// named `collection_name`, by upserting, deleting entries or rebuilding it.
+pub fn collection_version(collection_name: &str) -> u64 {
+    COLLECTION_VERSIONS
+        .lock()
+        .unwrap()
+        .get(collection_name)
+        .copied()
+        .unwrap_or(0)
+}
+
+fn bump_collection_version(collection_name: &str) {
+    *COLLECTION_VERSIONS
+        .lock()
+        .unwrap()
+        .entry(collection_name.to_string())
+        .or_insert(0) += 1;
+}
+
+#[derive(Clone)]
 pub struct RetrievedChunk {
+    /// Chroma id of the entry, for follow-up updates or deletes.
+    pub id: String,
     pub chunk: String,
     pub doc_id: usize,
-    pub distance: f32,
+    pub chunk_id: usize,
+    /// Distance from the query, or `None` when Chroma returned none for this
+    /// chunk or it was not ranked by similarity (pinned chunks). An unknown
+    /// distance never passes a distance threshold.
+    pub distance: Option<f32>,
+    /// Placed in the context through `pinned_ids` rather than found by the
+    /// search. Pinned chunks stay first and are exempt from distance gates.
+    pub pinned: bool,
+}
+
+/// A distance for display: four decimals, or "unknown".
+pub fn format_distance(distance: Option<f32>) -> String {
+    distance.map_or_else(|| "unknown".to_string(), |d| format!("{:.4}", d))
+}
+
+/// Order distances closest first, with unknown distances last.
+fn compare_distances(a: Option<f32>, b: Option<f32>) -> std::cmp::Ordering {
+    a.unwrap_or(f32::INFINITY).total_cmp(&b.unwrap_or(f32::INFINITY))
+}
+
+/// Distance function of a Chroma collection (its `hnsw:space` setting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Squared Euclidean distance, Chroma's default.
+    L2,
+    /// Cosine distance, `1 - cos(a, b)`.
+    Cosine,
+    /// Inner-product distance, `1 - a·b`.
+    InnerProduct,
+}
+
+impl DistanceMetric {
+    /// Read the metric from the collection's metadata, defaulting to L2 as Chroma does.
+    pub fn of_collection(collection: &ChromaCollection) -> Self {
+        match collection
+            .metadata()
+            .and_then(|meta| meta.get("hnsw:space"))
+            .and_then(|space| space.as_str())
+        {
+            Some("cosine") => DistanceMetric::Cosine,
+            Some("ip") => DistanceMetric::InnerProduct,
+            _ => DistanceMetric::L2,
+        }
+    }
+}
+
+/// Convert a Chroma distance to a similarity where higher means more similar.
+///
+/// Cosine and inner-product distances are `1 - similarity`, so they are
+/// inverted directly. L2 distances are unbounded, so they map to
+/// `1 / (1 + d)` in (0, 1] instead of going negative past a distance of 1.
+pub fn distance_to_similarity(distance: f32, metric: DistanceMetric) -> f32 {
+    match metric {
+        DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+        DistanceMetric::L2 => 1.0 / (1.0 + distance),
+    }
 }
 
-pub async fn retrieve_top_chunks(
+/// Labels for ranges of distances, used to present how close a result is.
+///
+/// Each band is an inclusive upper bound on the distance; a distance above
+/// every bound gets the fallback label.
+#[derive(Debug, Clone)]
+pub struct SimilarityBands {
+    /// Upper bounds with their labels, in ascending order of distance.
+    bands: Vec<(f32, String)>,
+    fallback: String,
+}
+
+impl SimilarityBands {
+    /// Custom bands; they need not be given in order.
+    pub fn new(bands: Vec<(f32, String)>, fallback: impl Into<String>) -> Self {
+        let mut bands = bands;
+        bands.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self {
+            bands,
+            fallback: fallback.into(),
+        }
+    }
+
+    /// Default star ratings for `metric`.
+    ///
+    /// For unit-length embeddings a squared L2 distance is twice the cosine
+    /// distance, so the cosine and inner-product bounds are the L2 ones halved.
+    pub fn for_metric(metric: DistanceMetric) -> Self {
+        let scale = match metric {
+            DistanceMetric::L2 => 1.0,
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => 0.5,
+        };
+        let bands = [
+            (0.5, "Very High ★★★★★"),
+            (0.8, "High ★★★★"),
+            (1.0, "Good ★★★"),
+            (1.2, "Moderate ★★"),
+        ]
+        .into_iter()
+        .map(|(bound, label)| (bound * scale, label.to_string()))
+        .collect();
+        Self::new(bands, "Low ★")
+    }
+
+    /// The label of the first band whose upper bound is at least `distance`.
+    pub fn band_for(&self, distance: f32) -> &str {
+        self.bands
+            .iter()
+            .find(|(bound, _)| distance <= *bound)
+            .map_or(self.fallback.as_str(), |(_, label)| label.as_str())
+    }
+}
+
+/// A query given either as raw text or as an already computed embedding.
+///
+/// Passing an embedding lets callers that already embedded the query skip a
+/// second call to the embedder.
+pub enum QueryInput<'a> {
+    Text(&'a str),
+    Embedding(&'a [f32]),
+}
+
+impl<'a> From<&'a str> for QueryInput<'a> {
+    fn from(text: &'a str) -> Self {
+        QueryInput::Text(text)
+    }
+}
+
+impl<'a> From<&'a [f32]> for QueryInput<'a> {
+    fn from(embedding: &'a [f32]) -> Self {
+        QueryInput::Embedding(embedding)
+    }
+}
+
+/// Common English words that carry no meaning on their own; a query made only
+/// of these is treated as empty.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "is", "in", "of", "to", "a", "an", "that", "for", "on", "with", "as", "it", "by",
+    "this", "are", "was", "at", "from", "or", "be", "which", "not", "can", "also", "have", "has",
+    "had", "we", "they", "you", "he", "she", "his", "her", "its", "our", "us", "their", "them",
+    "i", "do", "does", "did", "just", "so", "if", "may", "will", "shall", "more", "most", "some",
+    "many", "any", "all", "what", "about", "would", "could", "should", "where", "when", "why",
+    "how",
+];
+
+/// True if `query` has no words but stopwords, e.g. "", "   " or "what is
+/// the". Embedding such a query would return arbitrary nearest neighbours.
+pub fn is_blank_query(query: &str) -> bool {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOPWORDS.contains(&word.to_lowercase().as_str()))
+}
+
+impl QueryInput<'_> {
+    /// Return the query embedding, embedding the text only when needed.
+    pub fn to_embedding(&self, embedder: &dyn Embedder) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        match self {
+            QueryInput::Text(text) => Ok(embedder.embed_texts(&[text])?.remove(0)),
+            QueryInput::Embedding(embedding) => Ok(embedding.to_vec()),
+        }
+    }
+}
+
+/// Collection metadata key recording whether the stored vectors have unit length.
+const NORMALIZED_KEY: &str = "embeddings_normalized";
+
+/// How far a vector's length may be from 1 for it to count as normalized.
+const NORM_TOLERANCE: f32 = 1e-3;
+
+/// Scale `vector` to unit length. A zero vector is left as is.
+pub fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Whether `embedder` returns unit-length vectors, judged from one probe text.
+pub fn embedder_normalizes(embedder: &dyn Embedder) -> Result<bool, Box<dyn std::error::Error>> {
+    let probe = embedder.embed_texts(&["normalization check"])?.remove(0);
+    let norm = probe.iter().map(|x| x * x).sum::<f32>().sqrt();
+    Ok((norm - 1.0).abs() < NORM_TOLERANCE)
+}
+
+/// Whether the collection was built from normalized vectors, as recorded by
+/// `build_chroma_collection`, or `None` for collections created without it.
+pub fn collection_normalized(collection: &ChromaCollection) -> Option<bool> {
+    collection
+        .metadata()
+        .and_then(|meta| meta.get(NORMALIZED_KEY))
+        .and_then(|flag| flag.as_bool())
+}
+
+/// Fail with `RagError::NormalizationMismatch` if `embedder` does not
+/// normalize its vectors the way the collection's were when it was built.
+///
+/// Distances between a unit-length and an unscaled vector mean little under
+/// L2 or inner product, so such a collection would return poor results
+/// without any error. Collections with no recorded setting always pass.
+pub async fn assert_consistent_normalization(
     collection: &ChromaCollection,
-    query: &str,
+    embedder: &dyn Embedder,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(collection_normalized) = collection_normalized(collection) else {
+        return Ok(());
+    };
+    let embedder_normalized = embedder_normalizes(embedder)?;
+    if collection_normalized != embedder_normalized {
+        return Err(RagError::NormalizationMismatch {
+            collection_normalized,
+            embedder_normalized,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Embed `query` for searching `collection`, normalizing it to unit length
+/// when the collection's vectors were.
+fn query_embedding_for(
+    collection: &ChromaCollection,
+    query: QueryInput<'_>,
+    embedder: &dyn Embedder,
+) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    let mut embedding = query.to_embedding(embedder)?;
+    if collection_normalized(collection) == Some(true) {
+        l2_normalize(&mut embedding);
+    }
+    Ok(embedding)
+}
+
+/// Build the Chroma `where` filter for an optional category and a list of
+/// excluded categories, combining the two with `$and` when both are set.
+fn category_where_clause(category_filter: Option<&str>, exclude_categories: &[String]) -> Option<Value> {
+    let mut clauses = Vec::new();
+    if let Some(category) = category_filter {
+        clauses.push(json!({"category": category}));
+    }
+    if !exclude_categories.is_empty() {
+        clauses.push(json!({"category": {"$nin": exclude_categories}}));
+    }
+
+    match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(json!({"$and": clauses})),
+    }
+}
+
+/// True if `category_filter` names an excluded category, in which case the
+/// exclusion wins and the search can only come back empty.
+fn filter_is_excluded(category_filter: Option<&str>, exclude_categories: &[String]) -> bool {
+    match category_filter {
+        Some(category) if exclude_categories.iter().any(|c| c == category) => {
+            warn!("Category {:?} is both included and excluded; excluding it", category);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Retrieve the `top_k` chunks closest to `query`.
+///
+/// Results can be restricted to `category_filter` and kept out of any of
+/// `exclude_categories`; if both name the same category, nothing is returned.
+/// A blank text query fails with `RagError::EmptyQuery` before anything is embedded.
+///
+/// Chunks whose Chroma ids are in `pinned_ids` are always returned first,
+/// whatever their distance and regardless of the filters, with no distance;
+/// similarity results fill the remaining `top_k` slots without repeating
+/// them. A repeated pinned id is returned once; ids missing from the
+/// collection or without a `doc_id` are skipped with a warning.
+#[allow(clippy::too_many_arguments)]
+pub async fn retrieve_top_chunks<'a>(
+    collection: &ChromaCollection,
+    query: impl Into<QueryInput<'a>>,
     top_k: usize,
-    embedder: &SentenceEmbedder,
+    embedder: &dyn Embedder,
     category_filter: Option<&str>,
+    exclude_categories: Option<Vec<String>>,
     distance_threshold: Option<f32>,
+    min_chunk_words: Option<usize>,
+    pinned_ids: &[&str],
 ) -> Result<Vec<RetrievedChunk>, Box<dyn std::error::Error>> {
+    let query = query.into();
+    if let QueryInput::Text(text) = query
+        && is_blank_query(text)
+    {
+        return Err(RagError::EmptyQuery.into());
+    }
 
-    let query_embeddings = embedder.embed_texts(&[query])?;
+    let mut pinned = fetch_pinned(collection, pinned_ids).await?;
+    let remaining = top_k.saturating_sub(pinned.len());
 
-    // Build metadata filter if category is provided
-    let where_metadata = category_filter.map(|category| {
-        json!({"category": category})
-    });
+    let exclude_categories = exclude_categories.unwrap_or_default();
+    if remaining == 0 || filter_is_excluded(category_filter, &exclude_categories) {
+        return Ok(pinned);
+    }
+
+    let query_embedding = query_embedding_for(collection, query, embedder)?;
 
-    // Request more results than top_k to account for filtering by distance
-    let query_n = if distance_threshold.is_some() {
+    // Build metadata filter from the included and excluded categories
+    let where_metadata = category_where_clause(category_filter, &exclude_categories);
+
+    // Request more results than top_k to account for filtering by distance or length
+    let query_n = if distance_threshold.is_some() || min_chunk_words.is_some() {
         top_k * 3  // Request more to ensure we have enough after filtering
     } else {
         top_k
     };
 
+    let query_options = QueryOptions {
+        query_texts: None,
+        query_embeddings: Some(vec![query_embedding]),
+        // Pinned chunks may come back too; ask for enough to replace them
+        n_results: Some(query_n + pinned.len()),
+        where_metadata,
+        where_document: None,
+        include: Some(vec!["documents", "distances", "metadatas"]),
+    };
+
+    let query_result = collection.query(query_options, None).await?;
+    let similar = collect_row(
+        &query_result,
+        0,
+        remaining + pinned.len(),
+        distance_threshold,
+        min_chunk_words,
+    );
+
+    let fetched: HashSet<String> = pinned.iter().map(|chunk| chunk.id.clone()).collect();
+    pinned.extend(
+        similar
+            .into_iter()
+            .filter(|chunk| !fetched.contains(&chunk.id))
+            .take(remaining),
+    );
+    Ok(pinned)
+}
+
+/// Fetch the chunks stored under `ids`, in the order given, as retrieved
+/// chunks without a distance. See `pinned_chunks`.
+async fn fetch_pinned(
+    collection: &ChromaCollection,
+    ids: &[&str],
+) -> Result<Vec<RetrievedChunk>, Box<dyn std::error::Error>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let options = GetOptions {
+        ids: ids.iter().map(|id| id.to_string()).collect(),
+        where_metadata: None,
+        limit: None,
+        offset: None,
+        where_document: None,
+        include: Some(vec!["documents".to_string(), "metadatas".to_string()]),
+    };
+    let response = collection.get(options).await?;
+    Ok(pinned_chunks(&response, ids))
+}
+
+/// The entries of `response` for `ids`, in the order given and each id once.
+///
+/// Ids the collection does not have, or whose metadata lacks a `doc_id`, are
+/// skipped with a warning rather than attributed to a made-up document.
+fn pinned_chunks(response: &GetResult, ids: &[&str]) -> Vec<RetrievedChunk> {
+    let mut seen = HashSet::new();
+    let mut pinned = Vec::new();
+    for &id in ids {
+        if !seen.insert(id) {
+            continue;
+        }
+        // Chroma returns the entries in its own order, so look each id up
+        let Some(pos) = response.ids.iter().position(|found| found == id) else {
+            warn!("Pinned id {:?} is not in the collection; skipping it", id);
+            continue;
+        };
+        let metadata = response
+            .metadatas
+            .as_ref()
+            .and_then(|metadatas| metadatas.get(pos))
+            .and_then(|metadata| metadata.as_ref());
+        let field = |key: &str| {
+            metadata
+                .and_then(|metadata| metadata.get(key))
+                .and_then(|value| value.as_u64())
+                .map(|value| value as usize)
+        };
+        let Some(doc_id) = field("doc_id") else {
+            warn!("Pinned id {:?} has no doc_id metadata; skipping it", id);
+            continue;
+        };
+
+        pinned.push(RetrievedChunk {
+            id: id.to_string(),
+            chunk: response
+                .documents
+                .as_ref()
+                .and_then(|documents| documents.get(pos))
+                .cloned()
+                .flatten()
+                .unwrap_or_default(),
+            doc_id,
+            chunk_id: field("chunk_id").unwrap_or(0),
+            distance: None,
+            pinned: true,
+        });
+    }
+    pinned
+}
+
+/// Run one category-filtered search per category, concurrently, and return
+/// each category's own top `k_per_category` chunks.
+///
+/// The query is embedded once and shared by all searches. Every requested
+/// category is present in the result; categories without matching documents
+/// map to an empty vector.
+pub async fn search_per_category(
+    collection: &ChromaCollection,
+    query: &str,
+    categories: &[&str],
+    k_per_category: usize,
+    embedder: &dyn Embedder,
+) -> Result<HashMap<String, Vec<RetrievedChunk>>, Box<dyn std::error::Error>> {
+    let query_embedding = embedder.embed_texts(&[query])?.remove(0);
+
+    let searches = categories.iter().map(|&category| {
+        retrieve_top_chunks(
+            collection,
+            query_embedding.as_slice(),
+            k_per_category,
+            embedder,
+            Some(category),
+            None,
+            None,
+            None,
+            &[],
+        )
+    });
+    let results = join_all(searches).await;
+
+    categories
+        .iter()
+        .zip(results)
+        .map(|(&category, chunks)| Ok((category.to_string(), chunks?)))
+        .collect()
+}
+
+/// Turn row `row` of a query result into at most `top_k` chunks, skipping those
+/// beyond `distance_threshold` (or without a distance, when there is a
+/// threshold) or shorter than `min_chunk_words`.
+fn collect_row(
+    query_result: &QueryResult,
+    row: usize,
+    top_k: usize,
+    distance_threshold: Option<f32>,
+    min_chunk_words: Option<usize>,
+) -> Vec<RetrievedChunk> {
+    let mut retrieved_chunks = Vec::new();
+
+    for i in 0..row_len(query_result, row) {
+        let chunk = chunk_at(query_result, row, i);
+
+        // Apply distance threshold filtering
+        // Note: In ChromaDB, lower distance = higher similarity
+        // Typical distance ranges: 0.0 (identical) to 2.0 (completely different)
+        if let Some(threshold) = distance_threshold {
+            if !chunk.distance.is_some_and(|d| d <= threshold) {
+                continue; // Skip chunks that are too dissimilar, or not known to be close
+            }
+        }
+
+        // Skip chunks too short to carry usable information
+        if let Some(min_words) = min_chunk_words {
+            if chunk.chunk.split_whitespace().count() < min_words {
+                continue;
+            }
+        }
+
+        retrieved_chunks.push(chunk);
+
+        // Stop if we've collected enough chunks
+        if retrieved_chunks.len() >= top_k {
+            break;
+        }
+    }
+
+    retrieved_chunks
+}
+
+/// Number of documents in row `row` of a query result.
+fn row_len(query_result: &QueryResult, row: usize) -> usize {
+    query_result
+        .documents
+        .as_ref()
+        .and_then(|rows| rows.get(row))
+        .map_or(0, Vec::len)
+}
+
+/// Metadata of result `i` in row `row`, if Chroma returned any.
+fn metadata_at(query_result: &QueryResult, row: usize, i: usize) -> Option<&serde_json::Map<String, Value>> {
+    query_result
+        .metadatas
+        .as_ref()
+        .and_then(|rows| rows.get(row))
+        .and_then(|row| row.get(i))
+        .and_then(|metadata| metadata.as_ref())
+}
+
+/// Result `i` in row `row` of a query result as a retrieved chunk.
+fn chunk_at(query_result: &QueryResult, row: usize, i: usize) -> RetrievedChunk {
+    let doc = query_result
+        .documents
+        .as_ref()
+        .and_then(|rows| rows.get(row))
+        .and_then(|row| row.get(i))
+        .cloned()
+        .unwrap_or_default();
+
+    let distance = query_result
+        .distances
+        .as_ref()
+        .and_then(|rows| rows.get(row))
+        .and_then(|row| row.get(i))
+        .copied();
+
+    let metadata = metadata_at(query_result, row, i);
+    // Extract doc_id from metadata if available
+    let doc_id = metadata
+        .and_then(|metadata| metadata.get("doc_id"))
+        .and_then(|value| value.as_u64())
+        .map(|id| id as usize)
+        .unwrap_or(i); // Fallback to index if metadata not found
+
+    // Extract chunk_id from metadata, defaulting to the first chunk
+    let chunk_id = metadata
+        .and_then(|metadata| metadata.get("chunk_id"))
+        .and_then(|value| value.as_u64())
+        .map(|id| id as usize)
+        .unwrap_or(0);
+
+    let id = query_result
+        .ids
+        .get(row)
+        .and_then(|ids| ids.get(i))
+        .cloned()
+        .unwrap_or_default();
+
+    RetrievedChunk {
+        id,
+        chunk: doc,
+        doc_id,
+        chunk_id,
+        distance,
+        pinned: false,
+    }
+}
+
+/// Why `retrieve_top_chunks_verbose` left a candidate out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterReason {
+    /// The chunk's category is not the requested one.
+    CategoryMismatch { category: String },
+    /// The chunk's category is one of the excluded ones.
+    CategoryExcluded { category: String },
+    /// Chroma returned no distance for the chunk, so it cannot be shown to be
+    /// within the distance threshold.
+    DistanceUnknown,
+    /// The chunk is farther from the query than the distance threshold.
+    DistanceExceeded { threshold: f32 },
+    /// The chunk has fewer words than the minimum.
+    TooShort { words: usize, min_words: usize },
+}
+
+impl fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterReason::CategoryMismatch { category } => {
+                write!(f, "category {:?} does not match the filter", category)
+            }
+            FilterReason::CategoryExcluded { category } => {
+                write!(f, "category {:?} is excluded", category)
+            }
+            FilterReason::DistanceUnknown => write!(f, "no distance was returned"),
+            FilterReason::DistanceExceeded { threshold } => {
+                write!(f, "distance above the threshold of {:.2}", threshold)
+            }
+            FilterReason::TooShort { words, min_words } => {
+                write!(f, "{} words, fewer than the minimum of {}", words, min_words)
+            }
+        }
+    }
+}
+
+/// A candidate chunk that `retrieve_top_chunks_verbose` did not keep.
+#[derive(Debug, Clone)]
+pub struct FilteredOut {
+    pub id: String,
+    pub distance: Option<f32>,
+    pub reason: FilterReason,
+}
+
+/// Retrieve chunks as `retrieve_top_chunks` does, and also report each nearby
+/// chunk that a filter removed and why.
+///
+/// To see what the category filters remove, this searches without them and
+/// applies every filter locally to the `3 * top_k` nearest chunks, checking
+/// category, then distance, then length. It is meant for diagnostics such as
+/// explaining an empty result: when the requested category's chunks all lie
+/// outside that window, it keeps fewer chunks than `retrieve_top_chunks`
+/// would. A blank text query fails with `RagError::EmptyQuery`.
+#[allow(clippy::too_many_arguments)]
+pub async fn retrieve_top_chunks_verbose<'a>(
+    collection: &ChromaCollection,
+    query: impl Into<QueryInput<'a>>,
+    top_k: usize,
+    embedder: &dyn Embedder,
+    category_filter: Option<&str>,
+    exclude_categories: &[String],
+    distance_threshold: Option<f32>,
+    min_chunk_words: Option<usize>,
+) -> Result<(Vec<RetrievedChunk>, Vec<FilteredOut>), Box<dyn std::error::Error>> {
+    let query = query.into();
+    if let QueryInput::Text(text) = query
+        && is_blank_query(text)
+    {
+        return Err(RagError::EmptyQuery.into());
+    }
+
+    let query_options = QueryOptions {
+        query_texts: None,
+        query_embeddings: Some(vec![query_embedding_for(collection, query, embedder)?]),
+        n_results: Some(top_k * 3),
+        where_metadata: None,
+        where_document: None,
+        include: Some(vec!["documents", "distances", "metadatas"]),
+    };
+    let query_result = collection.query(query_options, None).await?;
+
+    let mut kept = Vec::new();
+    let mut filtered = Vec::new();
+    for i in 0..row_len(&query_result, 0) {
+        if kept.len() >= top_k {
+            break;
+        }
+        let chunk = chunk_at(&query_result, 0, i);
+        let category = metadata_at(&query_result, 0, i)
+            .and_then(|metadata| metadata.get("category"))
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let words = chunk.chunk.split_whitespace().count();
+
+        let reason = if exclude_categories.contains(&category) {
+            Some(FilterReason::CategoryExcluded { category })
+        } else if category_filter.is_some_and(|wanted| wanted != category) {
+            Some(FilterReason::CategoryMismatch { category })
+        } else if distance_threshold.is_some() && chunk.distance.is_none()
+        {
+            Some(FilterReason::DistanceUnknown)
+        } else if let Some(threshold) = distance_threshold
+            && chunk.distance.is_some_and(|d| d > threshold)
+        {
+            Some(FilterReason::DistanceExceeded { threshold })
+        } else if let Some(min_words) = min_chunk_words
+            && words < min_words
+        {
+            Some(FilterReason::TooShort { words, min_words })
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => filtered.push(FilteredOut {
+                id: chunk.id,
+                distance: chunk.distance,
+                reason,
+            }),
+            None => kept.push(chunk),
+        }
+    }
+
+    Ok((kept, filtered))
+}
+
+/// Retrieve the top chunks for many queries at once.
+///
+/// All queries are embedded in a single call and sent to Chroma as one
+/// multi-row query; the filters behave as in `retrieve_top_chunks`. The
+/// result holds one list of chunks per query, in input order. If any query is
+/// blank, the whole batch fails with `RagError::EmptyQuery`.
+#[allow(clippy::too_many_arguments)]
+pub async fn retrieve_top_chunks_batch(
+    collection: &ChromaCollection,
+    queries: &[&str],
+    top_k: usize,
+    embedder: &dyn Embedder,
+    category_filter: Option<&str>,
+    exclude_categories: Option<Vec<String>>,
+    distance_threshold: Option<f32>,
+    min_chunk_words: Option<usize>,
+) -> Result<Vec<Vec<RetrievedChunk>>, Box<dyn std::error::Error>> {
+    if queries.is_empty() {
+        return Ok(Vec::new());
+    }
+    if queries.iter().any(|query| is_blank_query(query)) {
+        return Err(RagError::EmptyQuery.into());
+    }
+
+    let exclude_categories = exclude_categories.unwrap_or_default();
+    if filter_is_excluded(category_filter, &exclude_categories) {
+        return Ok(queries.iter().map(|_| Vec::new()).collect());
+    }
+
+    let mut query_embeddings = embedder.embed_texts(queries)?;
+    if collection_normalized(collection) == Some(true) {
+        query_embeddings.iter_mut().for_each(|embedding| l2_normalize(embedding));
+    }
+
+    let where_metadata = category_where_clause(category_filter, &exclude_categories);
+
+    // Request more results than top_k to account for filtering by distance or length
+    let query_n = if distance_threshold.is_some() || min_chunk_words.is_some() {
+        top_k * 3
+    } else {
+        top_k
+    };
+
     let query_options = QueryOptions {
         query_texts: None,
         query_embeddings: Some(query_embeddings),
@@ -43,79 +778,240 @@ pub async fn retrieve_top_chunks(
     };
 
     let query_result = collection.query(query_options, None).await?;
-    let mut retrieved_chunks = Vec::new();
 
-    if let Some(documents_groups) = query_result.documents.as_ref() {
-        if let Some(documents) = documents_groups.get(0) {
-            for (i, doc) in documents.iter().enumerate() {
-                let distance = query_result
-                    .distances
-                    .as_ref()
-                    .and_then(|rows| rows.get(0))
-                    .and_then(|row| row.get(i))
-                    .copied()
-                    .unwrap_or(0.0);
-
-                // Apply distance threshold filtering
-                // Note: In ChromaDB, lower distance = higher similarity
-                // Typical distance ranges: 0.0 (identical) to 2.0 (completely different)
-                if let Some(threshold) = distance_threshold {
-                    if distance > threshold {
-                        continue; // Skip chunks that are too dissimilar
-                    }
-                }
-
-                // Extract doc_id from metadata if available
-                let doc_id = query_result
-                    .metadatas
-                    .as_ref()
-                    .and_then(|rows| rows.get(0))
-                    .and_then(|row| row.get(i))
-                    .and_then(|metadata| metadata.as_ref())
-                    .and_then(|metadata| metadata.get("doc_id"))
-                    .and_then(|value| value.as_u64())
-                    .map(|id| id as usize)
-                    .unwrap_or(i); // Fallback to index if metadata not found
-
-                retrieved_chunks.push(RetrievedChunk {
-                    chunk: doc.clone(),
-                    doc_id,
-                    distance,
-                });
-
-                // Stop if we've collected enough chunks
-                if retrieved_chunks.len() >= top_k {
-                    break;
-                }
+    Ok((0..queries.len())
+        .map(|row| collect_row(&query_result, row, top_k, distance_threshold, min_chunk_words))
+        .collect())
+}
+
+/// Merge retrieved chunks that are adjacent pieces of the same document.
+///
+/// Chunks sharing a `doc_id` with consecutive `chunk_id`s are joined in
+/// document order into a single result that keeps the best (lowest) distance
+/// and the first `chunk_id` and `id` of the run. The merged results are ordered by
+/// distance, most similar first, with chunks of unknown distance last.
+/// Pinned chunks are neither merged nor reordered: they come first, in the
+/// order they were pinned.
+pub fn coalesce_adjacent(chunks: Vec<RetrievedChunk>) -> Vec<RetrievedChunk> {
+    let (pinned, mut chunks): (Vec<_>, Vec<_>) = chunks.into_iter().partition(|chunk| chunk.pinned);
+
+    // Group by document, in chunk order
+    chunks.sort_by(|a, b| a.doc_id.cmp(&b.doc_id).then(a.chunk_id.cmp(&b.chunk_id)));
+
+    let mut merged: Vec<RetrievedChunk> = Vec::new();
+    let mut last_chunk_id = 0;
+
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(prev) if prev.doc_id == chunk.doc_id && chunk.chunk_id == last_chunk_id + 1 => {
+                prev.chunk.push(' ');
+                prev.chunk.push_str(&chunk.chunk);
+                prev.distance = match (prev.distance, chunk.distance) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (known, None) | (None, known) => known,
+                };
+                last_chunk_id = chunk.chunk_id;
+            }
+            _ => {
+                last_chunk_id = chunk.chunk_id;
+                merged.push(chunk);
             }
         }
     }
 
-    Ok(retrieved_chunks)
+    merged.sort_by(|a, b| compare_distances(a.distance, b.distance));
+    pinned.into_iter().chain(merged).collect()
 }
 
 
-/// Create (or retrieve) a ChromaDB collection and upsert the full document texts.
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// HNSW index settings applied when a collection is created.
+///
+/// Higher `construction_ef` and `m` build a denser graph with better recall at
+/// the cost of slower inserts and more memory; higher `search_ef` improves
+/// recall per query at the cost of latency. The defaults match Chroma's own
+/// and are fine for small corpora. Chroma fixes these values at creation, so
+/// they have no effect on a collection that already exists.
+pub struct IndexParams {
+    /// Candidate list size while building the graph (`hnsw:construction_ef`).
+    pub construction_ef: usize,
+    /// Candidate list size while searching (`hnsw:search_ef`).
+    pub search_ef: usize,
+    /// Maximum neighbours per node (`hnsw:M`).
+    pub m: usize,
+}
+
+impl Default for IndexParams {
+    fn default() -> Self {
+        Self {
+            construction_ef: 100,
+            search_ef: 10,
+            m: 16,
+        }
+    }
+}
+
+impl IndexParams {
+    /// Collection metadata carrying these settings.
+    fn to_metadata(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut map = serde_json::Map::new();
+        map.insert("hnsw:construction_ef".to_string(), json!(self.construction_ef));
+        map.insert("hnsw:search_ef".to_string(), json!(self.search_ef));
+        map.insert("hnsw:M".to_string(), json!(self.m));
+        map
+    }
+}
+
+/// What `build_chroma_collection` does with a collection that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollectionMode {
+    /// Upsert into the existing entries. Entries whose ids the new chunks no
+    /// longer use, such as those left by a run with another chunk size, stay
+    /// and are retrieved alongside the new ones.
+    Reuse,
+    /// Delete the collection and create it anew, so it holds exactly `chunks`.
+    #[default]
+    Rebuild,
+    /// Keep the collection but delete the entries whose ids are not among the
+    /// new chunks' ids, then upsert. Ends like `Rebuild` without dropping the
+    /// collection or its settings.
+    Sync,
+}
+
+impl CollectionMode {
+    /// Parse "reuse", "rebuild" or "sync", ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "reuse" => Some(CollectionMode::Reuse),
+            "rebuild" => Some(CollectionMode::Rebuild),
+            "sync" => Some(CollectionMode::Sync),
+            _ => None,
+        }
+    }
+}
+
+/// Create (or retrieve) a ChromaDB collection and upsert `chunks` into it
+/// with `upsert_chunks`, first handling existing entries according to `mode`.
+///
+/// `IndexParams` only apply when the collection is created, which `Rebuild`
+/// always does. A new collection also records whether `embedder` returns
+/// normalized vectors, so queries can be normalized to match. An existing
+/// collection keeps its metadata, and with `Reuse` or `Sync` the call fails
+/// with `RagError::NormalizationMismatch` before changing anything if
+/// `embedder` normalizes differently from the recorded setting.
 pub async fn build_chroma_collection(
     chunks: &[Chunk],
     collection_name: &str,
-    embedder: &SentenceEmbedder,
+    embedder: &dyn Embedder,
+    index_params: &IndexParams,
+    mode: CollectionMode,
 ) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
+    let client = connect_chroma().await?;
+    let exists = client
+        .list_collections()
+        .await?
+        .iter()
+        .any(|existing| existing.name() == collection_name);
+    if exists && mode == CollectionMode::Rebuild {
+        client.delete_collection(collection_name).await?;
+        bump_collection_version(collection_name);
+    }
+    let collection = if exists && mode != CollectionMode::Rebuild {
+        // Leave the stored metadata alone: it records how the entries were embedded
+        let collection = client.get_collection(collection_name).await?;
+        assert_consistent_normalization(&collection, embedder).await?;
+        collection
+    } else {
+        let mut metadata = index_params.to_metadata();
+        metadata.insert(NORMALIZED_KEY.to_string(), json!(embedder_normalizes(embedder)?));
+        client.create_collection(collection_name, Some(metadata), true).await?
+    };
 
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
-    let collection = client.get_or_create_collection(collection_name, None).await?;
+    if mode == CollectionMode::Sync {
+        let wanted: HashSet<String> = chunk_ids(chunks).into_iter().collect();
+        let stale: Vec<String> = collection_ids(&collection)
+            .await?
+            .into_iter()
+            .filter(|id| !wanted.contains(id))
+            .collect();
+        if !stale.is_empty() {
+            info!("Removing {} stale entries from {}", stale.len(), collection_name);
+            for page in stale.chunks(REEMBED_PAGE_SIZE) {
+                let ids: Vec<&str> = page.iter().map(String::as_str).collect();
+                collection.delete(Some(ids), None, None).await?;
+                bump_collection_version(collection_name);
+            }
+        }
+    }
 
-    // Use the entire document content.
-    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+    upsert_chunks(&collection, chunks, embedder).await?;
+    Ok(collection)
+}
 
-    // Create a unique ID for each document
-    let ids_owned: Vec<String> = chunks
+/// Chroma ids for `chunks`: `doc_{doc_id}_chunk_{chunk_id}`.
+///
+/// Every chunk of a document gets its own id. If the same pair occurs twice,
+/// e.g. because two documents share an explicit `id`, the later ones get a
+/// `_dup{n}` suffix and a warning instead of overwriting the first.
+pub(crate) fn chunk_ids(chunks: &[Chunk]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    chunks
         .iter()
-        .map(|chunk| format!("doc_{}", chunk.doc_id))
-        .collect();
+        .map(|chunk| {
+            let id = format!("doc_{}_chunk_{}", chunk.doc_id, chunk.chunk_id);
+            let count = seen.entry(id.clone()).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                id
+            } else {
+                warn!("Duplicate chunk id {}; storing copy {} separately", id, count);
+                format!("{}_dup{}", id, *count - 1)
+            }
+        })
+        .collect()
+}
+
+/// Embed `chunks` and upsert them into an existing collection.
+///
+/// Each entry is stored under its `chunk_ids` id, so every chunk of a document
+/// gets its own entry.
+pub async fn upsert_chunks(
+    collection: &ChromaCollection,
+    chunks: &[Chunk],
+    embedder: &dyn Embedder,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if chunks.is_empty() {
+        return Ok(());
+    }
+
+    let ids_owned = chunk_ids(chunks);
     let ids: Vec<&str> = ids_owned.iter().map(|s| s.as_str()).collect();
 
-    // Prepare metadata for each document.
     let metadatas: Vec<serde_json::Map<String, serde_json::Value>> = chunks
         .iter()
         .map(|chunk| {
@@ -127,11 +1023,8 @@ pub async fn build_chroma_collection(
         })
         .collect();
 
-    // Get embeddings for the documents.
-    let text_slices: Vec<&str> = texts.iter().map(String::as_str).collect();
-    let embeddings = embedder.embed_texts(&text_slices)?;
-
-    let documents: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+    let documents: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+    let embeddings = embedder.embed_texts(&documents)?;
 
     let entries = CollectionEntries {
         ids,
@@ -141,5 +1034,249 @@ pub async fn build_chroma_collection(
     };
 
     collection.upsert(entries, None).await?;
-    Ok(collection)
+    bump_collection_version(collection.name());
+    Ok(())
+}
+
+/// Entries fetched and re-embedded at a time by `reembed_collection`, and
+/// ids listed or deleted at a time when syncing a collection.
+const REEMBED_PAGE_SIZE: usize = 100;
+
+/// Every id in `collection`.
+async fn collection_ids(collection: &ChromaCollection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut ids: Vec<String> = Vec::new();
+    loop {
+        let page = collection
+            .get(GetOptions {
+                ids: Vec::new(),
+                where_metadata: None,
+                limit: Some(REEMBED_PAGE_SIZE),
+                offset: Some(ids.len()),
+                where_document: None,
+                include: Some(Vec::new()),
+            })
+            .await?;
+        let fetched = page.ids.len();
+        ids.extend(page.ids);
+        if fetched < REEMBED_PAGE_SIZE {
+            break;
+        }
+    }
+    Ok(ids)
+}
+
+/// Replace every vector in `collection` with one from `embedder`, in place.
+///
+/// The stored documents are read back page by page, embedded again and
+/// upserted under their existing ids, keeping their metadata and adding an
+/// `embedding_model` field with `embedder.model_name()`. Entries without a
+/// stored document are skipped with a warning. Returns the number of entries
+/// re-embedded.
+///
+/// Chroma fixes a collection's dimension, so if the new vectors have a
+/// different length than the stored ones this fails with
+/// `RagError::DimensionMismatch` before anything is changed, and an embedder
+/// that normalizes differently from the collection's record fails with
+/// `RagError::NormalizationMismatch`.
+pub async fn reembed_collection(
+    collection: &ChromaCollection,
+    embedder: &dyn Embedder,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let model_name = embedder.model_name();
+    assert_consistent_normalization(collection, embedder).await?;
+
+    // Check the dimension on one stored vector first
+    let sample = collection
+        .get(GetOptions {
+            ids: Vec::new(),
+            where_metadata: None,
+            limit: Some(1),
+            offset: None,
+            where_document: None,
+            include: Some(vec!["embeddings".to_string()]),
+        })
+        .await?;
+    let stored_dim = sample
+        .embeddings
+        .and_then(|embeddings| embeddings.into_iter().next().flatten())
+        .map(|embedding| embedding.len());
+    let Some(stored_dim) = stored_dim else {
+        return Ok(0); // Empty collection
+    };
+    let new_dim = embedder.embed_texts(&["dimension check"])?.remove(0).len();
+    if new_dim != stored_dim {
+        return Err(RagError::DimensionMismatch {
+            collection: stored_dim,
+            embedder: new_dim,
+        }
+        .into());
+    }
+
+    // Collect the ids up front: upserts may reorder what offset-based paging sees
+    let ids = collection_ids(collection).await?;
+
+    let mut reembedded = 0;
+    for page_ids in ids.chunks(REEMBED_PAGE_SIZE) {
+        let page = collection
+            .get(GetOptions {
+                ids: page_ids.to_vec(),
+                where_metadata: None,
+                limit: None,
+                offset: None,
+                where_document: None,
+                include: Some(vec!["documents".to_string(), "metadatas".to_string()]),
+            })
+            .await?;
+
+        let mut entry_ids = Vec::new();
+        let mut documents = Vec::new();
+        let mut metadatas = Vec::new();
+        for (i, id) in page.ids.iter().enumerate() {
+            let document = page
+                .documents
+                .as_ref()
+                .and_then(|documents| documents.get(i))
+                .cloned()
+                .flatten();
+            let Some(document) = document else {
+                warn!("Entry {} has no stored document; leaving its vector as is", id);
+                continue;
+            };
+            let mut metadata = page
+                .metadatas
+                .as_ref()
+                .and_then(|metadatas| metadatas.get(i))
+                .cloned()
+                .flatten()
+                .unwrap_or_default();
+            metadata.insert("embedding_model".to_string(), model_name.clone().into());
+
+            entry_ids.push(id.as_str());
+            documents.push(document);
+            metadatas.push(metadata);
+        }
+        if entry_ids.is_empty() {
+            continue;
+        }
+
+        let texts: Vec<&str> = documents.iter().map(String::as_str).collect();
+        let embeddings = embedder.embed_texts(&texts)?;
+        reembedded += entry_ids.len();
+
+        let entries = CollectionEntries {
+            ids: entry_ids,
+            embeddings: Some(embeddings),
+            metadatas: Some(metadatas),
+            documents: Some(texts),
+        };
+        collection.upsert(entries, None).await?;
+        bump_collection_version(collection.name());
+    }
+
+    Ok(reembedded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(doc_id: usize, chunk_id: usize, distance: Option<f32>, pinned: bool) -> RetrievedChunk {
+        RetrievedChunk {
+            id: format!("doc_{}_chunk_{}", doc_id, chunk_id),
+            chunk: format!("{}.{}", doc_id, chunk_id),
+            doc_id,
+            chunk_id,
+            distance,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn coalesce_keeps_pinned_chunks_first_in_pinned_order() {
+        let chunks = vec![
+            chunk(5, 0, None, true),
+            chunk(1, 0, None, true),
+            chunk(2, 0, Some(0.3), false),
+            chunk(2, 1, Some(0.1), false),
+            chunk(3, 0, Some(0.2), false),
+        ];
+        let merged = coalesce_adjacent(chunks);
+        let ids: Vec<&str> = merged.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, ["doc_5_chunk_0", "doc_1_chunk_0", "doc_2_chunk_0", "doc_3_chunk_0"]);
+        assert_eq!(merged[2].chunk, "2.0 2.1");
+        assert_eq!(merged[2].distance, Some(0.1));
+    }
+
+    #[test]
+    fn coalesce_does_not_merge_a_pinned_chunk_into_its_neighbour() {
+        let chunks = vec![chunk(1, 0, None, true), chunk(1, 1, Some(0.4), false)];
+        let merged = coalesce_adjacent(chunks);
+        assert_eq!(merged.len(), 2);
+        assert!(merged[0].pinned);
+        assert_eq!(merged[1].chunk, "1.1");
+    }
+
+    #[test]
+    fn collection_versions_are_tracked_per_collection() {
+        let before = collection_version("versions_test_other");
+        bump_collection_version("versions_test");
+        bump_collection_version("versions_test");
+        assert_eq!(collection_version("versions_test"), 2);
+        assert_eq!(collection_version("versions_test_other"), before);
+    }
+
+    #[test]
+    fn chunk_ids_are_distinct_even_for_repeated_chunks() {
+        let source = |doc_id, chunk_id| Chunk {
+            doc_id,
+            chunk_id,
+            category: "test".to_string(),
+            text: String::new(),
+        };
+        let chunks = vec![source(0, 0), source(0, 1), source(0, 0), source(1, 0), source(0, 0)];
+        let ids = chunk_ids(&chunks);
+        assert_eq!(ids.len(), chunks.len());
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), chunks.len());
+        assert_eq!(ids[0], "doc_0_chunk_0");
+        assert_eq!(ids[2], "doc_0_chunk_0_dup1");
+    }
+
+    #[test]
+    fn empty_whitespace_and_stopword_queries_are_blank() {
+        for query in ["", "   ", "what is the?"] {
+            assert!(is_blank_query(query), "{query:?}");
+        }
+        assert!(!is_blank_query("what is the NVDA price"));
+    }
+
+    fn stored(ids: &[&str], metadatas: Vec<Option<serde_json::Map<String, Value>>>) -> GetResult {
+        GetResult {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            documents: Some(ids.iter().map(|id| Some(format!("text of {}", id))).collect()),
+            metadatas: Some(metadatas),
+            embeddings: None,
+        }
+    }
+
+    fn meta(doc_id: u64, chunk_id: u64) -> Option<serde_json::Map<String, Value>> {
+        json!({ "doc_id": doc_id, "chunk_id": chunk_id }).as_object().cloned()
+    }
+
+    #[test]
+    fn pinned_chunks_keep_the_given_order_once_each() {
+        let response = stored(&["b", "a"], vec![meta(2, 0), meta(1, 3)]);
+        let pinned = pinned_chunks(&response, &["a", "b", "a", "missing"]);
+        let ids: Vec<&str> = pinned.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!((pinned[0].doc_id, pinned[0].chunk_id), (1, 3));
+        assert_eq!(pinned[0].chunk, "text of a");
+        assert!(pinned.iter().all(|c| c.pinned && c.distance.is_none()));
+    }
+
+    #[test]
+    fn pinned_chunks_without_doc_id_are_skipped() {
+        let no_doc_id = json!({ "chunk_id": 1 }).as_object().cloned();
+        let response = stored(&["a", "b"], vec![None, no_doc_id]);
+        assert!(pinned_chunks(&response, &["a", "b"]).is_empty());
+    }
 }
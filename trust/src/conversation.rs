@@ -0,0 +1,52 @@
+use crate::llm::LlmClient;
+use crate::prompt::render_builtin;
+
+/// One question of a conversation and the answer it got.
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub question: String,
+    pub answer: String,
+}
+
+/// Instructions for rewriting a follow-up into a standalone query.
+const REWRITE_SYSTEM_PROMPT: &str = "You rewrite follow-up questions so they can be understood \
+    without the conversation. Resolve pronouns and elliptical references (\"what about X?\", \
+    \"and the day before?\") using the conversation, keeping any dates, symbols and figures it \
+    mentions. If the follow-up already stands on its own, return it unchanged. Reply with the \
+    rewritten question only.";
+
+/// Rewrite `follow_up` into a query that makes sense without `history`.
+///
+/// A follow-up such as "what about MSFT?" means nothing to the retriever on
+/// its own; given the earlier turns, the LLM turns it into "What was MSFT's
+/// closing price on April 14, 2023?". With an empty history there is nothing
+/// to resolve and `follow_up` is returned as is, without calling the LLM; so
+/// is an empty rewrite.
+pub async fn contextualize_query(
+    history: &[Turn],
+    follow_up: &str,
+    llm: &LlmClient,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if history.is_empty() {
+        return Ok(follow_up.to_string());
+    }
+
+    let history: String = history
+        .iter()
+        .map(|turn| format!("User: {}\nAssistant: {}\n", turn.question, turn.answer))
+        .collect();
+    let prompt = render_builtin(
+        "rewrite",
+        &[("history", &history), ("follow_up", follow_up)],
+    );
+
+    let rewritten = llm
+        .get_llm_response_with_system(REWRITE_SYSTEM_PROMPT, &prompt)
+        .await?;
+    let rewritten = rewritten.trim().trim_matches('"').trim();
+    Ok(if rewritten.is_empty() {
+        follow_up.to_string()
+    } else {
+        rewritten.to_string()
+    })
+}
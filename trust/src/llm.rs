@@ -31,9 +31,18 @@ impl LlmClient {
     }
 
     pub async fn get_llm_response(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // Build messages using the default system prompt.
+        self.get_llm_response_with_system(&self.system_prompt, prompt).await
+    }
+
+    /// Like `get_llm_response`, but uses `system` as the system message for this call only.
+    pub async fn get_llm_response_with_system(
+        &self,
+        system: &str,
+        prompt: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        // Build messages using the given system prompt.
         let system_message = ChatCompletionRequestSystemMessage {
-            content: ChatCompletionRequestSystemMessageContent::Text(self.system_prompt.clone()),
+            content: ChatCompletionRequestSystemMessageContent::Text(system.to_string()),
             name: None,
         };
 
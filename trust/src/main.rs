@@ -1,58 +1,65 @@
+mod conversation;
+mod data;
+mod embeddings;
+mod error;
 mod llm;
+mod prompt;
+mod tickers;
+mod vector_db;
 
+use chromadb::collection::ChromaCollection;
+use conversation::{Turn, contextualize_query};
+use data::{Document, KnowledgeBase, kb_to_chunks, load_knowledge_base};
+use embeddings::SentenceEmbedder;
+use prompt::render_builtin;
 use std::collections::{HashMap, HashSet};
-
-#[derive(Debug, Clone)]
-struct Document {
-    title: String,
-    content: String,
-}
-
-type KnowledgeBase = HashMap<String, Document>;
+use std::env;
+use tickers::{known_tickers, mentions_ticker, missing_symbols, query_tickers};
+use vector_db::{build_chroma_collection, dense_retrieval};
 
 fn create_knowledge_base() -> KnowledgeBase {
     let mut kb = HashMap::new();
 
     kb.insert(
         "AAPL".to_string(),
-        Document {
-            title: "AAPL Stock (April 2023)".to_string(),
-            content: "On 2023-04-13, AAPL opened at $160.50, closed at $162.30, \
+        Document::new(
+            "AAPL",
+            "AAPL Stock (April 2023)",
+            "On 2023-04-13, AAPL opened at $160.50, closed at $162.30, \
                      with a high of $163.00 and a low of $159.90. \
                      Trading volume was 80 million shares. \
                      On 2023-04-14, AAPL opened at $161.10, closed at $162.80, \
                      with a high of $163.50 and a low of $160.50. \
-                     Trading volume was 85 million shares."
-                .to_string(),
-        },
+                     Trading volume was 85 million shares.",
+        ),
     );
 
     kb.insert(
         "MSFT".to_string(),
-        Document {
-            title: "MSFT Stock (April 2023)".to_string(),
-            content: "On 2023-04-13, MSFT opened at $285.00, closed at $288.50, \
+        Document::new(
+            "MSFT",
+            "MSFT Stock (April 2023)",
+            "On 2023-04-13, MSFT opened at $285.00, closed at $288.50, \
                      with a high of $290.00 and a low of $283.50. \
                      Trading volume was 35 million shares. \
                      On 2023-04-14, MSFT opened at $286.00, closed at $289.00, \
                      with a high of $291.50 and a low of $284.70. \
-                     Trading volume was 40 million shares."
-                .to_string(),
-        },
+                     Trading volume was 40 million shares.",
+        ),
     );
 
     kb.insert(
         "TSLA".to_string(),
-        Document {
-            title: "TSLA Stock (April 2023)".to_string(),
-            content: "On 2023-04-13, TSLA opened at $185.00, closed at $187.00, \
+        Document::new(
+            "TSLA",
+            "TSLA Stock (April 2023)",
+            "On 2023-04-13, TSLA opened at $185.00, closed at $187.00, \
                      with a high of $189.00 and a low of $184.50. \
                      Trading volume was 50 million shares. \
                      On 2023-04-14, TSLA opened at $186.00, closed at $188.50, \
                      with a high of $190.00 and a low of $185.50. \
-                     Trading volume was 55 million shares."
-                .to_string(),
-        },
+                     Trading volume was 55 million shares.",
+        ),
     );
 
     kb
@@ -63,80 +70,181 @@ async fn naive_generation(
     query: &str,
     llm: &llm::LlmClient,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let prompt = format!("Answer directly the following query: {}", query);
+    let prompt = render_builtin("naive", &[("query", query)]);
     llm.get_llm_response(&prompt).await
 }
 
+/// Default weight of the title overlap in `rag_retrieval` (the content gets the rest).
+const DEFAULT_TITLE_WEIGHT: f32 = 0.3;
+
+/// Common English words that carry no meaning on their own; a query made only
+/// of these is treated as empty.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "is", "in", "of", "to", "a", "an", "that", "for", "on", "with", "as", "it", "by",
+    "this", "are", "was", "at", "from", "or", "be", "which", "not", "can", "also", "have", "has",
+    "had", "we", "they", "you", "he", "she", "his", "her", "its", "our", "us", "their", "them",
+    "i", "do", "does", "did", "just", "so", "if", "may", "will", "shall", "more", "most", "some",
+    "many", "any", "all", "what", "about", "would", "could", "should", "where", "when", "why",
+    "how",
+];
+
+/// True if `query` has no words but stopwords, e.g. "", "   " or "what is
+/// the". Embedding such a query would return arbitrary nearest neighbours.
+fn is_blank_query(query: &str) -> bool {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .all(|word| STOPWORDS.contains(&word.to_lowercase().as_str()))
+}
+
 /// Retrieve the document from the knowledge base with highest word overlap.
-fn rag_retrieval<'a>(query: &str, documents: &'a KnowledgeBase) -> Option<&'a Document> {
+///
+/// If the query names ticker symbols (see `query_tickers`; those in the
+/// documents' titles count in any case), only documents
+/// whose title mentions one of them are considered, those mentioning the most
+/// first; when none does, nothing is retrieved, however many ordinary words
+/// like "price" or "volume" other documents share with the query.
+///
+/// Title and content overlaps are scored separately and combined as
+/// `title_weight * title_overlap + (1 - title_weight) * content_overlap`, so a
+/// query naming a document's title gets a boost. A `title_weight` of 0.0
+/// ranks by content alone. A query of nothing but stopwords (see
+/// `is_blank_query`) retrieves nothing.
+fn rag_retrieval<'a>(
+    query: &str,
+    documents: &'a KnowledgeBase,
+    title_weight: f32,
+) -> Option<&'a Document> {
+    if is_blank_query(query) {
+        return None;
+    }
+
+    let known = known_tickers(documents.values().map(|doc| doc.title.as_str()));
+    let tickers = query_tickers(query, &known);
+    let ticker_matches = |doc: &Document| {
+        tickers
+            .iter()
+            .filter(|ticker| mentions_ticker(&doc.title, ticker))
+            .count()
+    };
+
     let query_lower = query.to_lowercase();
     let query_words: HashSet<_> = query_lower.split_whitespace().collect();
+    let overlap = |text: &str| {
+        let text_lower = text.to_lowercase();
+        let text_words: HashSet<_> = text_lower.split_whitespace().collect();
+        query_words.intersection(&text_words).count() as f32
+    };
 
     documents
         .values()
-        .map(|doc| {
-            let doc_lower = doc.content.to_lowercase();
-            let doc_words: HashSet<_> = doc_lower.split_whitespace().collect();
-            let overlap = query_words.intersection(&doc_words).count();
-            (doc, overlap)
+        .map(|doc| (doc, ticker_matches(doc)))
+        .filter(|&(_, matched)| tickers.is_empty() || matched > 0)
+        .map(|(doc, matched)| {
+            let score = title_weight * overlap(&doc.title) + (1.0 - title_weight) * overlap(&doc.content);
+            (doc, matched, score)
+        })
+        .max_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then(a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
         })
-        .max_by_key(|(_, overlap)| *overlap)
-        .map(|(doc, _)| doc)
+        .map(|(doc, _, _)| doc)
 }
 
 /// Generate a response using the retrieved document as context.
+///
+/// The query's tickers are read with `query_tickers`, so symbols in `known` (the
+/// knowledge base's) count in any case.
 async fn rag_generation(
     query: &str,
     document: Option<&Document>,
+    known: &HashSet<String>,
     llm: &llm::LlmClient,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    // Extract requested stock symbols from the query
-    let query_lower = query.to_lowercase();
-    let query_words: HashSet<String> = query_lower
-        .split_whitespace()
-        .map(|word| word.trim_matches(|c| c == ',' || c == '.').to_string())
-        .collect();
-    let stock_symbols: Vec<String> = query_words
-        .into_iter()
-        .filter(|word| word.chars().all(|c| c.is_alphabetic()))
-        .collect();
+    let stock_symbols = query_tickers(query, known);
 
     // Prepare the prompt based on document availability and completeness
     let prompt = match document {
         Some(doc) => {
+            let context = format!("{}: {}", doc.title, doc.content);
             // Check if the document contains data for all requested symbols
-            let doc_lower = doc.title.to_lowercase();
-            let has_all_symbols = stock_symbols
-                .iter()
-                .all(|symbol| doc_lower.contains(&symbol.to_lowercase()));
-            if has_all_symbols {
-                format!(
-                    "Using the following information: '{}: {}', provide a confident and accurate answer to the query: '{}'",
-                    doc.title, doc.content, query
-                )
+            let missing = missing_symbols(&stock_symbols, &doc.title);
+            if missing.is_empty() {
+                render_builtin("answer", &[("context", &context), ("query", query)])
             } else {
-                format!(
-                    "The available information: '{}: {}' does not contain sufficient data for all requested stock symbols. \
-                    Politely refuse to answer the query, stating that there isn't enough information to respond accurately: '{}'",
-                    doc.title, doc.content, query
+                render_builtin(
+                    "insufficient",
+                    &[("context", &context), ("missing", &missing.join(", ")), ("query", query)],
                 )
             }
         }
-        None => {
-            format!(
-                "No relevant information was found in the knowledge base for the requested stock symbols. \
-                Politely refuse to answer the query, stating that there isn't enough information to respond accurately: '{}'",
-                query
-            )
-        }
+        None => render_builtin("refuse", &[("query", query)]),
     };
 
     llm.get_llm_response(&prompt).await
 }
 
+/// A Chroma collection exported from the knowledge base, with the embedder
+/// that built it.
+type DenseIndex = (ChromaCollection, SentenceEmbedder);
+
+/// Retrieve the most relevant document for the query.
+///
+/// With a `dense` index (RAG_RETRIEVER=chroma) the document with the closest
+/// chunk wins; otherwise `rag_retrieval` ranks by word overlap.
+async fn retrieve_document<'a>(
+    query: &str,
+    kb: &'a KnowledgeBase,
+    dense: Option<&DenseIndex>,
+) -> Result<Option<&'a Document>, Box<dyn std::error::Error>> {
+    let Some((collection, embedder)) = dense else {
+        return Ok(rag_retrieval(query, kb, DEFAULT_TITLE_WEIGHT));
+    };
+    if is_blank_query(query) {
+        return Ok(None);
+    }
+
+    let doc_ids = dense_retrieval(collection, query, 1, embedder).await?;
+    Ok(doc_ids.first().and_then(|id| kb.get(id)))
+}
+
+/// Result of answering the same query without and with retrieval.
+#[derive(Debug, Clone)]
+struct CompareResult {
+    naive: String,
+    rag: String,
+    /// Ids of the retrieved documents (at most one here)
+    retrieved_doc_ids: Vec<String>,
+}
+
+/// Answer the query both naively and with the retrieved document as context.
+async fn compare_approaches(
+    query: &str,
+    kb: &KnowledgeBase,
+    dense: Option<&DenseIndex>,
+    llm: &llm::LlmClient,
+) -> Result<CompareResult, Box<dyn std::error::Error>> {
+    let naive = naive_generation(query, llm).await?;
+
+    let retrieved_doc = retrieve_document(query, kb, dense).await?;
+    let retrieved_doc_ids = retrieved_doc.iter().map(|doc| doc.id.clone()).collect();
+    let known = known_tickers(kb.values().map(|doc| doc.title.as_str()));
+    let rag = rag_generation(query, retrieved_doc, &known, llm).await?;
+
+    Ok(CompareResult {
+        naive,
+        rag,
+        retrieved_doc_ids,
+    })
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let kb = create_knowledge_base();
+    // An optional JSON corpus path replaces the built-in knowledge base
+    let kb = match env::args().nth(1) {
+        Some(path) => load_knowledge_base(&path)?,
+        None => create_knowledge_base(),
+    };
 
     let query = "Write a short summary of the stock market performance on April 14, \
                  2023 for the following symbols: NVDA, GOOG.\n\
@@ -147,18 +255,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                  - The highest and lowest prices of the day\n\
                  - The trading volume";
 
+    // RAG_RETRIEVER=chroma exports the knowledge base to a Chroma collection
+    // (see `kb_to_chunks`) once, for every retrieval of the run
+    let dense = if env::var("RAG_RETRIEVER").as_deref() == Ok("chroma") {
+        let embedder = SentenceEmbedder::new().await?;
+        let collection =
+            build_chroma_collection(&kb_to_chunks(&kb), "trust_kb_collection", &embedder).await?;
+        Some((collection, embedder))
+    } else {
+        None
+    };
+
     let llm_client = llm::LlmClient::new();
 
-    println!(
-        "Naive approach:\n{}",
-        naive_generation(query, &llm_client).await?
-    );
+    let result = compare_approaches(query, &kb, dense.as_ref(), &llm_client).await?;
+    println!("Naive approach:\n{}", result.naive);
+    for id in &result.retrieved_doc_ids {
+        let metadata = kb.get(id).map(|doc| doc.metadata_summary()).unwrap_or_default();
+        println!("\nRetrieved {} [{}]", id, metadata);
+    }
+    println!("\n\nRAG approach:\n{}", result.rag);
 
-    let retrieved_doc = rag_retrieval(query, &kb);
-    println!(
-        "\n\nRAG approach:\n{}",
-        rag_generation(query, retrieved_doc, &llm_client).await?
-    );
+    // FOLLOW_UP="what about MSFT?" asks a follow-up that depends on the first
+    // question; it is rewritten into a standalone query before retrieval
+    if let Ok(follow_up) = env::var("FOLLOW_UP") {
+        let history = [Turn {
+            question: query.to_string(),
+            answer: result.rag,
+        }];
+        let standalone = contextualize_query(&history, &follow_up, &llm_client).await?;
+        println!("\nFollow-up: {}\nRewritten as: {}", follow_up, standalone);
+
+        let document = retrieve_document(&standalone, &kb, dense.as_ref()).await?;
+        let known = known_tickers(kb.values().map(|doc| doc.title.as_str()));
+        let answer = rag_generation(&standalone, document, &known, &llm_client).await?;
+        println!("\nRAG approach:\n{}", answer);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kb() -> KnowledgeBase {
+        let doc = Document {
+            id: "1".to_string(),
+            title: "Quarterly report".to_string(),
+            content: "The quarterly report is out and it has what we expected".to_string(),
+            metadata: HashMap::new(),
+        };
+        HashMap::from([(doc.id.clone(), doc)])
+    }
+
+    #[test]
+    fn empty_whitespace_and_stopword_queries_retrieve_nothing() {
+        let kb = kb();
+        for query in ["", "   ", "what is the?"] {
+            assert!(rag_retrieval(query, &kb, DEFAULT_TITLE_WEIGHT).is_none(), "{query:?}");
+        }
+        assert!(rag_retrieval("quarterly report", &kb, DEFAULT_TITLE_WEIGHT).is_some());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+/// Short uppercase words that are ordinary English (or finance jargon) far
+/// more often than tickers; written in capitals they would otherwise look
+/// like symbols.
+const NOT_TICKERS: [&str; 40] = [
+    "A", "I", "AM", "AN", "AND", "ARE", "AS", "AT", "BE", "BY", "DO", "FOR", "FROM", "HAS", "HOW",
+    "IF", "IN", "IS", "IT", "ITS", "ME", "MY", "NO", "NOT", "OF", "ON", "OR", "SO", "THE", "TO",
+    "UP", "US", "WAS", "WE", "WHAT", "WITH", "CEO", "USD", "EPS", "ETF",
+];
+
+/// Ticker symbols mentioned in `query`, in order of first mention, without repeats.
+///
+/// A ticker is a word of 1 to 5 characters, a capital letter followed by
+/// capitals or digits ("NVDA", "GOOG", "F", "BRK2"), optionally written as a
+/// cashtag ("$NVDA") or with a possessive ("NVDA's"). Capitalized ordinary
+/// words such as "I", "A" or "THE" are ignored.
+///
+/// With `known`, only symbols in that set count, and lowercase or mixed-case
+/// spellings of them ("nvda", "Goog") are accepted too; the result then uses
+/// the spelling from `known`.
+pub fn extract_tickers(query: &str, known: Option<&HashSet<String>>) -> Vec<String> {
+    collect_tickers(query, |word| match known {
+        Some(known) => known_spelling(word, known),
+        None => is_ticker_shaped(word).then(|| word.to_string()),
+    })
+}
+
+/// Ticker symbols mentioned in `query`, in any case if they are in `known`.
+///
+/// Spellings of known symbols in any case ("nvda", "Nvda") are returned as
+/// written in `known`; words shaped like tickers that `known` lacks are kept
+/// too, so a query can name a symbol the knowledge base has no data for and
+/// have it reported missing.
+pub fn query_tickers(query: &str, known: &HashSet<String>) -> Vec<String> {
+    collect_tickers(query, |word| {
+        known_spelling(word, known).or_else(|| is_ticker_shaped(word).then(|| word.to_string()))
+    })
+}
+
+/// The ticker-shaped words in `titles`, for use as the `known` set.
+pub fn known_tickers<'a>(titles: impl IntoIterator<Item = &'a str>) -> HashSet<String> {
+    titles
+        .into_iter()
+        .flat_map(|title| extract_tickers(title, None))
+        .collect()
+}
+
+/// Apply `ticker` to each word of `query`, stripped of cashtags and
+/// possessives, and collect the tickers it returns without repeats.
+fn collect_tickers(query: &str, ticker: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut tickers: Vec<String> = Vec::new();
+    for raw in query.split(|c: char| c.is_whitespace() || ",;:()/!?\"".contains(c)) {
+        let word = raw
+            .trim_start_matches('$')
+            .trim_end_matches(['.', '\''])
+            .trim_end_matches("'s")
+            .trim_end_matches("’s");
+
+        if let Some(ticker) = ticker(word)
+            && !tickers.contains(&ticker)
+        {
+            tickers.push(ticker);
+        }
+    }
+    tickers
+}
+
+/// The spelling in `known` of `word`, compared case-insensitively.
+fn known_spelling(word: &str, known: &HashSet<String>) -> Option<String> {
+    known.iter().find(|k| k.eq_ignore_ascii_case(word)).cloned()
+}
+
+/// 1 to 5 characters, an uppercase letter then uppercase letters or digits,
+/// and not a common word.
+fn is_ticker_shaped(word: &str) -> bool {
+    let mut chars = word.chars();
+    let starts_with_capital = chars.next().is_some_and(|c| c.is_ascii_uppercase());
+    starts_with_capital
+        && word.len() <= 5
+        && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && !NOT_TICKERS.contains(&word)
+}
+
+/// True if `text` has `ticker` as a whole word, ignoring case.
+pub fn mentions_ticker(text: &str, ticker: &str) -> bool {
+    text.split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.eq_ignore_ascii_case(ticker))
+}
+
+/// The `symbols` that `title` does not mention as a whole word, compared
+/// case-insensitively, so "NVDA", "nvda" and "Nvda" all match a title
+/// containing "NVDA".
+pub fn missing_symbols<'s>(symbols: &'s [String], title: &str) -> Vec<&'s str> {
+    symbols
+        .iter()
+        .filter(|symbol| !mentions_ticker(title, symbol))
+        .map(String::as_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known() -> HashSet<String> {
+        known_tickers(["AAPL Stock (April 2023)", "NVDA Stock (April 2023)"])
+    }
+
+    #[test]
+    fn known_tickers_come_from_the_titles() {
+        let known = known();
+        assert_eq!(known.len(), 2);
+        assert!(known.contains("AAPL") && known.contains("NVDA"));
+    }
+
+    #[test]
+    fn query_tickers_accept_known_symbols_in_any_case() {
+        assert_eq!(query_tickers("what about nvda?", &known()), ["NVDA"]);
+        assert_eq!(
+            query_tickers("Compare Aapl and $nvda's volume", &known()),
+            ["AAPL", "NVDA"]
+        );
+    }
+
+    #[test]
+    fn query_tickers_keep_unknown_uppercase_symbols() {
+        assert_eq!(
+            query_tickers("NVDA, GOOG and the rest", &known()),
+            ["NVDA", "GOOG"]
+        );
+        // An unknown symbol must be written in capitals to count
+        assert!(query_tickers("how did goog do", &known()).is_empty());
+    }
+
+    #[test]
+    fn mentions_ticker_ignores_case_but_not_word_boundaries() {
+        for spelling in ["NVDA", "nvda", "Nvda"] {
+            assert!(mentions_ticker("NVDA Stock (April 2023)", spelling));
+            assert!(mentions_ticker("nvda stock", spelling));
+        }
+        assert!(!mentions_ticker("NVDAX Fund", "NVDA"));
+    }
+
+    #[test]
+    fn missing_symbols_match_titles_in_any_case() {
+        let symbols = ["NVDA".to_string(), "nvda".to_string(), "Nvda".to_string()];
+        assert!(missing_symbols(&symbols, "NVDA Stock (April 2023)").is_empty());
+        assert!(missing_symbols(&symbols, "Nvda stock (april 2023)").is_empty());
+
+        let symbols = ["AAPL".to_string(), "Goog".to_string()];
+        assert_eq!(
+            missing_symbols(&symbols, "AAPL Stock (April 2023)"),
+            ["Goog"]
+        );
+    }
+}
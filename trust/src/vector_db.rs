@@ -0,0 +1,133 @@
+use crate::data::Chunk;
+use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
+use chromadb::client::{ChromaClient, ChromaClientOptions};
+use chromadb::collection::{ChromaCollection, CollectionEntries, QueryOptions};
+use serde_json::json;
+use std::error::Error;
+
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// Recreate a ChromaDB collection and upsert the chunks (see `kb_to_chunks`).
+///
+/// An existing collection of that name is deleted first, so chunks exported
+/// from a previously loaded knowledge base cannot be retrieved.
+pub async fn build_chroma_collection(
+    chunks: &[Chunk],
+    collection_name: &str,
+    embedder: &SentenceEmbedder,
+) -> Result<ChromaCollection, Box<dyn Error>> {
+    let client = connect_chroma().await?;
+    let exists = client
+        .list_collections()
+        .await?
+        .iter()
+        .any(|existing| existing.name() == collection_name);
+    if exists {
+        client.delete_collection(collection_name).await?;
+    }
+    let collection = client
+        .create_collection(collection_name, None, true)
+        .await?;
+
+    // Skip empty collection
+    if chunks.is_empty() {
+        return Ok(collection);
+    }
+
+    let documents: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+
+    // Create unique IDs by combining doc_id and chunk_id
+    let ids_owned: Vec<String> = chunks
+        .iter()
+        .map(|chunk| format!("{}_chunk_{}", chunk.doc_id, chunk.chunk_id))
+        .collect();
+    let ids: Vec<&str> = ids_owned.iter().map(AsRef::as_ref).collect();
+
+    let metadatas = chunks
+        .iter()
+        .map(|chunk| {
+            let mut map = serde_json::Map::new();
+            map.insert("doc_id".to_string(), json!(chunk.doc_id));
+            map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
+            map.insert("category".to_string(), json!(chunk.category));
+            map
+        })
+        .collect();
+
+    let embeddings = embedder.embed_texts(&documents)?;
+
+    let entries = CollectionEntries {
+        ids,
+        embeddings: Some(embeddings),
+        metadatas: Some(metadatas),
+        documents: Some(documents),
+    };
+
+    collection.upsert(entries, None).await?;
+    Ok(collection)
+}
+
+/// Ids of the documents whose chunks are closest to `query`, most similar first.
+///
+/// `top_k` chunks are fetched; a document with several of them appears once,
+/// at the rank of its best chunk.
+pub async fn dense_retrieval(
+    collection: &ChromaCollection,
+    query: &str,
+    top_k: usize,
+    embedder: &SentenceEmbedder,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let query_embeddings = embedder.embed_texts(&[query])?;
+    let opts = QueryOptions {
+        query_texts: None,
+        query_embeddings: Some(query_embeddings),
+        n_results: Some(top_k),
+        where_metadata: None,
+        where_document: None,
+        include: Some(vec!["metadatas"]),
+    };
+    let result = collection.query(opts, None).await?;
+
+    let metadatas = result
+        .metadatas
+        .and_then(|rows| rows.into_iter().next())
+        .unwrap_or_default();
+
+    let mut doc_ids: Vec<String> = Vec::new();
+    for doc_id in metadatas.iter().filter_map(|metadata| {
+        metadata
+            .as_ref()
+            .and_then(|m| m.get("doc_id"))
+            .and_then(|v| v.as_str())
+    }) {
+        if !doc_ids.iter().any(|id| id == doc_id) {
+            doc_ids.push(doc_id.to_string());
+        }
+    }
+    Ok(doc_ids)
+}
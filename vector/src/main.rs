@@ -1,5 +1,6 @@
 mod data;
 mod embeddings;
+mod error;
 mod vector_db;
 
 use crate::embeddings::SentenceEmbedder;
@@ -8,7 +9,7 @@ use data::{Chunk, load_and_chunk_dataset};
 use serde_json::{Value, json};
 use std::env;
 use std::error::Error;
-use vector_db::build_chroma_collection;
+use vector_db::{CollectionMode, build_chroma_collection};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -19,8 +20,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("Loading data from: {}", dataset_file.display());
 
     // Build the initial collection from chunked documents
-    let chunked_docs = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 30)?;
-    let collection = build_chroma_collection(&chunked_docs, "corpus_collection", &embedder).await?;
+    let chunked_docs = load_and_chunk_dataset(dataset_file.to_str().unwrap(), 30, 5)?;
+    // Start from an empty collection so chunks from runs with another chunk
+    // size never mix in; COLLECTION_MODE=sync or reuse keeps the collection
+    let mode = env::var("COLLECTION_MODE")
+        .ok()
+        .and_then(|name| CollectionMode::from_name(&name))
+        .unwrap_or_default();
+    let collection =
+        build_chroma_collection(&chunked_docs, "corpus_collection", &embedder, mode).await?;
     let total_docs = collection.count().await?;
     println!("ChromaDB collection created with {} documents.", total_docs);
 
@@ -30,6 +38,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         chunk_id: 0,
         category: "food".to_string(),
         text: "Bananas are yellow fruits rich in potassium.".to_string(),
+        title: None,
     };
 
     // Generate a unique ID string
@@ -1,5 +1,6 @@
 use crate::data::Chunk;
 use crate::embeddings::SentenceEmbedder;
+use crate::error::RagError;
 use chromadb::client::{ChromaClient, ChromaClientOptions};
 use chromadb::collection::{ChromaCollection, CollectionEntries, GetOptions};
 use serde_json::{Map, json};
@@ -60,16 +61,130 @@ pub async fn delete_documents_with_keyword(
     Ok(())
 }
 
-pub async fn build_chroma_collection(
-    chunks: &[Chunk],
+/// Connect to the Chroma server, reporting an unreachable server as
+/// `RagError::VectorDbUnavailable` rather than a raw connection error.
+async fn connect_chroma() -> Result<ChromaClient, RagError> {
+    let options = ChromaClientOptions::default();
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| "http://localhost:8000".to_string());
+
+    let client = ChromaClient::new(options)
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+    client
+        .heartbeat()
+        .await
+        .map_err(|e| RagError::VectorDbUnavailable {
+            url,
+            reason: e.to_string(),
+        })?;
+
+    Ok(client)
+}
+
+/// What `build_chroma_collection` does with a collection that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollectionMode {
+    /// Upsert into the existing entries. Entries whose ids the new chunks no
+    /// longer use, such as those left by a run with another chunk size, stay
+    /// and are retrieved alongside the new ones.
+    Reuse,
+    /// Delete the collection and create it anew, so it holds exactly the new chunks.
+    #[default]
+    Rebuild,
+    /// Keep the collection but delete the entries whose ids are not among the
+    /// new chunks' ids before upserting.
+    Sync,
+}
+
+impl CollectionMode {
+    /// Parse "reuse", "rebuild" or "sync", ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "reuse" => Some(CollectionMode::Reuse),
+            "rebuild" => Some(CollectionMode::Rebuild),
+            "sync" => Some(CollectionMode::Sync),
+            _ => None,
+        }
+    }
+}
+
+/// Ids listed or deleted per request when syncing a collection.
+const SYNC_PAGE_SIZE: usize = 100;
+
+/// Get or create `collection_name`, first dropping it (`Rebuild`) or the
+/// entries whose ids are not in `ids` (`Sync`), as `mode` says.
+async fn open_collection(
+    client: &ChromaClient,
     collection_name: &str,
-    embedder: &SentenceEmbedder,
+    mode: CollectionMode,
+    ids: &[String],
 ) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
-    let client = ChromaClient::new(ChromaClientOptions::default()).await?;
+    if mode == CollectionMode::Rebuild
+        && client
+            .list_collections()
+            .await?
+            .iter()
+            .any(|existing| existing.name() == collection_name)
+    {
+        client.delete_collection(collection_name).await?;
+    }
     let collection = client
         .get_or_create_collection(collection_name, None)
         .await?;
 
+    if mode == CollectionMode::Sync {
+        let mut existing: Vec<String> = Vec::new();
+        loop {
+            let page = collection
+                .get(GetOptions {
+                    ids: Vec::new(),
+                    where_metadata: None,
+                    limit: Some(SYNC_PAGE_SIZE),
+                    offset: Some(existing.len()),
+                    where_document: None,
+                    include: Some(Vec::new()),
+                })
+                .await?;
+            let fetched = page.ids.len();
+            existing.extend(page.ids);
+            if fetched < SYNC_PAGE_SIZE {
+                break;
+            }
+        }
+        let stale: Vec<&str> = existing
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !ids.iter().any(|wanted| wanted == id))
+            .collect();
+        for page in stale.chunks(SYNC_PAGE_SIZE) {
+            collection.delete(Some(page.to_vec()), None, None).await?;
+        }
+    }
+    Ok(collection)
+}
+
+/// Create (or retrieve) a ChromaDB collection and upsert `chunks` into it,
+/// first handling existing entries according to `mode`.
+pub async fn build_chroma_collection(
+    chunks: &[Chunk],
+    collection_name: &str,
+    embedder: &SentenceEmbedder,
+    mode: CollectionMode,
+) -> Result<ChromaCollection, Box<dyn std::error::Error>> {
+    let ids_owned: Vec<String> = chunks
+        .iter()
+        .map(|chunk| format!("doc_{}_{}", chunk.doc_id, chunk.chunk_id))
+        .collect();
+
+    let client = connect_chroma().await?;
+    let collection = open_collection(&client, collection_name, mode, &ids_owned).await?;
+
     // Skip empty collection
     if chunks.is_empty() {
         return Ok(collection);
@@ -78,10 +193,6 @@ pub async fn build_chroma_collection(
     let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
     let documents: Vec<&str> = texts.iter().map(AsRef::as_ref).collect();
 
-    let ids_owned: Vec<String> = chunks
-        .iter()
-        .map(|chunk| format!("doc_{}_{}", chunk.doc_id, chunk.chunk_id))
-        .collect();
     let ids: Vec<&str> = ids_owned.iter().map(AsRef::as_ref).collect();
 
     let metadatas: Vec<Map<String, serde_json::Value>> = chunks
@@ -91,12 +202,17 @@ pub async fn build_chroma_collection(
             map.insert("doc_id".to_string(), json!(chunk.doc_id));
             map.insert("chunk_id".to_string(), json!(chunk.chunk_id));
             map.insert("category".to_string(), json!(chunk.category));
+            if let Some(title) = &chunk.title {
+                map.insert("title".to_string(), json!(title));
+            }
             map
         })
         .collect();
 
-    // Embed documents directly
-    let embeddings = embedder.embed(&documents).await?;
+    // Embed each chunk with its document title on the first chunk
+    let embedding_texts: Vec<_> = chunks.iter().map(Chunk::embedding_text).collect();
+    let embedding_refs: Vec<&str> = embedding_texts.iter().map(AsRef::as_ref).collect();
+    let embeddings = embedder.embed(&embedding_refs).await?;
 
     let entries = CollectionEntries {
         ids,